@@ -0,0 +1,30 @@
+use std::env;
+use std::fs::File;
+use std::time::Instant;
+
+use mapr::Mmap;
+use rayon::prelude::*;
+
+/// Computes the byte sum of a file, both serially and in parallel across all cores. The file path
+/// must be provided as the first process argument.
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .expect("supply a single path as the program argument");
+
+    let file = File::open(path).expect("failed to open the file");
+
+    let mmap = unsafe { Mmap::map(&file).expect("failed to map the file") };
+
+    let checksum = |chunk: &[u8]| chunk.iter().map(|&b| b as u64).sum::<u64>();
+
+    let start = Instant::now();
+    let serial = checksum(&mmap);
+    println!("serial:   {:#018x} in {:?}", serial, start.elapsed());
+
+    let start = Instant::now();
+    let parallel: u64 = mmap.par_chunks(1 << 20).map(checksum).sum();
+    println!("parallel: {:#018x} in {:?}", parallel, start.elapsed());
+
+    assert_eq!(serial, parallel);
+}
@@ -0,0 +1,47 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::time::Instant;
+
+use mapr::MmapMut;
+
+const RECORD_LEN: usize = 64;
+const RECORDS: usize = 1000;
+
+/// Compares appending records to a write-ahead log file with `flush_data()` and `flush_all()`
+/// after each record. The log file path must be provided as the first process argument.
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .expect("supply a single path as the program argument");
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .expect("failed to open the file");
+    file.set_len((RECORD_LEN * RECORDS) as u64)
+        .expect("failed to set the file length");
+
+    let mut mmap = unsafe { MmapMut::map_mut(&file).expect("failed to map the file") };
+
+    for (name, flush) in [
+        (
+            "flush_data",
+            MmapMut::flush_data as fn(&MmapMut) -> std::io::Result<()>,
+        ),
+        ("flush_all", MmapMut::flush_all),
+    ] {
+        let start = Instant::now();
+        for i in 0..RECORDS {
+            mmap[i * RECORD_LEN..(i + 1) * RECORD_LEN].fill(i as u8);
+            flush(&mmap).expect("failed to flush the record");
+        }
+        println!(
+            "{}: {:?} per record",
+            name,
+            start.elapsed() / RECORDS as u32
+        );
+    }
+}
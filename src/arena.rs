@@ -0,0 +1,115 @@
+use std::io::{Error, ErrorKind, Result};
+use std::slice;
+
+use crate::{page_size, MmapInner};
+
+/// A bump allocator over a reserved region of anonymous memory.
+///
+/// `MmapArena` reserves address space for its whole capacity up front, but only backs it with
+/// memory as allocations advance into it: pages are committed lazily, a page at a time, once the
+/// bump pointer first crosses into them. [`reset()`](MmapArena::reset()) frees every allocation
+/// at once and releases the committed memory back to the operating system.
+///
+/// ## Example
+///
+/// ```
+/// use mapr::MmapArena;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut arena = MmapArena::new(1 << 20)?;
+///
+/// let buf = arena.alloc(100)?;
+/// buf.copy_from_slice(&[42; 100]);
+/// assert_eq!(100, arena.used());
+///
+/// arena.reset()?;
+/// assert_eq!(0, arena.used());
+/// # Ok(())
+/// # }
+/// ```
+pub struct MmapArena {
+    inner: MmapInner,
+    used: usize,
+    committed: usize,
+}
+
+impl MmapArena {
+    /// Creates an arena able to hold `capacity` bytes of allocations.
+    ///
+    /// No memory is committed until the first allocation.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `capacity` is 0, or when the underlying system call fails.
+    pub fn new(capacity: usize) -> Result<MmapArena> {
+        Ok(MmapArena {
+            inner: MmapInner::reserve(capacity)?,
+            used: 0,
+            committed: 0,
+        })
+    }
+
+    /// Allocates `size` zeroed bytes from the arena, committing more pages if necessary.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `OutOfMemory` if the allocation does not fit in the
+    /// remaining capacity of the arena, or when committing more pages fails.
+    pub fn alloc(&mut self, size: usize) -> Result<&mut [u8]> {
+        let end = match self.used.checked_add(size) {
+            Some(end) if end <= self.capacity() => end,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::OutOfMemory,
+                    "allocation exceeds the capacity of the arena",
+                ))
+            }
+        };
+
+        if end > self.committed {
+            let page_size = page_size();
+            let committed = end.div_ceil(page_size) * page_size;
+            let committed = committed.min(self.capacity());
+            self.inner
+                .commit(self.committed, committed - self.committed)?;
+            self.committed = committed;
+        }
+
+        let start = self.used;
+        self.used = end;
+        unsafe {
+            Ok(slice::from_raw_parts_mut(
+                self.inner.mut_ptr().add(start),
+                size,
+            ))
+        }
+    }
+
+    /// Frees every allocation, and releases all committed pages.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn reset(&mut self) -> Result<()> {
+        self.inner.decommit()?;
+        self.used = 0;
+        self.committed = 0;
+        Ok(())
+    }
+
+    /// Returns the number of bytes allocated from the arena.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the number of bytes backed by committed pages, which is the high-water mark of the
+    /// allocations rounded up to a whole page.
+    pub fn committed(&self) -> usize {
+        self.committed
+    }
+
+    /// Returns the maximum number of bytes which can be allocated from the arena.
+    pub fn capacity(&self) -> usize {
+        self.inner.len()
+    }
+}
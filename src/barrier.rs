@@ -0,0 +1,115 @@
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+
+use crate::MmapMut;
+
+/// A single durable commit point for writes to a file-backed memory map.
+///
+/// A `DurabilityBarrier` separates tracking which parts of a memory map were modified from
+/// flushing them. Ranges are recorded with [`mark_dirty()`](DurabilityBarrier::mark_dirty()) in
+/// any order, and [`commit()`](DurabilityBarrier::commit()) flushes the coalesced ranges before
+/// `fsync`ing the backing file once.
+///
+/// Use [`MmapMut::durability_barrier()`] to create a barrier.
+///
+/// ## Example
+///
+/// ```
+/// use std::fs::OpenOptions;
+///
+/// use mapr::MmapMut;
+/// #
+/// # fn main() -> std::io::Result<()> {
+/// # let tempdir = tempdir::TempDir::new("mmap")?;
+/// let file = OpenOptions::new()
+///     .read(true)
+///     .write(true)
+///     .create(true)
+///     .truncate(true)
+///     .open(tempdir.path().join("wal"))?;
+/// file.set_len(4096)?;
+///
+/// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+/// mmap[512..516].copy_from_slice(b"tail");
+/// mmap[..4].copy_from_slice(b"head");
+///
+/// let mut barrier = mmap.durability_barrier();
+/// barrier.mark_dirty(512, 4)?;
+/// barrier.mark_dirty(0, 4)?;
+/// barrier.commit()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct DurabilityBarrier<'a> {
+    mmap: &'a MmapMut,
+    dirty: Vec<Range<usize>>,
+}
+
+impl<'a> DurabilityBarrier<'a> {
+    pub(crate) fn new(mmap: &'a MmapMut) -> DurabilityBarrier<'a> {
+        DurabilityBarrier {
+            mmap,
+            dirty: Vec::new(),
+        }
+    }
+
+    /// Records the range as modified, to be flushed by [`commit()`](DurabilityBarrier::commit()).
+    ///
+    /// Overlapping and adjacent ranges are coalesced as they are recorded.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map.
+    pub fn mark_dirty(&mut self, offset: usize, len: usize) -> Result<()> {
+        let end = match offset.checked_add(len) {
+            Some(end) if end <= self.mmap.len() => end,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "range is out of the bounds of the memory map",
+                ))
+            }
+        };
+        if len == 0 {
+            return Ok(());
+        }
+
+        // The recorded ranges are sorted and disjoint, so the new range merges with a contiguous
+        // run of them.
+        let first = self.dirty.partition_point(|range| range.end < offset);
+        let last = self.dirty.partition_point(|range| range.start <= end);
+        let mut merged = offset..end;
+        if first < last {
+            merged.start = merged.start.min(self.dirty[first].start);
+            merged.end = merged.end.max(self.dirty[last - 1].end);
+        }
+        self.dirty.splice(first..last, Some(merged));
+        Ok(())
+    }
+
+    /// Returns the coalesced ranges recorded so far, in ascending order.
+    pub fn dirty_ranges(&self) -> &[Range<usize>] {
+        &self.dirty
+    }
+
+    /// Flushes every recorded range, then durably stores the backing file.
+    ///
+    /// When this method returns with a non-error result, all recorded changes and the file's
+    /// metadata are durably stored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the memory map is anonymous, or when the underlying system
+    /// calls fail.
+    pub fn commit(self) -> Result<()> {
+        let file =
+            self.mmap.inner.file().ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput, "memory map is not file-backed")
+            })?;
+        for range in &self.dirty {
+            self.mmap.flush_range(range.start, range.len())?;
+        }
+        file.sync_all()
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Deref;
+use std::slice;
+
+use crate::{page_size, MmapInner};
+
+/// A growable buffer over a reserved region of anonymous memory.
+///
+/// `GrowableMmap` reserves address space for its maximum capacity up front, and commits pages as
+/// it grows, much like a `Vec<u8>` with page granular capacity. Unlike a `Vec`, the buffer is
+/// never moved: pointers into it stay valid as it grows, up to the reserved maximum capacity.
+///
+/// ## Example
+///
+/// ```
+/// use mapr::GrowableMmap;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut buf = GrowableMmap::new(1 << 20)?;
+///
+/// buf.push_slice(b"hello")?;
+/// let ptr = buf.as_ptr();
+/// buf.push_slice(&[0; 8192])?;
+///
+/// assert_eq!(ptr, buf.as_ptr());
+/// assert_eq!(b"hello", &buf[..5]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct GrowableMmap {
+    inner: MmapInner,
+    len: usize,
+    capacity: usize,
+}
+
+impl GrowableMmap {
+    /// Creates an empty buffer able to grow up to `max_capacity` bytes.
+    ///
+    /// The address space is reserved through an inaccessible `mmap` on unix, and `MEM_RESERVE` on
+    /// Windows. No memory is committed until the buffer grows.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `max_capacity` is 0, or when the underlying system call
+    /// fails.
+    pub fn new(max_capacity: usize) -> Result<GrowableMmap> {
+        Ok(GrowableMmap {
+            inner: MmapInner::reserve(max_capacity)?,
+            len: 0,
+            capacity: 0,
+        })
+    }
+
+    /// Commits enough pages for at least `additional` more bytes to be pushed without committing
+    /// again.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `OutOfMemory` if the buffer would exceed its maximum
+    /// capacity, or when committing more pages fails.
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        let end = match self.len.checked_add(additional) {
+            Some(end) if end <= self.max_capacity() => end,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::OutOfMemory,
+                    "buffer would exceed its maximum capacity",
+                ))
+            }
+        };
+
+        if end > self.capacity {
+            let page_size = page_size();
+            let capacity = end.div_ceil(page_size) * page_size;
+            let capacity = capacity.min(self.max_capacity());
+            self.inner.commit(self.capacity, capacity - self.capacity)?;
+            self.capacity = capacity;
+        }
+        Ok(())
+    }
+
+    /// Appends the bytes of `data` to the buffer, committing more pages if necessary.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `OutOfMemory` if the buffer would exceed its maximum
+    /// capacity, or when committing more pages fails. The buffer is left unchanged on error.
+    pub fn push_slice(&mut self, data: &[u8]) -> Result<()> {
+        self.reserve(data.len())?;
+        unsafe {
+            slice::from_raw_parts_mut(self.inner.mut_ptr().add(self.len), data.len())
+                .copy_from_slice(data);
+        }
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Returns the number of bytes in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes backed by committed pages, which the buffer can hold without
+    /// committing more.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of bytes reserved for the buffer, which it can never grow past.
+    pub fn max_capacity(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl Deref for GrowableMmap {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.inner.ptr(), self.len) }
+    }
+}
+
+impl AsRef<[u8]> for GrowableMmap {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
@@ -1,23 +1,53 @@
 //! A cross-platform Rust API for memory mapped buffers.
 
 #![doc(html_root_url = "https://docs.rs/mapr/0.7.0")]
+// `usize::is_multiple_of` requires Rust 1.87.
+#![allow(clippy::manual_is_multiple_of)]
 
 #[cfg(windows)]
 mod windows;
 #[cfg(windows)]
-use windows::MmapInner;
+use windows::{file_writable, MmapInner};
 
 #[cfg(unix)]
 mod unix;
 #[cfg(unix)]
-use unix::MmapInner;
+use unix::{file_writable, MmapInner};
+
+mod arena;
+mod barrier;
+mod growable;
+mod owned;
+mod region;
+#[cfg(target_os = "linux")]
+mod seal;
+mod secure;
+
+pub use arena::MmapArena;
+pub use barrier::DurabilityBarrier;
+pub use growable::GrowableMmap;
+pub use owned::MmapMutOwned;
+pub use region::MmapRegion;
+#[cfg(target_os = "linux")]
+pub use seal::{seal, seals, Seals};
+pub use secure::SecureMmapMut;
 
 use std::fmt;
-use std::fs::File;
-use std::io::{Error, ErrorKind, Result};
-use std::ops::{Deref, DerefMut};
-use std::slice;
-use std::usize;
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Error, ErrorKind, Result};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+#[cfg(unix)]
+use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{FromRawHandle, RawHandle};
+use std::path::Path;
+#[cfg(target_has_atomic = "64")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicU32, AtomicU8};
+use std::sync::Arc;
+use std::time::Instant;
+use std::{mem, ptr, slice};
 
 /// A memory map builder, providing advanced options and flags for specifying memory map behavior.
 ///
@@ -43,10 +73,22 @@ pub struct MmapOptions {
     offset: u64,
     len: Option<usize>,
     stack: bool,
+    grows_down: bool,
+    guard_pages: usize,
     locked: bool,
     private: bool,
-    huge: u8,
+    huge: Option<HugePageSize>,
+    huge_shift: Option<u8>,
     noreserve: bool,
+    auto_protection: bool,
+    sync: bool,
+    deny_exec: bool,
+    huge_aligned: bool,
+    populate: bool,
+    address: usize,
+    fixed_noreplace: bool,
+    low_32bit: bool,
+    protection: Option<Protection>,
 }
 
 impl MmapOptions {
@@ -131,10 +173,51 @@ impl MmapOptions {
         self
     }
 
-    /// Returns the configured length, or the length of the provided file.
-    fn get_len(&self, file: &File) -> Result<usize> {
+    /// Returns the length a memory map of `file` would have: the configured length, or the
+    /// length of the file after the configured offset.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if no length is configured and the
+    /// offset is past the end of the file, or the file is not a regular file and reports a length
+    /// of 0, as many device files do. It returns an error of kind `InvalidData` if the length of
+    /// the file does not fit in `usize`, or an error when reading the file's metadata fails.
+    ///
+    /// When a length is configured, the file's metadata is not consulted at all, so device files
+    /// such as framebuffers can be mapped at any offset the device supports.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = File::open("README.md")?;
+    /// let len = file.metadata()?.len() as usize;
+    /// assert_eq!(len - 2, MmapOptions::new().offset(2).len_for(&file)?);
+    /// assert_eq!(6, MmapOptions::new().offset(2).len(6).len_for(&file)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn len_for(&self, file: &File) -> Result<usize> {
         self.len.map(Ok).unwrap_or_else(|| {
-            let len = file.metadata()?.len() - self.offset;
+            let metadata = file.metadata()?;
+            let file_len = metadata.len();
+            // Device and other special files commonly report a length of 0.
+            if file_len == 0 && !metadata.is_file() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "file has no reportable length, configure the memory map length with len()",
+                ));
+            }
+            if self.offset > file_len {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "memory map offset is past the end of the file",
+                ));
+            }
+            let len = file_len - self.offset;
             if len > (usize::MAX as u64) {
                 return Err(Error::new(
                     ErrorKind::InvalidData,
@@ -145,6 +228,123 @@ impl MmapOptions {
         })
     }
 
+    /// Returns the log2 of the huge page size selected with [`huge()`](MmapOptions::huge()) or
+    /// [`huge_shift()`](MmapOptions::huge_shift()).
+    fn huge_page_shift(&self) -> Option<u8> {
+        self.huge_shift
+            .or_else(|| self.huge.map(HugePageSize::shift))
+    }
+
+    /// Creates a file-backed memory map with `map`, which is given the length and offset to map.
+    ///
+    /// With [`huge_aligned()`](MmapOptions::huge_aligned()), the range is widened to huge page
+    /// boundaries before mapping, and the memory map narrowed back to the requested bytes.
+    fn map_file_range<F>(&self, file: &File, map: F) -> Result<MmapInner>
+    where
+        F: FnOnce(usize, u64) -> Result<MmapInner>,
+    {
+        let len = self.len_for(file)?;
+        #[cfg(unix)]
+        {
+            if let Some(shift) = self.huge_page_shift().filter(|_| self.huge_aligned) {
+                let huge_size = 1u64 << shift;
+                let head = self.offset % huge_size;
+                let offset = self.offset - head;
+                let end = self
+                    .offset
+                    .checked_add(len as u64)
+                    .and_then(|end| end.checked_add(huge_size - 1))
+                    .map(|end| end / huge_size * huge_size)
+                    .filter(|&end| end <= file.metadata().map_or(0, |m| m.len()))
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidInput,
+                            "huge page aligned memory map extends past the end of the file",
+                        )
+                    })?;
+
+                let mut inner = map((end - offset) as usize, offset)?;
+                inner.narrow(head as usize, len);
+                return Ok(inner);
+            }
+        }
+        map(len, self.offset)
+    }
+
+    /// Maps `file` with `protection`, after checking that the options apply to it.
+    fn map_protected(&self, file: &File, protection: Protection) -> Result<MmapInner> {
+        self.validate()?;
+        match protection {
+            Protection::ReadExec if self.deny_exec => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "memory map options deny execute permission",
+                ))
+            }
+            Protection::CopyOnWrite if self.sync => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "copy-on-write memory maps can not be synchronously durable",
+                ))
+            }
+            _ => {}
+        }
+        self.map_file_range(file, |len, offset| {
+            MmapInner::map_file(
+                len,
+                file,
+                offset,
+                protection,
+                self.locked,
+                self.private,
+                self.huge_page_shift(),
+                self.noreserve,
+                self.populate,
+                self.sync,
+                self.address,
+                self.fixed_noreplace,
+                self.low_32bit,
+            )
+        })
+    }
+
+    /// Checks that the configured options do not contradict each other.
+    fn validate(&self) -> Result<()> {
+        if self.locked && self.noreserve {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "locked memory maps can not be mapped without reserve",
+            ));
+        }
+        if self.huge.is_some() && self.huge_shift.is_some() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "huge page size can not be configured with both huge() and huge_shift()",
+            ));
+        }
+        if self.fixed_noreplace && self.address == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "fixed memory maps require an address",
+            ));
+        }
+        if self.low_32bit && !cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "memory maps in the low 2 GiB are only supported on x86-64 Linux",
+            ));
+        }
+        if let Some(shift) = self.huge_shift {
+            if !(MIN_HUGE_SHIFT..=MAX_HUGE_SHIFT).contains(&shift) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "huge page size shift is out of range",
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Configures the anonymous memory map to be suitable for a process or thread stack.
     ///
     /// This option corresponds to the `MAP_STACK` flag on Linux.
@@ -166,9 +366,90 @@ impl MmapOptions {
         self
     }
 
-    /// Configures the memory map to be locked using.
+    /// Configures the anonymous memory map to grow downward when the memory just below it is
+    /// accessed, like the main thread stack.
+    ///
+    /// This option corresponds to the `MAP_GROWSDOWN` flag on Linux, and is meant to be combined
+    /// with [`stack()`](MmapOptions::stack()) and [`private()`](MmapOptions::private()) for custom
+    /// thread stacks. The memory map only covers its initial length: accessing the page below it
+    /// extends it downward, which the memory map does not track and does not unmap when dropped.
+    ///
+    /// The kernel keeps a guard gap of `stack_guard_gap` pages (256 by default) between a
+    /// downward growing region and the mapping below it. An access that would grow the region
+    /// into the gap faults with `SIGSEGV` instead, so the gap acts as a guard page, and an
+    /// inaccessible mapping placed directly below the region stops its growth.
+    ///
+    /// This option has no effect on file-backed memory maps.
+    /// [`map_anon()`](MmapOptions::map_anon()) returns an error of kind `InvalidInput` if the
+    /// memory map is not private, and the kernel may restrict or reject downward growing mappings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let stack = MmapOptions::new()
+    ///     .stack()
+    ///     .grows_down()
+    ///     .private()
+    ///     .len(64 * 1024)
+    ///     .map_anon();
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn grows_down(&mut self) -> &mut Self {
+        self.grows_down = true;
+        self
+    }
+
+    /// Configures the anonymous memory map to be preceded by `n` inaccessible guard pages.
+    ///
+    /// The guard pages are mapped with `PROT_NONE` directly below the memory map, at the end a
+    /// stack grows towards, so that overflowing a stack created with
+    /// [`stack()`](MmapOptions::stack()) faults with `SIGSEGV` instead of corrupting the memory
+    /// below it. The memory map itself still has the configured length and excludes the guard
+    /// pages, which are unmapped together with it when it is dropped.
+    ///
+    /// This option has no effect on file-backed memory maps.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let stack = MmapOptions::new()
+    ///     .stack()
+    ///     .guard_pages(1)
+    ///     .len(64 * 1024)
+    ///     .map_anon()?;
+    /// assert_eq!(64 * 1024, stack.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub fn guard_pages(&mut self, n: usize) -> &mut Self {
+        self.guard_pages = n;
+        self
+    }
+
+    /// Configures the memory map to be locked into RAM when it is created.
     ///
-    /// This option corresponds to the `MAP_LOCKED` flag on Linux, and has no effect on Window and MacOS.
+    /// This option corresponds to the `MAP_LOCKED` flag on Linux, and to calling `VirtualLock`
+    /// after mapping on Windows. It has no effect on other platforms.
+    ///
+    /// The pages of the memory map are faulted in and locked as part of creating it, but on Linux
+    /// this is best-effort: creating the map does not fail if some pages could not be faulted in.
+    /// On Windows creating the map fails if it can not be locked. Calling
+    /// [`Mmap::mlock()`] or [`MmapMut::mlock()`] on an existing map instead faults in and locks
+    /// every page, and reports an error if that is not possible. Use `lock()` when the map should
+    /// be resident from the start, and `mlock()` when locking must be verified or only becomes
+    /// necessary later.
+    ///
+    /// Locked pages are always backed by memory, so this option can not be combined with
+    /// [`noreserve()`](MmapOptions::noreserve()).
     ///
     /// Note this requires privileged access.
     pub fn lock(&mut self) -> &mut Self {
@@ -178,24 +459,287 @@ impl MmapOptions {
 
     /// Configures the memory map to be private.
     ///
-    /// This option corresponds to the `MAP_PRIVATE` flag on Linux.
+    /// This option corresponds to the `MAP_PRIVATE` flag on Linux. On Windows, private writable
+    /// file-backed memory maps are copy-on-write views, like those created by
+    /// [`map_copy()`](MmapOptions::map_copy()).
     pub fn private(&mut self) -> &mut Self {
         self.private = true;
         self
     }
 
-    pub fn huge(&mut self, huge: u8) -> &mut Self {
+    /// Configures the memory map to be backed by huge pages of the given size, or by regular pages
+    /// with `None`, which is the default.
+    ///
+    /// This option corresponds to the `MAP_HUGETLB` flag, together with `MAP_HUGE_2MB` or
+    /// `MAP_HUGE_1GB`, on Linux. Huge pages must have been reserved beforehand, e.g. through
+    /// `/proc/sys/vm/nr_hugepages`, otherwise creating the memory map fails. File-backed huge page
+    /// memory maps require a file on a hugetlbfs file system.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use mapr::{HugePageSize, MmapOptions};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapOptions::new()
+    ///     .len(2 << 20)
+    ///     .huge(Some(HugePageSize::Size2MB))
+    ///     .map_anon()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn huge(&mut self, huge: Option<HugePageSize>) -> &mut Self {
         self.huge = huge;
         self
     }
 
+    /// Configures the memory map to be backed by huge pages of `1 << shift` bytes.
+    ///
+    /// This supports the huge page sizes not covered by [`HugePageSize`], such as 512 MiB or
+    /// 16 GiB pages on some architectures. It corresponds to the `MAP_HUGETLB` flag with `shift`
+    /// encoded at `MAP_HUGE_SHIFT` on Linux. The sizes available on a system are listed in
+    /// `/sys/kernel/mm/hugepages`.
+    ///
+    /// The constructors return an error of kind `InvalidInput` if `shift` is not between 20 and 34
+    /// inclusive, or if [`huge()`](MmapOptions::huge()) is also configured.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// // 2 MiB huge pages.
+    /// let mmap = MmapOptions::new().len(2 << 20).huge_shift(21).map_anon()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn huge_shift(&mut self, shift: u8) -> &mut Self {
+        self.huge_shift = Some(shift);
+        self
+    }
+
+    /// Configures the memory map to be backed by huge pages, where `1` selects 2 MiB pages, `2`
+    /// selects 1 GiB pages, and any other value regular pages.
+    #[deprecated(since = "0.8.0", note = "use `huge()` with a `HugePageSize` instead")]
+    pub fn huge_u8(&mut self, huge: u8) -> &mut Self {
+        self.huge(match huge {
+            1 => Some(HugePageSize::Size2MB),
+            2 => Some(HugePageSize::Size1GB),
+            _ => None,
+        })
+    }
+
+    /// Configures file-backed huge page memory maps to be aligned to the huge page size.
+    ///
+    /// Huge page memory maps of a file fail unless both the offset and the end of the mapped range
+    /// are aligned to the huge page size configured with [`huge()`](MmapOptions::huge()). With
+    /// this option, the offset is rounded down and the end rounded up to huge page boundaries,
+    /// while the memory map still only presents the requested bytes.
+    ///
+    /// The file constructors return an error of kind `InvalidInput` if rounding up the end
+    /// would extend the memory map past the end of the file. This option has no effect without a
+    /// huge page size, on anonymous memory maps, or on platforms other than Unix.
+    pub fn huge_aligned(&mut self) -> &mut Self {
+        self.huge_aligned = true;
+        self
+    }
+
+    /// Configures the memory map to not reserve swap space for its pages.
+    ///
+    /// This option corresponds to the `MAP_NORESERVE` flag on Linux. It can not be combined with
+    /// [`lock()`](MmapOptions::lock()).
     pub fn noreserve(&mut self) -> &mut Self {
         self.noreserve = true;
         self
     }
 
+    /// Configures the memory map to be populated when it is created, so that accessing it does not
+    /// incur page faults.
+    ///
+    /// This option corresponds to the `MAP_POPULATE` flag on Linux, and has no effect on other
+    /// platforms. For file-backed memory maps this reads ahead the file contents. Populating is
+    /// best-effort: creating the map does not fail if some pages could not be faulted in.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { MmapOptions::new().populate().map(&File::open("README.md")?)? };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn populate(&mut self) -> &mut Self {
+        self.populate = true;
+        self
+    }
+
+    /// Configures the address at which the memory map should preferably be created.
+    ///
+    /// This passes `addr` as the address argument of `mmap` on unix, where the kernel treats it as
+    /// a hint: the memory map is created elsewhere if the address is not page aligned or the range
+    /// is already in use, and the constructors do not fail because of it. The hint is for the
+    /// page aligned start of the memory map, so with an [`offset()`](MmapOptions::offset()) that
+    /// is not page aligned the contents start `offset % page_size()` bytes after it. An `addr` of 0
+    /// leaves the choice to the kernel, which is the default. This option has no effect on
+    /// Windows.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapOptions::new().address(0x6000_0000).len(4096).map_anon()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn address(&mut self, addr: usize) -> &mut Self {
+        self.address = addr;
+        self
+    }
+
+    /// Configures the memory map to be created exactly at the [`address()`](MmapOptions::address())
+    /// option, without replacing existing mappings.
+    ///
+    /// This option corresponds to the `MAP_FIXED_NOREPLACE` flag on Linux 4.17 and later. Unlike
+    /// a plain address hint, creating the memory map fails if it can not be placed at the
+    /// address: with an error of kind `AlreadyExists` (the OS error `EEXIST`) if any part of the
+    /// range is already mapped, which is reported on older kernels too, or `InvalidInput` if the
+    /// address is not page aligned.
+    ///
+    /// The constructors return an error of kind `InvalidInput` if no address is configured.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    /// use std::io::ErrorKind;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapOptions::new().len(4096).map_anon()?;
+    ///
+    /// let err = MmapOptions::new()
+    ///     .address(mmap.as_ptr() as usize)
+    ///     .fixed_noreplace()
+    ///     .len(4096)
+    ///     .map_anon()
+    ///     .unwrap_err();
+    /// assert_eq!(ErrorKind::AlreadyExists, err.kind());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn fixed_noreplace(&mut self) -> &mut Self {
+        self.fixed_noreplace = true;
+        self
+    }
+
+    /// Configures the memory map to be created in the first 2 GiB of the address space.
+    ///
+    /// This option corresponds to the `MAP_32BIT` flag on x86-64 Linux. It is meant for JIT
+    /// compilers emitting code with 32-bit relative references to other code or data, e.g. in
+    /// combination with [`map_exec()`](MmapOptions::map_exec()) or with
+    /// [`map_anon()`](MmapOptions::map_anon()) and [`MmapMut::make_exec()`]. Resizing the memory
+    /// map keeps it in the first 2 GiB.
+    ///
+    /// The constructors return an error of kind `Unsupported` on other platforms, rather than
+    /// silently creating a memory map at an arbitrary address. They return an error of kind
+    /// `OutOfMemory` (the OS error `ENOMEM`) if the first 2 GiB of the address space are full.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// # if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+    /// let code = MmapOptions::new().low_32bit().len(4096).map_anon()?.make_exec()?;
+    /// assert!((code.as_ptr() as usize) < 0x8000_0000);
+    /// # }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn low_32bit(&mut self) -> &mut Self {
+        self.low_32bit = true;
+        self
+    }
+
+    /// Configures writable file-backed memory maps to be synchronously durable, for files on
+    /// persistent memory (DAX) file systems.
+    ///
+    /// This option corresponds to the `MAP_SHARED_VALIDATE | MAP_SYNC` flags on Linux. With them,
+    /// writes through the memory map are durable once they are flushed from the CPU cache, without
+    /// calling [`MmapMut::flush()`]. Most file systems do not support `MAP_SYNC`, in which case
+    /// [`map_mut()`](MmapOptions::map_mut()) falls back to a regular shared memory map. Always
+    /// check [`MmapMut::is_sync_mapped()`] before relying on durability without flushing.
+    ///
+    /// [`map_copy()`](MmapOptions::map_copy()) returns an error when this option is set, since
+    /// copy-on-write memory maps never write through to the file. This option has no effect on
+    /// other constructors, or on platforms other than Linux.
+    pub fn sync_on_write(&mut self) -> &mut Self {
+        self.sync = true;
+        self
+    }
+
+    /// Configures the memory map to never be executable.
+    ///
+    /// The data constructors never request execute permission, so this option only documents
+    /// intent for them. [`map_exec()`](MmapOptions::map_exec()) returns an error when it is set.
+    /// Use [`Mmap::ensure_no_exec()`] or [`MmapMut::ensure_no_exec()`] to drop execute
+    /// permission from an existing memory map.
+    pub fn deny_exec(&mut self) -> &mut Self {
+        self.deny_exec = true;
+        self
+    }
+
+    /// Configures [`map_with()`](MmapOptions::map_with()) to pick the protection of the memory
+    /// map from the access mode the file was opened with.
+    ///
+    /// When enabled, `map_with()` creates a writable memory map if the file is open for writing,
+    /// and a read-only memory map otherwise. This option has no effect on the other constructors.
+    pub fn auto_protection(&mut self) -> &mut Self {
+        self.auto_protection = true;
+        self
+    }
+
+    /// Configures the protection of the memory map created by
+    /// [`map_with()`](MmapOptions::map_with()).
+    ///
+    /// This takes precedence over [`auto_protection()`](MmapOptions::auto_protection()), and has
+    /// no effect on the other constructors, which each imply a protection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::{AnyMmap, MmapOptions, Protection};
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = File::open("README.md")?;
+    /// let mmap = unsafe {
+    ///     MmapOptions::new()
+    ///         .protection(Protection::CopyOnWrite)
+    ///         .map_with(&file)?
+    /// };
+    /// assert!(matches!(mmap, AnyMmap::ReadWrite(_)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn protection(&mut self, protection: Protection) -> &mut Self {
+        self.protection = Some(protection);
+        self
+    }
+
     /// Creates a read-only memory map backed by a file.
     ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapOptions#safety) for why this function is `unsafe`.
+    ///
     /// # Errors
     ///
     /// This method returns an error when the underlying system call fails, which can happen for a
@@ -223,22 +767,34 @@ impl MmapOptions {
     /// # }
     /// ```
     pub unsafe fn map(&self, file: &File) -> Result<Mmap> {
-        MmapInner::map(self.get_len(file)?, file, self.offset, self.locked, self.private, self.huge, self.noreserve).map(|inner| Mmap { inner: inner })
+        self.map_protected(file, Protection::Read)
+            .map(|inner| Mmap { inner })
     }
 
     /// Creates a readable and executable memory map backed by a file.
     ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapOptions#safety) for why this function is `unsafe`.
+    ///
     /// # Errors
     ///
-    /// This method returns an error when the underlying system call fails, which can happen for a
-    /// variety of reasons, such as when the file is not open with read permissions.
+    /// This method returns an error of kind `InvalidInput` if
+    /// [`deny_exec()`](MmapOptions::deny_exec()) is set, or when the underlying system call fails,
+    /// which can happen for a variety of reasons, such as when the file is not open with read
+    /// permissions. On Windows the file must also be open with an access mode including
+    /// `GENERIC_EXECUTE`, see `std::os::windows::fs::OpenOptionsExt::access_mode`.
     pub unsafe fn map_exec(&self, file: &File) -> Result<Mmap> {
-        MmapInner::map_exec(self.get_len(file)?, file, self.offset, self.locked, self.private, self.huge, self.noreserve)
-            .map(|inner| Mmap { inner: inner })
+        self.map_protected(file, Protection::ReadExec)
+            .map(|inner| Mmap { inner })
     }
 
     /// Creates a writeable memory map backed by a file.
     ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapOptions#safety) for why this function is `unsafe`.
+    ///
     /// # Errors
     ///
     /// This method returns an error when the underlying system call fails, which can happen for a
@@ -268,63 +824,645 @@ impl MmapOptions {
     /// # }
     /// ```
     pub unsafe fn map_mut(&self, file: &File) -> Result<MmapMut> {
-        MmapInner::map_mut(self.get_len(file)?, file, self.offset, self.locked, self.private, self.huge, self.noreserve)
-            .map(|inner| MmapMut { inner: inner })
+        self.map_protected(file, Protection::ReadWrite)
+            .map(|inner| MmapMut { inner })
     }
 
-    /// Creates a copy-on-write memory map backed by a file.
+    /// Creates a writeable memory map which takes ownership of the file backing it.
     ///
-    /// Data written to the memory map will not be visible by other processes,
-    /// and will not be carried through to the underlying file.
+    /// This is the same as [`map_mut()`](MmapOptions::map_mut()), except the returned memory map
+    /// keeps `file` open until it is dropped, or returned by
+    /// [`MmapMutOwned::into_inner()`].
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapOptions#safety) for why this function is `unsafe`.
     ///
     /// # Errors
     ///
     /// This method returns an error when the underlying system call fails, which can happen for a
-    /// variety of reasons, such as when the file is not open with writable permissions.
+    /// variety of reasons, such as when the file is not open with read and write permissions.
+    pub unsafe fn map_mut_owned(&self, file: File) -> Result<MmapMutOwned> {
+        let mmap = self.map_mut(&file)?;
+        Ok(MmapMutOwned::new(mmap, file))
+    }
+
+    /// Creates a read-only memory map backed by a raw file descriptor, such as one returned by
+    /// `memfd_create` or received over a unix socket.
     ///
-    /// # Example
+    /// The descriptor is only borrowed: it is not closed, and may be closed once this returns.
+    /// Since the length of the descriptor is not queried, [`len()`](MmapOptions::len()) must be
+    /// configured.
     ///
-    /// ```
-    /// use mapr::MmapOptions;
-    /// use std::fs::File;
-    /// use std::io::Write;
+    /// # Safety
     ///
-    /// # fn main() -> std::io::Result<()> {
-    /// let file = File::open("README.md")?;
-    /// let mut mmap = unsafe { MmapOptions::new().map_copy(&file)? };
-    /// (&mut mmap[..]).write_all(b"Hello, world!")?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub unsafe fn map_copy(&self, file: &File) -> Result<MmapMut> {
-        MmapInner::map_copy(self.get_len(file)?, file, self.offset, self.locked, self.huge, self.noreserve)
-            .map(|inner| MmapMut { inner: inner })
+    /// `fd` must be an open file descriptor. See the [type-level docs](MmapOptions#safety) for why
+    /// this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if no length is configured, or when the
+    /// underlying system call fails, which can happen for a variety of reasons, such as when the
+    /// descriptor is not open with read permissions.
+    #[cfg(unix)]
+    pub unsafe fn map_raw_fd(&self, fd: RawFd) -> Result<Mmap> {
+        // The `File` only lends the descriptor to the mapping functions, and must never close it.
+        let file = mem::ManuallyDrop::new(File::from_raw_fd(fd));
+        self.map_borrowed(&file, Protection::Read)
+            .map(|inner| Mmap { inner })
     }
 
-    /// Creates an anonymous memory map.
+    /// Creates a writeable memory map backed by a raw file descriptor.
     ///
-    /// Note: the memory map length must be configured to be greater than 0 before creating an
-    /// anonymous memory map using `MmapOptions::len()`.
+    /// This is the same as [`map_raw_fd()`](MmapOptions::map_raw_fd()), except the memory map is
+    /// writeable, and the descriptor must be open with read and write permissions.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open file descriptor. See the [type-level docs](MmapOptions#safety) for why
+    /// this function is `unsafe`.
     ///
     /// # Errors
     ///
-    /// This method returns an error when the underlying system call fails.
-    pub fn map_anon(&self) -> Result<MmapMut> {
-        MmapInner::map_anon(self.len.unwrap_or(0), self.stack, self.locked, self.private, self.huge, self.noreserve).map(|inner| MmapMut { inner: inner })
+    /// This method returns an error of kind `InvalidInput` if no length is configured, or when the
+    /// underlying system call fails, which can happen for a variety of reasons, such as when the
+    /// descriptor is not open with read and write permissions.
+    #[cfg(unix)]
+    pub unsafe fn map_raw_fd_mut(&self, fd: RawFd) -> Result<MmapMut> {
+        // The `File` only lends the descriptor to the mapping functions, and must never close it.
+        let file = mem::ManuallyDrop::new(File::from_raw_fd(fd));
+        self.map_borrowed(&file, Protection::ReadWrite)
+            .map(|inner| MmapMut { inner })
     }
-}
 
-/// A handle to an immutable memory mapped buffer.
-///
-/// A `Mmap` may be backed by a file, or it can be anonymous map, backed by volatile memory. Use
-/// [`MmapOptions`] or [`map()`] to create a file-backed memory map. To create an immutable
-/// anonymous memory map, first create a mutable anonymous memory map, and then make it immutable
-/// with [`MmapMut::make_read_only()`].
-///
+    /// Creates a read-only memory map backed by a raw handle, such as one returned by
+    /// `CreateFileW` or inherited from a parent process.
+    ///
+    /// The handle is only borrowed: the memory map retains a duplicate of it, as with
+    /// [`map()`](MmapOptions::map()), so it may be closed once this returns. Since the size of the
+    /// handle is not queried, [`len()`](MmapOptions::len()) must be configured.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be an open file handle. See the [type-level docs](MmapOptions#safety) for
+    /// why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if no length is configured, or when the
+    /// underlying system call fails, which can happen for a variety of reasons, such as when the
+    /// handle is not open with read permissions.
+    #[cfg(windows)]
+    pub unsafe fn map_raw_handle(&self, handle: RawHandle) -> Result<Mmap> {
+        // The `File` only lends the handle to the mapping functions, and must never close it.
+        let file = mem::ManuallyDrop::new(File::from_raw_handle(handle));
+        self.map_borrowed(&file, Protection::Read)
+            .map(|inner| Mmap { inner })
+    }
+
+    /// Maps `file`, which borrows a raw descriptor or handle, with `protection`.
+    fn map_borrowed(&self, file: &File, protection: Protection) -> Result<MmapInner> {
+        if self.len.is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory maps of raw descriptors and handles require a length, configure it with len()",
+            ));
+        }
+        self.map_protected(file, protection)
+    }
+
+    /// Creates a copy-on-write memory map backed by a file.
+    ///
+    /// Data written to the memory map will not be visible by other processes,
+    /// and will not be carried through to the underlying file.
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapOptions#safety) for why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if options which only apply to shared
+    /// memory maps are set, such as [`sync_on_write()`](MmapOptions::sync_on_write()), or if the
+    /// options contradict each other. It also returns an error when the underlying system call
+    /// fails, which can happen for a variety of reasons, such as when the file is not open with
+    /// read permissions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    /// use std::fs::File;
+    /// use std::io::Write;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = File::open("README.md")?;
+    /// let mut mmap = unsafe { MmapOptions::new().map_copy(&file)? };
+    /// (&mut mmap[..]).write_all(b"Hello, world!")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn map_copy(&self, file: &File) -> Result<MmapMut> {
+        self.map_protected(file, Protection::CopyOnWrite)
+            .map(|inner| MmapMut { inner })
+    }
+
+    /// Creates a memory map backed by a file, with the configured protection or one chosen from
+    /// the file's access mode.
+    ///
+    /// If [`protection()`](MmapOptions::protection()) is configured, this creates a memory map with
+    /// that protection, like the constructor named for it, and returns it as an [`AnyMmap`] of the
+    /// matching kind. Otherwise, if [`auto_protection()`](MmapOptions::auto_protection()) is
+    /// configured and `file` is open for writing, this creates a writable memory map like
+    /// [`map_mut()`](MmapOptions::map_mut()). Otherwise it creates a read-only memory map like
+    /// [`map()`](MmapOptions::map()).
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapOptions#safety) for why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the file's access mode can not be determined, or when the
+    /// underlying system call fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::{AnyMmap, MmapOptions};
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = File::open("README.md")?;
+    /// let mmap = unsafe { MmapOptions::new().auto_protection().map_with(&file)? };
+    /// assert!(matches!(mmap, AnyMmap::ReadOnly(_)));
+    /// assert_eq!(b"# mapr", &mmap[..6]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn map_with(&self, file: &File) -> Result<AnyMmap> {
+        if let Some(protection) = self.protection {
+            let inner = self.map_protected(file, protection)?;
+            return Ok(match protection {
+                Protection::Read | Protection::ReadExec => AnyMmap::ReadOnly(Mmap { inner }),
+                Protection::ReadWrite | Protection::CopyOnWrite => {
+                    AnyMmap::ReadWrite(MmapMut { inner })
+                }
+            });
+        }
+        if self.auto_protection && file_writable(file)? {
+            self.map_mut(file).map(AnyMmap::ReadWrite)
+        } else {
+            self.map(file).map(AnyMmap::ReadOnly)
+        }
+    }
+
+    /// Creates an anonymous memory map.
+    ///
+    /// Note: the memory map length must be configured to be greater than 0 before creating an
+    /// anonymous memory map using `MmapOptions::len()`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if guard pages are configured without a
+    /// non-zero length, or when the underlying system call fails.
+    pub fn map_anon(&self) -> Result<MmapMut> {
+        self.validate()?;
+        if self.grows_down && !self.private {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "downward growing memory maps must be private",
+            ));
+        }
+        let len = self.len.unwrap_or(0);
+        if self.guard_pages > 0 && len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "guard pages require a non-zero memory map length",
+            ));
+        }
+        let guard_len = match self.guard_pages.checked_mul(page_size()) {
+            Some(guard_len) if len.checked_add(guard_len).is_some() => guard_len,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "guard pages overflow the memory map length",
+                ))
+            }
+        };
+        let mut inner = MmapInner::map_anon(
+            len + guard_len,
+            self.stack,
+            self.grows_down,
+            self.locked,
+            self.private,
+            self.huge_page_shift(),
+            self.noreserve,
+            self.populate,
+            self.address,
+            self.fixed_noreplace,
+            self.low_32bit,
+        )?;
+        if guard_len > 0 {
+            inner.guard(guard_len)?;
+        }
+        Ok(MmapMut { inner })
+    }
+
+    /// Creates an anonymous memory map of `len` bytes which is shared with child processes.
+    ///
+    /// The memory is mapped with `MAP_SHARED | MAP_ANON`, so a child process created with `fork`
+    /// inherits the same physical memory rather than a copy-on-write copy of it: writes by the
+    /// child are visible to the parent, and vice versa, as long as both keep the memory map. This
+    /// does not hold for memory advised with [`Advice::DontFork`], which is not inherited at all.
+    ///
+    /// The length and private options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `len` is 0, or when the underlying system call fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapOptions::new().map_anon_shared(4096)?;
+    /// match unsafe { libc::fork() } {
+    ///     -1 => return Err(std::io::Error::last_os_error()),
+    ///     0 => unsafe {
+    ///         *(mmap.as_ptr() as *mut u8) = 42;
+    ///         libc::_exit(0);
+    ///     },
+    ///     pid => unsafe {
+    ///         libc::waitpid(pid, std::ptr::null_mut(), 0);
+    ///     },
+    /// }
+    /// assert_eq!(42, mmap[0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub fn map_anon_shared(&self, len: usize) -> Result<MmapMut> {
+        let mut options = self.clone();
+        options.len = Some(len);
+        options.private = false;
+        options.map_anon()
+    }
+
+    /// Creates a shared memory map of `len` bytes, along with a file descriptor which can be used
+    /// to map the same memory in another process.
+    ///
+    /// The memory is backed by a `memfd_create` file where available, and by an unlinked temporary
+    /// file otherwise, so it never has a name in the file system. The returned descriptor can be
+    /// sent to an unrelated process (e.g. with `SCM_RIGHTS` over a unix socket), which maps the
+    /// memory by converting it into a [`File`] and calling [`map_mut()`](MmapOptions::map_mut()).
+    /// Writes through any of the maps are visible through all of them.
+    ///
+    /// The offset, length, private and huge page options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `len` is 0, or when the underlying system calls fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (mut mmap, fd) = MmapOptions::new().map_anon_shared_fd(4096)?;
+    ///
+    /// // Typically in another process, after receiving the descriptor:
+    /// let file = File::from(fd);
+    /// let other = unsafe { MmapOptions::new().map(&file)? };
+    ///
+    /// mmap[..5].copy_from_slice(b"hello");
+    /// assert_eq!(b"hello", &other[..5]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub fn map_anon_shared_fd(&self, len: usize) -> Result<(MmapMut, OwnedFd)> {
+        self.validate()?;
+        let file = unix::anonymous_file(len as u64)?;
+        let inner = MmapInner::map_file(
+            len,
+            &file,
+            0,
+            Protection::ReadWrite,
+            self.locked,
+            false,
+            None,
+            self.noreserve,
+            self.populate,
+            false,
+            self.address,
+            self.fixed_noreplace,
+            self.low_32bit,
+        )?;
+        Ok((MmapMut { inner }, file.into()))
+    }
+
+    /// Creates a shared memory map of `len` bytes backed by a `memfd_create` file named `name`,
+    /// along with the file.
+    ///
+    /// The file lives in memory only, and `name` is only used for debugging, e.g. in
+    /// `/proc/self/maps`. The file can be grown with [`File::set_len()`], sealed, or shared with
+    /// another process by sending its descriptor, which then maps it with
+    /// [`map_mut()`](MmapOptions::map_mut()). Writes through the memory map are visible through
+    /// the file, and vice versa.
+    ///
+    /// The offset, length, private and huge page options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `len` is 0 or `name` contains a nul
+    /// byte, or when the underlying system calls fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    /// use std::io::Read;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (mut mmap, mut file) = MmapOptions::new().map_memfd("buffer", 4096)?;
+    /// mmap[..5].copy_from_slice(b"hello");
+    ///
+    /// let mut contents = [0; 5];
+    /// file.read_exact(&mut contents)?;
+    /// assert_eq!(b"hello", &contents);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn map_memfd(&self, name: &str, len: usize) -> Result<(MmapMut, File)> {
+        self.validate()?;
+        if len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map must have a non-zero length",
+            ));
+        }
+        let file = unix::named_memfd(name, len as u64)?;
+        let inner = MmapInner::map_file(
+            len,
+            &file,
+            0,
+            Protection::ReadWrite,
+            self.locked,
+            false,
+            None,
+            self.noreserve,
+            self.populate,
+            false,
+            self.address,
+            self.fixed_noreplace,
+            self.low_32bit,
+        )?;
+        Ok((MmapMut { inner }, file))
+    }
+
+    /// Creates a shared memory map of `len` bytes of the named shared memory object `name`.
+    ///
+    /// The object is created if it does not exist and `create` is set. Any process which maps the
+    /// same name shares the memory. A leading slash is added to `name` if it is missing, so
+    /// `"queue"` and `"/queue"` name the same object.
+    ///
+    /// On unix the object is opened with `shm_open`, and lives until the name is removed with
+    /// [`unlink_shared()`](MmapOptions::unlink_shared()). When `create` is set, an object shorter
+    /// than `len` bytes is extended to `len` bytes; it is never truncated, since that would break
+    /// other memory maps of it.
+    ///
+    /// On Windows the object is a pagefile-backed section created with `CreateFileMappingW`, or
+    /// opened with `OpenFileMappingW` without `create`, named without the leading slash. It lives
+    /// until the last memory map of it in any process is unmapped, and an existing section can not
+    /// be extended.
+    ///
+    /// Either way, an existing object must already be at least `len` bytes long.
+    ///
+    /// The offset, length, private and huge page options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `len` is 0, if `name` is empty or
+    /// contains a slash other than the leading one or a nul byte, or if the object is shorter than
+    /// `len` bytes and `create` is not set. It returns an error of kind `NotFound` if the object
+    /// does not exist and `create` is not set, and an error when the underlying system calls fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// # let name = format!("mapr-doc-{}", std::process::id());
+    /// let mut mmap = MmapOptions::new().map_shared_named(&name, 4096, true)?;
+    ///
+    /// // Typically in another process:
+    /// let other = MmapOptions::new().map_shared_named(&name, 4096, false)?;
+    ///
+    /// mmap[..5].copy_from_slice(b"hello");
+    /// assert_eq!(b"hello", &other[..5]);
+    /// MmapOptions::unlink_shared(&name)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(any(windows, all(unix, not(target_os = "android"))))]
+    pub fn map_shared_named(&self, name: &str, len: usize, create: bool) -> Result<MmapMut> {
+        self.validate()?;
+        if len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map must have a non-zero length",
+            ));
+        }
+        #[cfg(unix)]
+        let inner = {
+            let file = unix::shm_open(name, create)?;
+            if file.metadata()?.len() < len as u64 {
+                if !create {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "shared memory object is shorter than the memory map",
+                    ));
+                }
+                file.set_len(len as u64)?;
+            }
+            MmapInner::map_file(
+                len,
+                &file,
+                0,
+                Protection::ReadWrite,
+                self.locked,
+                false,
+                None,
+                self.noreserve,
+                self.populate,
+                false,
+                self.address,
+                self.fixed_noreplace,
+                self.low_32bit,
+            )?
+        };
+        #[cfg(windows)]
+        let inner = MmapInner::map_named(name, len, create, self.locked)?;
+        Ok(MmapMut { inner })
+    }
+
+    /// Removes the named shared memory object `name`, as created by
+    /// [`map_shared_named()`](MmapOptions::map_shared_named()).
+    ///
+    /// Existing memory maps of the object remain valid, and the memory is freed once all of them
+    /// are unmapped. As with `map_shared_named()`, a leading slash is added to `name` if it is
+    /// missing.
+    ///
+    /// Named sections are removed automatically on Windows once the last memory map of them is
+    /// unmapped, so this only validates `name` there.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `name` is not valid. On unix, it
+    /// returns an error of kind `NotFound` if the object does not exist, or when the underlying
+    /// system call fails.
+    #[cfg(any(windows, all(unix, not(target_os = "android"))))]
+    pub fn unlink_shared(name: &str) -> Result<()> {
+        #[cfg(unix)]
+        return unix::shm_unlink(name);
+        #[cfg(windows)]
+        return windows::shared_name(name).map(|_| ());
+    }
+
+    /// Creates a shared memory map of the configured length backed by huge pages, along with a
+    /// file descriptor which can be used to map the same memory in another process.
+    ///
+    /// This is the huge page counterpart of
+    /// [`map_anon_shared_fd()`](MmapOptions::map_anon_shared_fd()): the memory is backed by a
+    /// `memfd_create(MFD_HUGETLB)` file named `name`, with pages of `huge_size` bytes, which must
+    /// be 2 MiB or 1 GiB. The length must be a non-zero multiple of `huge_size`. Huge pages must
+    /// have been reserved beforehand, e.g. through `/proc/sys/vm/nr_hugepages`.
+    ///
+    /// The offset, private and huge page options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the length or `huge_size` is not
+    /// valid, of kind `Unsupported` if the kernel does not support huge page backed memfds, and of
+    /// kind `OutOfMemory` if not enough huge pages are available. It also returns an error when
+    /// the underlying system calls fail.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn map_memfd_huge(&self, name: &str, huge_size: usize) -> Result<(MmapMut, OwnedFd)> {
+        self.validate()?;
+        let len = match self.len {
+            Some(len) if len > 0 && huge_size > 0 && len % huge_size == 0 => len,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "memory map length must be a non-zero multiple of the huge page size",
+                ))
+            }
+        };
+
+        let file = unix::huge_memfd(name, huge_size, len as u64)?;
+        let inner = MmapInner::map_file(
+            len,
+            &file,
+            0,
+            Protection::ReadWrite,
+            self.locked,
+            false,
+            None,
+            self.noreserve,
+            self.populate,
+            false,
+            self.address,
+            self.fixed_noreplace,
+            self.low_32bit,
+        )
+        .map_err(|err| {
+            if err.raw_os_error() == Some(libc::ENOMEM) {
+                Error::new(
+                    ErrorKind::OutOfMemory,
+                    "not enough huge pages are available, see /proc/sys/vm/nr_hugepages",
+                )
+            } else {
+                err
+            }
+        })?;
+        Ok((MmapMut { inner }, file.into()))
+    }
+
+    /// Creates an anonymous memory map of the configured length backed by huge pages of the given
+    /// size.
+    ///
+    /// This is equivalent to [`map_anon()`](MmapOptions::map_anon()) with
+    /// [`huge()`](MmapOptions::huge()), but validates the length up front and reports a lack of
+    /// huge pages distinctly. The length must be a non-zero multiple of the huge page size. Huge
+    /// pages must have been reserved beforehand, e.g. through `/proc/sys/vm/nr_hugepages`.
+    ///
+    /// The huge page options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the length is not valid, of kind
+    /// `OutOfMemory` if not enough huge pages are available, or when the underlying system call
+    /// fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use mapr::{HugePageSize, MmapOptions};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapOptions::new()
+    ///     .len(4 << 20)
+    ///     .map_anon_huge(HugePageSize::Size2MB)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn map_anon_huge(&self, size: HugePageSize) -> Result<MmapMut> {
+        match self.len {
+            Some(len) if len > 0 && len % size.bytes() == 0 => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "memory map length must be a non-zero multiple of the huge page size",
+                ))
+            }
+        }
+
+        let mut options = self.clone();
+        options.huge = Some(size);
+        options.huge_shift = None;
+        options.map_anon().map_err(|err| {
+            if err.raw_os_error() == Some(libc::ENOMEM) {
+                Error::new(
+                    ErrorKind::OutOfMemory,
+                    "not enough huge pages are available, see /proc/sys/vm/nr_hugepages",
+                )
+            } else {
+                err
+            }
+        })
+    }
+}
+
+/// A handle to an immutable memory mapped buffer.
+///
+/// A `Mmap` may be backed by a file, or it can be anonymous map, backed by volatile memory. Use
+/// [`MmapOptions`] or [`map()`] to create a file-backed memory map. To create an immutable
+/// anonymous memory map, first create a mutable anonymous memory map, and then make it immutable
+/// with [`MmapMut::make_read_only()`].
+///
 /// A file backed `Mmap` is created by `&File` reference, and will remain valid even after the
 /// `File` is dropped. In other words, the `Mmap` handle is completely independent of the `File`
-/// used to create it. For consistency, on some platforms this is achieved by duplicating the
-/// underlying file handle. The memory will be unmapped when the `Mmap` handle is dropped.
+/// used to create it. This is achieved by duplicating the underlying file handle, which the memory
+/// map keeps open, e.g. to sync the file. The memory will be unmapped when the `Mmap` handle is
+/// dropped, or explicitly with [`unmap()`](Mmap::unmap()) to observe errors.
 ///
 /// Dereferencing and accessing the bytes of the buffer may result in page faults (e.g. swapping
 /// the mapped pages into physical memory) though the details of this are platform specific.
@@ -366,6 +1504,10 @@ impl Mmap {
     ///
     /// This is equivalent to calling `MmapOptions::new().map(file)`.
     ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](Mmap#safety) for why this function is `unsafe`.
+    ///
     /// # Errors
     ///
     /// This method returns an error when the underlying system call fails, which can happen for a
@@ -395,6 +1537,86 @@ impl Mmap {
         MmapOptions::new().map(file)
     }
 
+    /// Opens the file at `path` read-only and creates a read-only memory map backed by it.
+    ///
+    /// This is equivalent to calling `Mmap::map(&File::open(path)?)`.
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](Mmap#safety) for why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the error from opening the file unchanged, and an error of kind
+    /// `InvalidInput` if the file is empty, or when the underlying system call fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::Mmap;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { Mmap::open("README.md")? };
+    /// assert_eq!(b"# mapr", &mmap[..6]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<Mmap> {
+        Mmap::map(&File::open(path)?)
+    }
+
+    /// Takes ownership of an existing memory map of `len` bytes at `ptr`, e.g. one created by
+    /// another library.
+    ///
+    /// The memory is unmapped when the returned `Mmap` is dropped, and is treated as anonymous
+    /// memory otherwise. See [`MmapMut::from_raw()`] for the mutable counterpart.
+    ///
+    /// # Safety
+    ///
+    /// **The returned `Mmap` unmaps the memory**, so the caller must uphold all of the following:
+    ///
+    /// * `ptr` must point into the first page of a live memory map created by `mmap` on unix, or
+    ///   into the first allocation granule of a view created by `MapViewOfFile` on Windows, such
+    ///   as a pointer returned by `into_raw()`;
+    /// * on unix, the memory map must start at the page boundary at or below `ptr` and end `len`
+    ///   bytes after `ptr`, and `len` must be non-zero;
+    /// * the memory must be readable for the lifetime of the `Mmap`;
+    /// * nothing else may unmap the memory, or access it mutably while the `Mmap` exists.
+    ///
+    /// Violating any of these can unmap unrelated memory or unmap the memory twice, which is
+    /// *Undefined Behavior*.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # fn main() {
+    /// use mapr::Mmap;
+    ///
+    /// let ptr = unsafe {
+    ///     libc::mmap(
+    ///         std::ptr::null_mut(),
+    ///         4096,
+    ///         libc::PROT_READ,
+    ///         libc::MAP_PRIVATE | libc::MAP_ANON,
+    ///         -1,
+    ///         0,
+    ///     )
+    /// };
+    /// assert_ne!(libc::MAP_FAILED, ptr);
+    ///
+    /// let mmap = unsafe { Mmap::from_raw(ptr as *mut u8, 4096) };
+    /// assert_eq!(&[0; 4096][..], &mmap[..]);
+    /// # }
+    /// # #[cfg(windows)]
+    /// # fn main() {}
+    /// ```
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> Mmap {
+        Mmap {
+            inner: MmapInner::from_raw(ptr, len),
+        }
+    }
+
     /// Transition the memory map to be writable.
     ///
     /// If the memory map is file-backed, the file must have been opened with write permissions.
@@ -434,269 +1656,630 @@ impl Mmap {
         Ok(MmapMut { inner: self.inner })
     }
 
-    /// Uses `mlock` to lock the whole memory map into RAM.
+    /// Unmaps the memory map and returns the file backing it.
     ///
-    /// Note this requires privileged access.
-    #[cfg(unix)]
-    pub fn mlock(&mut self) -> Result<()> {
-        self.inner.mlock()?;
-        
-        Ok(())
+    /// The returned `File` is an owned duplicate of the handle the memory map was created from,
+    /// so it can be used (e.g. to truncate the file) without reopening it by path. Returns `None`
+    /// for anonymous memory maps.
+    pub fn into_backing_file(self) -> Option<File> {
+        self.inner.into_file()
     }
 
-    /// Uses `munlock` to unlock the whole memory map.
+    /// Unmaps the memory map, returning any error.
     ///
-    /// Note this requires privileged access.
-    #[cfg(unix)]
-    pub fn munlock(&mut self) -> Result<()> {
-        self.inner.munlock()?;
-        
-        Ok(())
+    /// Dropping a memory map also unmaps it, but has to ignore errors. Use this method where a
+    /// failure to unmap must be observed. Unmapping is only attempted once: if it fails, the
+    /// memory map is not unmapped again on drop.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn unmap(self) -> Result<()> {
+        let mut inner = self.inner;
+        inner.close()
     }
-}
 
-impl Deref for Mmap {
-    type Target = [u8];
-
-    #[inline]
-    fn deref(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self.inner.ptr(), self.inner.len()) }
+    /// Releases ownership of the memory map without unmapping it, returning a pointer to its
+    /// first byte and its length, e.g. to hand it to C.
+    ///
+    /// The memory map is no longer unmapped on drop, which is now the caller's responsibility,
+    /// e.g. by passing the pointer and length back to [`Mmap::from_raw()`] or to `munmap`.
+    /// Any retained file handle is closed, and any pages mapped before the page containing the
+    /// pointer or after the memory map, such as guard pages, are unmapped. If the memory map does
+    /// not start on a page boundary, as for file-backed memory maps created at an unaligned
+    /// offset, the underlying memory map starts at the page boundary below the pointer, and
+    /// covers the `len` bytes after it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::{Mmap, MmapMut};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapMut::map_anon(4096)?.make_read_only()?;
+    /// let (ptr, len) = mmap.into_raw();
+    ///
+    /// // Later, take ownership back to unmap the memory.
+    /// drop(unsafe { Mmap::from_raw(ptr as *mut u8, len) });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_raw(self) -> (*const u8, usize) {
+        let (ptr, len) = self.inner.into_raw();
+        (ptr as *const u8, len)
     }
-}
 
-impl AsRef<[u8]> for Mmap {
-    #[inline]
-    fn as_ref(&self) -> &[u8] {
-        self.deref()
+    /// Releases ownership of the memory map without unmapping it, returning a slice of it which
+    /// lives for the rest of the program, unless the memory is unmapped.
+    ///
+    /// This is useful for data which must outlive any owner, e.g. when handed to a plugin. See
+    /// [`into_raw()`](Mmap::into_raw()) for how the memory can be unmapped again.
+    pub fn leak(self) -> &'static [u8] {
+        let (ptr, len) = self.into_raw();
+        unsafe { slice::from_raw_parts(ptr, len) }
     }
-}
 
-impl fmt::Debug for Mmap {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.debug_struct("Mmap")
-            .field("ptr", &self.as_ptr())
-            .field("len", &self.len())
-            .finish()
+    /// Creates an independent read-only memory map with the same contents.
+    ///
+    /// Shared file-backed memory maps are cloned by mapping the same range of the file again,
+    /// through the retained file handle, so the clone keeps reflecting changes to the file.
+    /// Anonymous and copy-on-write memory maps are cloned by copying their contents into a new
+    /// anonymous memory map. Either way, dropping one of the memory maps does not affect the
+    /// other.
+    ///
+    /// [`Clone`] is implemented in terms of this method, but panics on failure.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system calls fail.
+    pub fn try_clone(&self) -> Result<Mmap> {
+        match (self.inner.kind(), self.inner.file()) {
+            (MapKind::SharedFile, Some(file)) => unsafe {
+                MmapOptions::new()
+                    .offset(self.inner.offset())
+                    .len(self.len())
+                    .map(file)
+            },
+            _ => {
+                let mut copy = MmapMut::map_anon(self.len())?;
+                copy.copy_from_slice(self);
+                copy.make_read_only()
+            }
+        }
     }
-}
-
-/// A handle to a mutable memory mapped buffer.
-///
-/// A file-backed `MmapMut` buffer may be used to read from or write to a file. An anonymous
-/// `MmapMut` buffer may be used any place that an in-memory byte buffer is needed. Use
-/// [`MmapMut::map_mut()`] and [`MmapMut::map_anon()`] to create a mutable memory map of the
-/// respective types, or [`MmapOptions::map_mut()`] and [`MmapOptions::map_anon()`] if non-default
-/// options are required.
-///
-/// A file backed `MmapMut` is created by `&File` reference, and will remain valid even after the
-/// `File` is dropped. In other words, the `MmapMut` handle is completely independent of the `File`
-/// used to create it. For consistency, on some platforms this is achieved by duplicating the
-/// underlying file handle. The memory will be unmapped when the `MmapMut` handle is dropped.
-///
-/// Dereferencing and accessing the bytes of the buffer may result in page faults (e.g. swapping
-/// the mapped pages into physical memory) though the details of this are platform specific.
-///
-/// `Mmap` is [`Sync`](std::marker::Sync) and [`Send`](std::marker::Send).
-///
-/// See [`Mmap`] for the immutable version.
-///
-/// ## Safety
-///
-/// All file-backed memory map constructors are marked `unsafe` because of the potential for
-/// *Undefined Behavior* (UB) using the map if the underlying file is subsequently modified, in or
-/// out of process. Applications must consider the risk and take appropriate precautions when using
-/// file-backed maps. Solutions such as file permissions, locks or process-private (e.g. unlinked)
-/// files exist but are platform specific and limited.
-pub struct MmapMut {
-    inner: MmapInner,
-}
 
-impl MmapMut {
-    /// Creates a writeable memory map backed by a file.
+    /// Creates an independent writable copy-on-write view with the same contents.
     ///
-    /// This is equivalent to calling `MmapOptions::new().map_mut(file)`.
+    /// Shared file-backed memory maps are cloned by mapping the same range of the file again with
+    /// [`map_copy()`](MmapOptions::map_copy()), through the retained file handle, so the clone
+    /// shares pages with the page cache until they are written. Anonymous and copy-on-write
+    /// memory maps, whose contents may differ from the file, are cloned by copying their contents
+    /// into a new anonymous memory map. Either way, writes to the clone
+    /// are neither visible through this memory map nor carried through to the file.
     ///
     /// # Errors
     ///
-    /// This method returns an error when the underlying system call fails, which can happen for a
-    /// variety of reasons, such as when the file is not open with read and write permissions.
+    /// This method returns an error when the underlying system calls fail.
     ///
     /// # Example
     ///
     /// ```
-    /// use std::fs::OpenOptions;
-    /// use std::path::PathBuf;
+    /// use mapr::Mmap;
     ///
-    /// use mapr::MmapMut;
-    /// #
     /// # fn main() -> std::io::Result<()> {
-    /// # let tempdir = tempdir::TempDir::new("mmap")?;
-    /// let path: PathBuf = /* path to file */
-    /// #   tempdir.path().join("map_mut");
-    /// let file = OpenOptions::new()
-    ///                        .read(true)
-    ///                        .write(true)
-    ///                        .create(true)
-    ///                        .open(&path)?;
-    /// file.set_len(13)?;
+    /// let mmap = unsafe { Mmap::open("README.md")? };
+    /// let mut clone = mmap.try_clone_cow()?;
+    /// clone[..6].copy_from_slice(b"cloned");
     ///
-    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    /// assert_eq!(b"# mapr", &mmap[..6]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_clone_cow(&self) -> Result<MmapMut> {
+        match (self.inner.kind(), self.inner.file()) {
+            (MapKind::SharedFile, Some(file)) => unsafe {
+                MmapOptions::new()
+                    .offset(self.inner.offset())
+                    .len(self.len())
+                    .map_copy(file)
+            },
+            _ => {
+                let mut copy = MmapMut::map_anon(self.len())?;
+                copy.copy_from_slice(self);
+                Ok(copy)
+            }
+        }
+    }
+
+    /// Copies the contents of the memory map into a new `Vec<u8>`.
     ///
-    /// mmap.copy_from_slice(b"Hello, world!");
+    /// This is equivalent to `self[..].to_vec()` or `self.as_ref().to_vec()`. The pages are read
+    /// in order, so the kernel's readahead handles large file-backed memory maps well.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::Mmap;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { Mmap::open("README.md")? };
+    /// let contents: Vec<u8> = mmap.to_vec();
+    /// assert_eq!(b"# mapr", &contents[..6]);
     /// # Ok(())
     /// # }
     /// ```
-    pub unsafe fn map_mut(file: &File) -> Result<MmapMut> {
-        MmapOptions::new().map_mut(file)
+    pub fn to_vec(&self) -> Vec<u8> {
+        self[..].to_vec()
     }
 
-    /// Creates an anonymous memory map.
+    /// Returns a cursor over the memory map, which implements [`Read`](std::io::Read),
+    /// [`BufRead`](std::io::BufRead) and [`Seek`](std::io::Seek).
     ///
-    /// This is equivalent to calling `MmapOptions::new().len(length).map_anon()`.
+    /// Seeking past the end of the memory map is allowed, after which reads return no bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::Mmap;
+    /// use std::io::Read;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { Mmap::open("README.md")? };
+    /// let mut title = String::new();
+    /// mmap.reader().take(6).read_to_string(&mut title)?;
+    /// assert_eq!("# mapr", title);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reader(&self) -> Cursor<&[u8]> {
+        Cursor::new(&self[..])
+    }
+
+    /// Returns the bytes of the memory map in `range`.
+    ///
+    /// Unlike indexing the memory map, this reports a range out of bounds as an error rather than
+    /// panicking.
     ///
     /// # Errors
     ///
-    /// This method returns an error when the underlying system call fails.
-    pub fn map_anon(length: usize) -> Result<MmapMut> {
-        MmapOptions::new().len(length).map_anon()
+    /// This method returns an error of kind `InvalidInput` if `range` is out of the bounds of the
+    /// memory map, or if it ends before it starts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::Mmap;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { Mmap::open("README.md")? };
+    /// assert_eq!(b"mapr", mmap.slice(2..6)?);
+    /// assert!(mmap.slice(..mmap.len() + 1).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Result<&[u8]> {
+        let (start, end) = resolve_range(range, self.len())?;
+        Ok(&self[start..end])
     }
 
-    /// Flushes outstanding memory map modifications to disk.
+    /// Creates a separate read-only memory map of the bytes of the file in `range`, relative to
+    /// the start of this memory map.
     ///
-    /// When this method returns with a non-error result, all outstanding changes to a file-backed
-    /// memory map are guaranteed to be durably stored. The file's metadata (including last
-    /// modification timestamp) may not be updated.
+    /// The new memory map is independent of this one: it can e.g. be made writable with
+    /// [`make_mut()`](Mmap::make_mut()) on its own, and outlives this one. It maps the file
+    /// through the retained file handle, so it does not include changes to a copy-on-write memory
+    /// map.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the memory map is anonymous, or if
+    /// `range` is empty, out of the bounds of the memory map, or ends before it starts. It also
+    /// returns an error when the underlying system call fails.
     ///
     /// # Example
     ///
     /// ```
-    /// use std::fs::OpenOptions;
-    /// use std::io::Write;
-    /// use std::path::PathBuf;
+    /// use mapr::Mmap;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { Mmap::open("README.md")? };
+    /// let title = mmap.sub_map(2..6)?;
+    /// drop(mmap);
+    /// assert_eq!(b"mapr", &title[..]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sub_map<R: RangeBounds<usize>>(&self, range: R) -> Result<Mmap> {
+        let (start, end) = resolve_range(range, self.len())?;
+        if start == end {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map must have a non-zero length",
+            ));
+        }
+        let file = self.inner.file().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "memory map is not backed by a file",
+            )
+        })?;
+        unsafe {
+            MmapOptions::new()
+                .offset(self.inner.offset() + start as u64)
+                .len(end - start)
+                .map(file)
+        }
+    }
+
+    /// Reinterprets the memory map as a slice of `T`, e.g. to read an array of fixed-size records.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be plain old data: every bit pattern of `size_of::<T>()` bytes must be a valid
+    /// `T`, which holds for integers, floats, and arrays and `#[repr(C)]` structs of them without
+    /// padding, but not for e.g. `bool`, `char`, enums or references. The contents are read in the
+    /// native byte order.
     ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the memory map is not aligned for
+    /// `T`, if its length is not a multiple of the size of `T`, or if `T` is zero-sized.
+    ///
+    /// # Example
+    ///
+    /// ```
     /// use mapr::MmapMut;
     ///
     /// # fn main() -> std::io::Result<()> {
-    /// # let tempdir = tempdir::TempDir::new("mmap")?;
-    /// let path: PathBuf = /* path to file */
-    /// #   tempdir.path().join("flush");
-    /// let file = OpenOptions::new().read(true).write(true).create(true).open(&path)?;
-    /// file.set_len(128)?;
-    ///
-    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    /// let mut mmap = MmapMut::map_anon(16)?;
+    /// mmap[..4].copy_from_slice(&7u32.to_ne_bytes());
+    /// let mmap = mmap.make_read_only()?;
     ///
-    /// (&mut mmap[..]).write_all(b"Hello, world!")?;
-    /// mmap.flush()?;
+    /// let values: &[u32] = unsafe { mmap.as_slice_of()? };
+    /// assert_eq!(&[7, 0, 0, 0], values);
     /// # Ok(())
     /// # }
     /// ```
-    pub fn flush(&self) -> Result<()> {
-        let len = self.len();
-        self.inner.flush(0, len)
+    pub unsafe fn as_slice_of<T: Copy>(&self) -> Result<&[T]> {
+        let len = typed_len::<T>(self.as_ptr(), self.len())?;
+        Ok(slice::from_raw_parts(self.as_ptr() as *const T, len))
     }
 
-    /// Asynchronously flushes outstanding memory map modifications to disk.
+    /// Faults in the pages of the memory map until they are all resident or `deadline` passes.
     ///
-    /// This method initiates flushing modified pages to durable storage, but it will not wait for
-    /// the operation to complete before returning. The file's metadata (including last
-    /// modification timestamp) may not be updated.
-    pub fn flush_async(&self) -> Result<()> {
-        let len = self.len();
-        self.inner.flush_async(0, len)
+    /// Pages are touched in order by reading a byte from each, checking the clock periodically.
+    /// Returns `true` if every page was touched, and `false` if the deadline passed first. This
+    /// bounds the time spent warming up a large memory map, e.g. to meet a startup latency budget.
+    ///
+    /// # Errors
+    ///
+    /// Touching the pages does not currently fail, but populating them through the system, e.g.
+    /// with `MADV_POPULATE_READ`, may in the future.
+    pub fn populate_with_deadline(&self, deadline: Instant) -> Result<bool> {
+        Ok(populate_with_deadline(self, deadline))
     }
 
-    /// Flushes outstanding memory map modifications in the range to disk.
+    /// Returns the current protection of the pages covering `offset..offset + len` of the memory
+    /// map, e.g. to check the effect of [`MmapMut::protect_range()`].
     ///
-    /// The offset and length must be in the bounds of the memory map.
+    /// This reads `/proc/self/maps` on Linux, and calls `VirtualQuery` on Windows. Writable pages
+    /// of private file-backed memory maps are reported as [`Protection::CopyOnWrite`].
     ///
-    /// When this method returns with a non-error result, all outstanding changes to a file-backed
-    /// memory in the range are guaranteed to be durable stored. The file's metadata (including
-    /// last modification timestamp) may not be updated. It is not guaranteed the only the changes
-    /// in the specified range are flushed; other outstanding changes to the memory map may be
-    /// flushed as well.
-    pub fn flush_range(&self, offset: usize, len: usize) -> Result<()> {
-        self.inner.flush(offset, len)
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is empty or out of the
+    /// bounds of the memory map, and of kind `Unsupported` on other platforms. It returns an
+    /// error of kind `Other` if the range spans pages with different protections, or pages with a
+    /// protection no [`Protection`] variant describes, such as inaccessible pages.
+    pub fn protection_of(&self, offset: usize, len: usize) -> Result<Protection> {
+        match offset.checked_add(len) {
+            Some(end) if len > 0 && end <= self.len() => self.inner.protection_of(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is empty or out of the bounds of the memory map",
+            )),
+        }
     }
 
-    /// Asynchronously flushes outstanding memory map modifications in the range to disk.
+    /// Returns whether each page of the memory map is currently resident in memory.
     ///
-    /// The offset and length must be in the bounds of the memory map.
+    /// The result has an entry for every page the memory map overlaps, including partially
+    /// overlapped pages at either end. This corresponds to `mincore` on unix.
     ///
-    /// This method initiates flushing modified pages to durable storage, but it will not wait for
-    /// the operation to complete before returning. The file's metadata (including last
-    /// modification timestamp) may not be updated. It is not guaranteed that the only changes
-    /// flushed are those in the specified range; other outstanding changes to the memory map may
-    /// be flushed as well.
-    pub fn flush_async_range(&self, offset: usize, len: usize) -> Result<()> {
-        self.inner.flush_async(offset, len)
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` on Windows, or when the underlying
+    /// system call fails.
+    pub fn residency(&self) -> Result<Vec<bool>> {
+        self.inner.mincore()
     }
 
-    /// Returns an immutable version of this memory mapped buffer.
+    /// Returns the fraction of the pages of the memory map which are currently resident in
+    /// memory, between 0.0 and 1.0.
     ///
-    /// If the memory map is file-backed, the file must have been opened with read permissions.
+    /// This is cheaper than counting the entries of [`residency()`](Self::residency()), and
+    /// returns 0.0 for an empty memory map.
     ///
     /// # Errors
     ///
-    /// This method returns an error when the underlying system call fails, which can happen for a
-    /// variety of reasons, such as when the file has not been opened with read permissions.
+    /// This method returns an error of kind `Unsupported` on Windows, or when the underlying
+    /// system call fails.
+    pub fn resident_percentage(&self) -> Result<f64> {
+        if self.is_empty() {
+            return Ok(0.0);
+        }
+        self.inner.resident_fraction()
+    }
+
+    /// Faults in every page of the memory map by reading a byte from each.
     ///
-    /// # Example
+    /// Unlike [`MmapOptions::populate()`], this works on every platform and on existing memory
+    /// maps. Pages are only read, so copy-on-write pages are not copied.
+    pub fn prefault(&self) {
+        prefault(self);
+    }
+
+    /// Faults in every page overlapping `offset..offset + len` of the memory map by reading a byte
+    /// from each.
     ///
-    /// ```
-    /// use std::io::Write;
-    /// use std::path::PathBuf;
+    /// # Errors
     ///
-    /// use mapr::{Mmap, MmapMut};
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map.
+    pub fn prefault_range(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => {
+                prefault(&self[offset..end]);
+                Ok(())
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Copies bytes starting at `offset` in the memory map into `buf`, returning the number of
+    /// bytes copied.
     ///
-    /// # fn main() -> std::io::Result<()> {
-    /// let mut mmap = MmapMut::map_anon(128)?;
+    /// Fewer than `buf.len()` bytes are copied if the end of the memory map is reached; an
+    /// `offset` equal to the length of the map copies nothing.
     ///
-    /// (&mut mmap[..]).write(b"Hello, world!")?;
+    /// # Errors
     ///
-    /// let mmap: Mmap = mmap.make_read_only()?;
-    /// # Ok(())
-    /// # }
-    /// ```
-    pub fn make_read_only(mut self) -> Result<Mmap> {
-        self.inner.make_read_only()?;
-        Ok(Mmap { inner: self.inner })
+    /// This method returns an error of kind `InvalidInput` if `offset` is past the end of the
+    /// memory map.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        read_at(self, offset, buf)
     }
 
-    /// Transition the memory map to be readable and executable.
+    /// Returns a parallel iterator over the memory map in chunks of `chunk_size` bytes.
     ///
-    /// If the memory map is file-backed, the file must have been opened with execute permissions.
+    /// `chunk_size` is rounded up to a multiple of the page size, so that each chunk covers whole
+    /// pages and no page is faulted in by more than one thread. The last chunk may be shorter.
     ///
-    /// # Errors
+    /// This method is only available with the `rayon` feature.
     ///
-    /// This method returns an error when the underlying system call fails, which can happen for a
-    /// variety of reasons, such as when the file has not been opened with execute permissions.
-    pub fn make_exec(mut self) -> Result<Mmap> {
-        self.inner.make_exec()?;
-        Ok(Mmap { inner: self.inner })
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::Mmap;
+    /// use rayon::prelude::*;
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = unsafe { Mmap::map(&File::open("README.md")?)? };
+    ///
+    /// let sum: u64 = mmap
+    ///     .par_chunks(1 << 20)
+    ///     .map(|chunk| chunk.iter().map(|&b| b as u64).sum::<u64>())
+    ///     .sum();
+    /// assert_eq!(mmap.iter().map(|&b| b as u64).sum::<u64>(), sum);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "rayon")]
+    pub fn par_chunks(
+        &self,
+        chunk_size: usize,
+    ) -> impl rayon::iter::IndexedParallelIterator<Item = &[u8]> {
+        use rayon::slice::ParallelSlice;
+
+        let page_size = page_size();
+        let chunk_size = chunk_size.max(1).div_ceil(page_size) * page_size;
+        ParallelSlice::par_chunks(&self[..], chunk_size)
     }
 
     /// Uses `mlock` to lock the whole memory map into RAM.
     ///
+    /// On unix, when locking fails with `EPERM` or `ENOMEM`, the error message includes the
+    /// `RLIMIT_MEMLOCK` soft limit, and the original OS error is available as its source.
+    ///
+    /// On Windows this uses `VirtualLock`, and returns an error of kind `OutOfMemory` if the
+    /// memory map does not fit into the working set quota of the process.
+    ///
     /// Note this requires privileged access.
-    #[cfg(unix)]
     pub fn mlock(&mut self) -> Result<()> {
         self.inner.mlock()?;
-        
+
         Ok(())
     }
 
     /// Uses `munlock` to unlock the whole memory map.
     ///
+    /// On Windows this uses `VirtualUnlock`.
+    ///
     /// Note this requires privileged access.
-    #[cfg(unix)]
     pub fn munlock(&mut self) -> Result<()> {
         self.inner.munlock()?;
-        
+
         Ok(())
     }
-}
 
-impl Deref for MmapMut {
+    /// Locks the pages of the memory map into RAM as they are faulted in, rather than faulting
+    /// in every page up front like [`mlock()`](Self::mlock()).
+    ///
+    /// This uses `mlock2` with the `MLOCK_ONFAULT` flag, and is only supported on Linux 4.4 and
+    /// later.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` on other platforms and on older
+    /// kernels, in which case callers may fall back to `mlock()`, or when the underlying system
+    /// call fails.
+    pub fn mlock_on_fault(&mut self) -> Result<()> {
+        self.inner.mlock_on_fault()
+    }
+
+    /// Locks the pages overlapping `offset..offset + len` of the memory map into RAM.
+    ///
+    /// Whole pages are locked, so e.g. locking bytes 100..200 locks the page containing them.
+    /// This uses `mlock` on unix and `VirtualLock` on Windows.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails.
+    pub fn mlock_range(&mut self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.mlock_range(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Unlocks the pages overlapping `offset..offset + len` of the memory map.
+    ///
+    /// This uses `munlock` on unix and `VirtualUnlock` on Windows.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails.
+    pub fn munlock_range(&mut self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.munlock_range(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Invalidates the cached pages of the memory map, so that subsequent reads see changes
+    /// made to the backing file by others.
+    ///
+    /// This corresponds to `msync` with `MS_INVALIDATE` on unix. On systems with a unified
+    /// buffer cache, such as Linux, memory maps of a file already see the changes made through
+    /// `write` or other memory maps of it, so this is effectively a no-op there; it matters on
+    /// systems where memory maps may cache stale pages. Writers should flush their changes with
+    /// [`MmapMut::flush()`] before readers invalidate. On Windows, views of a file are always
+    /// coherent and this does nothing.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails, e.g. with `EBUSY` on
+    /// Linux if part of the memory map is locked.
+    pub fn invalidate(&self) -> Result<()> {
+        self.inner.invalidate()
+    }
+
+    /// Advises the operating system how the memory map will be accessed.
+    ///
+    /// This corresponds to `madvise` on unix, and to `PrefetchVirtualMemory` for
+    /// [`Advice::WillNeed`] on Windows. Advice which the platform does not support results in an
+    /// error rather than being silently ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` if the advice is not available on this
+    /// platform, or when the underlying system call fails.
+    pub fn advise(&self, advice: Advice) -> Result<()> {
+        advise(&self.inner, advice)
+    }
+
+    /// Advises the operating system how the range of the memory map will be accessed.
+    ///
+    /// The advice applies to every page overlapping `offset..offset + len`, so it may extend to
+    /// bytes just outside of the range. This is useful e.g. to prefetch only the pages about to be
+    /// read with [`Advice::WillNeed`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, of kind `Unsupported` if the advice is not available on this platform, or
+    /// when the underlying system call fails.
+    pub fn advise_range(&self, advice: Advice, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => advise_range(&self.inner, advice, offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Resets any access pattern advice given for the memory map back to [`Advice::Normal`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn reset_advice(&self) -> Result<()> {
+        self.advise(Advice::Normal)
+    }
+
+    /// Applies `advice` to the memory map for the duration of the closure `f`.
+    ///
+    /// The advice is reset to [`Advice::Normal`] once `f` returns, or if it panics, so long-lived
+    /// memory maps are not left with stale hints.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `advice` could not be applied, in which case `f` is not
+    /// run.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::{Advice, Mmap};
+    /// use std::fs::File;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = File::open("README.md")?;
+    /// let mmap = unsafe { Mmap::map(&file)? };
+    ///
+    /// let newlines = mmap.with_advice(Advice::Sequential, |mmap| {
+    ///     mmap.iter().filter(|&&b| b == b'\n').count()
+    /// })?;
+    /// assert!(newlines > 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_advice<F, R>(&self, advice: Advice, f: F) -> Result<R>
+    where
+        F: FnOnce(&Mmap) -> R,
+    {
+        self.advise(advice)?;
+        let _guard = AdviceGuard(&self.inner);
+        Ok(f(self))
+    }
+
+    /// Removes execute permission from the memory map, leaving it readable.
+    ///
+    /// This is a defense-in-depth measure for data maps: it is a no-op for memory maps which are
+    /// not executable.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn ensure_no_exec(&self) -> Result<()> {
+        self.inner.ensure_no_exec(false)
+    }
+}
+
+impl Deref for Mmap {
     type Target = [u8];
 
     #[inline]
@@ -705,249 +2288,5472 @@ impl Deref for MmapMut {
     }
 }
 
-impl DerefMut for MmapMut {
-    #[inline]
-    fn deref_mut(&mut self) -> &mut [u8] {
-        unsafe { slice::from_raw_parts_mut(self.inner.mut_ptr(), self.inner.len()) }
+impl Clone for Mmap {
+    /// Creates an independent read-only memory map with the same contents, see
+    /// [`try_clone()`](Mmap::try_clone()).
+    ///
+    /// # Panics
+    ///
+    /// Panics if creating the new memory map fails.
+    fn clone(&self) -> Mmap {
+        self.try_clone().expect("failed to clone memory map")
     }
 }
 
-impl AsRef<[u8]> for MmapMut {
+impl AsRef<[u8]> for Mmap {
     #[inline]
     fn as_ref(&self) -> &[u8] {
         self.deref()
     }
 }
 
-impl AsMut<[u8]> for MmapMut {
-    #[inline]
-    fn as_mut(&mut self) -> &mut [u8] {
-        self.deref_mut()
-    }
-}
-
-impl fmt::Debug for MmapMut {
+impl fmt::Debug for Mmap {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.debug_struct("MmapMut")
+        fmt.debug_struct("Mmap")
             .field("ptr", &self.as_ptr())
             .field("len", &self.len())
             .finish()
     }
-}
+}
+
+/// Formats the memory map with a human-readable length and its base pointer, for logging.
+///
+/// Use the `Debug` implementation for the exact length.
+///
+/// # Example
+///
+/// ```
+/// use mapr::Mmap;
+/// use std::fs::File;
+/// #
+/// # fn main() -> std::io::Result<()> {
+/// let mmap = unsafe { Mmap::map(&File::open("README.md")?)? };
+/// let display = mmap.to_string();
+/// assert!(display.starts_with("Mmap("));
+/// assert!(display.contains(" @ 0x"));
+/// # Ok(())
+/// # }
+/// ```
+impl fmt::Display for Mmap {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Mmap({} @ {:p})", HumanSize(self.len()), self.as_ptr())
+    }
+}
+
+/// Memory maps compare equal if their contents are equal, regardless of where they are mapped.
+impl PartialEq for Mmap {
+    fn eq(&self, other: &Mmap) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl PartialEq<MmapMut> for Mmap {
+    fn eq(&self, other: &MmapMut) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl Eq for Mmap {}
+
+/// Hashes the contents of the memory map, which takes time linear in its length, and faults in
+/// every page.
+impl Hash for Mmap {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self[..].hash(state)
+    }
+}
+
+/// A handle to a mutable memory mapped buffer.
+///
+/// A file-backed `MmapMut` buffer may be used to read from or write to a file. An anonymous
+/// `MmapMut` buffer may be used any place that an in-memory byte buffer is needed. Use
+/// [`MmapMut::map_mut()`] and [`MmapMut::map_anon()`] to create a mutable memory map of the
+/// respective types, or [`MmapOptions::map_mut()`] and [`MmapOptions::map_anon()`] if non-default
+/// options are required.
+///
+/// A file backed `MmapMut` is created by `&File` reference, and will remain valid even after the
+/// `File` is dropped. In other words, the `MmapMut` handle is completely independent of the `File`
+/// used to create it. This is achieved by duplicating the underlying file handle, which the memory
+/// map keeps open, e.g. to sync the file. The memory will be unmapped when the `MmapMut` handle is
+/// dropped, or explicitly with [`unmap()`](MmapMut::unmap()) to observe errors.
+///
+/// Dereferencing and accessing the bytes of the buffer may result in page faults (e.g. swapping
+/// the mapped pages into physical memory) though the details of this are platform specific.
+///
+/// `Mmap` is [`Sync`](std::marker::Sync) and [`Send`](std::marker::Send).
+///
+/// See [`Mmap`] for the immutable version.
+///
+/// ## Safety
+///
+/// All file-backed memory map constructors are marked `unsafe` because of the potential for
+/// *Undefined Behavior* (UB) using the map if the underlying file is subsequently modified, in or
+/// out of process. Applications must consider the risk and take appropriate precautions when using
+/// file-backed maps. Solutions such as file permissions, locks or process-private (e.g. unlinked)
+/// files exist but are platform specific and limited.
+///
+/// Shared file-backed maps which are written to concurrently by other maps or processes should be
+/// accessed through [`MmapMut::as_shared_atomic_slice()`] rather than through `Deref` and
+/// `DerefMut`, which assume no one else modifies the memory while the slice is borrowed. With the
+/// `log` feature enabled, debug builds log a warning the first time a shared file-backed map is
+/// mutably dereferenced.
+pub struct MmapMut {
+    inner: MmapInner,
+}
+
+impl MmapMut {
+    /// Creates a writeable memory map backed by a file.
+    ///
+    /// This is equivalent to calling `MmapOptions::new().map_mut(file)`.
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapMut#safety) for why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails, which can happen for a
+    /// variety of reasons, such as when the file is not open with read and write permissions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::OpenOptions;
+    /// use std::path::PathBuf;
+    ///
+    /// use mapr::MmapMut;
+    /// #
+    /// # fn main() -> std::io::Result<()> {
+    /// # let tempdir = tempdir::TempDir::new("mmap")?;
+    /// let path: PathBuf = /* path to file */
+    /// #   tempdir.path().join("map_mut");
+    /// let file = OpenOptions::new()
+    ///                        .read(true)
+    ///                        .write(true)
+    ///                        .create(true)
+    ///                        .open(&path)?;
+    /// file.set_len(13)?;
+    ///
+    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    ///
+    /// mmap.copy_from_slice(b"Hello, world!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn map_mut(file: &File) -> Result<MmapMut> {
+        MmapOptions::new().map_mut(file)
+    }
+
+    /// Opens the file at `path` for reading and writing and creates a writeable memory map
+    /// backed by it.
+    ///
+    /// This is equivalent to calling `MmapMut::map_mut()` with the file opened through
+    /// `OpenOptions::new().read(true).write(true).open(path)`.
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapMut#safety) for why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the error from opening the file unchanged, and an error of kind
+    /// `InvalidInput` if the file is empty, or when the underlying system call fails.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<MmapMut> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        MmapMut::map_mut(&file)
+    }
+
+    /// Creates the file at `path` if it does not exist, sets its length to `len` bytes, and
+    /// creates a writeable memory map backed by it.
+    ///
+    /// Existing contents of the file within `len` bytes are kept. On Windows the file is also
+    /// opened with execute access, so the memory map can later be made executable with
+    /// [`make_exec()`](MmapMut::make_exec()).
+    ///
+    /// # Safety
+    ///
+    /// See the [type-level docs](MmapMut#safety) for why this function is `unsafe`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `len` is zero, without creating the
+    /// file, the error from creating or resizing the file unchanged, or when the underlying system
+    /// call fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    /// #
+    /// # fn main() -> std::io::Result<()> {
+    /// # let tempdir = tempdir::TempDir::new("mmap")?;
+    /// let mut mmap = unsafe { MmapMut::create(tempdir.path().join("scratch"), 4096)? };
+    /// mmap[..5].copy_from_slice(b"hello");
+    /// mmap.flush()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn create<P: AsRef<Path>>(path: P, len: u64) -> Result<MmapMut> {
+        if len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map must have a non-zero length",
+            ));
+        }
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            use winapi::um::winnt::{GENERIC_EXECUTE, GENERIC_READ, GENERIC_WRITE};
+
+            options.access_mode(GENERIC_READ | GENERIC_WRITE | GENERIC_EXECUTE);
+        }
+        let file = options.open(path)?;
+        file.set_len(len)?;
+        MmapMut::map_mut(&file)
+    }
+
+    /// Creates an anonymous memory map.
+    ///
+    /// This is equivalent to calling `MmapOptions::new().len(length).map_anon()`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn map_anon(length: usize) -> Result<MmapMut> {
+        MmapOptions::new().len(length).map_anon()
+    }
+
+    /// Takes ownership of an existing writable memory map of `len` bytes at `ptr`, e.g. one
+    /// created by another library.
+    ///
+    /// The memory is unmapped when the returned `MmapMut` is dropped, and is treated as anonymous
+    /// memory otherwise, e.g. [`resize()`](MmapMut::resize()) copies it to a new memory map.
+    ///
+    /// # Safety
+    ///
+    /// **The returned `MmapMut` unmaps the memory**, so the caller must uphold all of the
+    /// following:
+    ///
+    /// * `ptr` must point into the first page of a live memory map created by `mmap` on unix, or
+    ///   into the first allocation granule of a view created by `MapViewOfFile` on Windows, such
+    ///   as a pointer returned by `into_raw()`;
+    /// * on unix, the memory map must start at the page boundary at or below `ptr` and end `len`
+    ///   bytes after `ptr`, and `len` must be non-zero;
+    /// * the memory must be readable and writable for the lifetime of the `MmapMut`;
+    /// * nothing else may unmap the memory, or access it while the `MmapMut` exists.
+    ///
+    /// Violating any of these can unmap unrelated memory or unmap the memory twice, which is
+    /// *Undefined Behavior*.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(unix)]
+    /// # fn main() {
+    /// use mapr::MmapMut;
+    ///
+    /// let ptr = unsafe {
+    ///     libc::mmap(
+    ///         std::ptr::null_mut(),
+    ///         4096,
+    ///         libc::PROT_READ | libc::PROT_WRITE,
+    ///         libc::MAP_PRIVATE | libc::MAP_ANON,
+    ///         -1,
+    ///         0,
+    ///     )
+    /// };
+    /// assert_ne!(libc::MAP_FAILED, ptr);
+    ///
+    /// let mut mmap = unsafe { MmapMut::from_raw(ptr as *mut u8, 4096) };
+    /// mmap[..5].copy_from_slice(b"hello");
+    /// // Dropping the memory map unmaps the memory.
+    /// drop(mmap);
+    /// # }
+    /// # #[cfg(windows)]
+    /// # fn main() {}
+    /// ```
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> MmapMut {
+        MmapMut {
+            inner: MmapInner::from_raw(ptr, len),
+        }
+    }
+
+    /// Creates a mirrored anonymous memory map of `len` bytes, for use as a ring buffer.
+    ///
+    /// The memory is backed by a shared anonymous file (see
+    /// [`MmapOptions::map_anon_shared_fd()`]) which is mapped twice into adjacent address space,
+    /// so that the `len` bytes following the memory map mirror it: reads and writes which wrap
+    /// around the end of the buffer are contiguous in memory. [`len()`](#method.len) and the slice
+    /// the memory map dereferences to only cover the first `len` bytes; the pointer returned by
+    /// [`as_ptr()`](#method.as_ptr) or [`as_mut_ptr()`](#method.as_mut_ptr) is valid for `2 * len`
+    /// bytes.
+    ///
+    /// Mirrored memory maps should not be resized: resizing removes the mirror, or fails.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `len` is not a non-zero multiple of
+    /// the page size, or when the underlying system calls fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::{page_size, MmapMut};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let page_size = page_size();
+    /// let mut ring = MmapMut::mirrored_anon(page_size)?;
+    /// let wrapped = unsafe { std::slice::from_raw_parts_mut(ring.as_mut_ptr(), 2 * page_size) };
+    /// wrapped[page_size - 2..page_size + 3].copy_from_slice(b"hello");
+    /// assert_eq!(b"llo", &ring[..3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(unix)]
+    pub fn mirrored_anon(len: usize) -> Result<MmapMut> {
+        if len == 0 || len % page_size() != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "mirrored memory map length must be a non-zero multiple of the page size",
+            ));
+        }
+        MmapInner::mirrored(len).map(|inner| MmapMut { inner })
+    }
+
+    /// Flushes outstanding memory map modifications to disk.
+    ///
+    /// When this method returns with a non-error result, all outstanding changes to a file-backed
+    /// memory map are guaranteed to be durably stored. The file's metadata (including last
+    /// modification timestamp) may not be updated.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::OpenOptions;
+    /// use std::io::Write;
+    /// use std::path::PathBuf;
+    ///
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// # let tempdir = tempdir::TempDir::new("mmap")?;
+    /// let path: PathBuf = /* path to file */
+    /// #   tempdir.path().join("flush");
+    /// let file = OpenOptions::new().read(true).write(true).create(true).open(&path)?;
+    /// file.set_len(128)?;
+    ///
+    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    ///
+    /// (&mut mmap[..]).write_all(b"Hello, world!")?;
+    /// mmap.flush()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn flush(&self) -> Result<()> {
+        let len = self.len();
+        self.inner.flush(0, len)
+    }
+
+    /// Asynchronously flushes outstanding memory map modifications to disk.
+    ///
+    /// This method initiates flushing modified pages to durable storage, but it will not wait for
+    /// the operation to complete before returning. The file's metadata (including last
+    /// modification timestamp) may not be updated.
+    pub fn flush_async(&self) -> Result<()> {
+        let len = self.len();
+        self.inner.flush_async(0, len)
+    }
+
+    /// Flushes outstanding memory map modifications in the range to disk.
+    ///
+    /// The offset and length must be in the bounds of the memory map.
+    ///
+    /// When this method returns with a non-error result, all outstanding changes to a file-backed
+    /// memory in the range are guaranteed to be durable stored. The file's metadata (including
+    /// last modification timestamp) may not be updated. It is not guaranteed the only the changes
+    /// in the specified range are flushed; other outstanding changes to the memory map may be
+    /// flushed as well.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails.
+    pub fn flush_range(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.flush(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Asynchronously flushes outstanding memory map modifications in the range to disk.
+    ///
+    /// The offset and length must be in the bounds of the memory map.
+    ///
+    /// This method initiates flushing modified pages to durable storage, but it will not wait for
+    /// the operation to complete before returning. The file's metadata (including last
+    /// modification timestamp) may not be updated. It is not guaranteed that the only changes
+    /// flushed are those in the specified range; other outstanding changes to the memory map may
+    /// be flushed as well.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails.
+    pub fn flush_async_range(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.flush_async(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Invalidates the cached pages of the memory map, so that subsequent reads see changes
+    /// made to the backing file by others.
+    ///
+    /// This corresponds to `msync` with `MS_INVALIDATE` on unix. On systems with a unified
+    /// buffer cache, such as Linux, memory maps of a file already see the changes made through
+    /// `write` or other memory maps of it, so this is effectively a no-op there; it matters on
+    /// systems where memory maps may cache stale pages. Changes written through this memory map
+    /// are not discarded by this call, but should be [flushed](MmapMut::flush()) first, since
+    /// some systems may drop dirty cached pages. On Windows, views of a file are always coherent
+    /// and this does nothing.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails, e.g. with `EBUSY` on
+    /// Linux if part of the memory map is locked.
+    pub fn invalidate(&self) -> Result<()> {
+        self.inner.invalidate()
+    }
+
+    /// Flushes this memory map, then durably stores the whole of its backing file.
+    ///
+    /// Unlike [`flush()`](MmapMut::flush()), which only covers the range of this memory map, this
+    /// `fsync`s the entire file, including changes made through other memory maps of it. This
+    /// makes it a single durability barrier when several partial maps of one file exist. On
+    /// Windows, changes made through other maps are only covered once those maps have been
+    /// flushed, e.g. with [`flush_async()`](MmapMut::flush_async()).
+    ///
+    /// This is equivalent to [`flush_all()`](MmapMut::flush_all()).
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the memory map is anonymous, or when the underlying system
+    /// calls fail.
+    pub fn flush_file(&self) -> Result<()> {
+        self.flush_all()
+    }
+
+    /// Flushes this memory map, then durably stores the data of its backing file, like
+    /// [`File::sync_data()`].
+    ///
+    /// This uses `fdatasync` where available, which skips flushing file metadata that is not
+    /// needed to read the data back, such as the modification timestamp. It is usually faster than
+    /// [`flush_all()`](MmapMut::flush_all()), and is sufficient for memory maps whose file length
+    /// does not change, which makes it the better choice for frequent flushes.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the memory map is anonymous, or when the underlying system
+    /// calls fail.
+    pub fn flush_data(&self) -> Result<()> {
+        let file = self.backing_file()?;
+        self.flush()?;
+        file.sync_data()
+    }
+
+    /// Flushes this memory map, then durably stores the data and metadata of its backing file,
+    /// like [`File::sync_all()`].
+    ///
+    /// This uses `fsync`, which also flushes all file metadata. Prefer
+    /// [`flush_data()`](MmapMut::flush_data()) unless the metadata must be durable as well.
+    ///
+    /// The memory map is flushed synchronously before the file is synced, so the metadata is
+    /// never made durable ahead of the data. The memory map retains its own handle to the file,
+    /// so none needs to be passed in. Syncing the file does not make its directory entry durable:
+    /// after creating or renaming the file, also sync its parent directory.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the memory map is anonymous, or when the underlying system
+    /// calls fail.
+    pub fn flush_all(&self) -> Result<()> {
+        let file = self.backing_file()?;
+        self.flush()?;
+        file.sync_all()
+    }
+
+    /// Returns the retained handle of the backing file.
+    fn backing_file(&self) -> Result<&File> {
+        self.inner
+            .file()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "memory map is not file-backed"))
+    }
+
+    /// Flushes the pages modified since they were last written back, returning the number of pages
+    /// of the memory map.
+    ///
+    /// The whole memory map is passed to `msync`, which only writes back the pages the kernel
+    /// tracks as dirty, so the cost is proportional to the modified pages. The kernel's
+    /// soft-dirty tracking is deliberately not used: it can only be reset for the whole process,
+    /// which would drop the modifications of other memory maps, and of this one while it is
+    /// flushed.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn flush_dirty(&self) -> Result<usize> {
+        let page_size = page_size();
+        let alignment = self.as_ptr() as usize % page_size;
+        self.flush()?;
+        Ok((self.len() + alignment).div_ceil(page_size))
+    }
+
+    /// Returns a [`DurabilityBarrier`] for recording modified ranges of this memory map, and
+    /// durably storing them with a single commit.
+    pub fn durability_barrier(&self) -> DurabilityBarrier<'_> {
+        DurabilityBarrier::new(self)
+    }
+
+    /// Returns an immutable version of this memory mapped buffer.
+    ///
+    /// If the memory map is file-backed, the file must have been opened with read permissions.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails, which can happen for a
+    /// variety of reasons, such as when the file has not been opened with read permissions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Write;
+    /// use std::path::PathBuf;
+    ///
+    /// use mapr::{Mmap, MmapMut};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(128)?;
+    ///
+    /// (&mut mmap[..]).write(b"Hello, world!")?;
+    ///
+    /// let mmap: Mmap = mmap.make_read_only()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn make_read_only(mut self) -> Result<Mmap> {
+        self.inner.make_read_only()?;
+        Ok(Mmap { inner: self.inner })
+    }
+
+    /// Transitions the memory map to be read-only, and wraps it in an [`Arc`] to be shared
+    /// between threads without copying.
+    ///
+    /// This is the same as `Arc::new(mmap.make_read_only()?)`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails, see
+    /// [`make_read_only()`](MmapMut::make_read_only()).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::thread;
+    ///
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(4096)?;
+    /// mmap.fill(7);
+    ///
+    /// let mmap = mmap.freeze()?;
+    /// let shared = mmap.clone();
+    /// thread::spawn(move || assert!(shared.iter().all(|&b| b == 7)))
+    ///     .join()
+    ///     .unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn freeze(self) -> Result<Arc<Mmap>> {
+        self.make_read_only().map(Arc::new)
+    }
+
+    /// Transition the memory map to be readable and executable.
+    ///
+    /// If the memory map is file-backed, the file must have been opened with execute permissions.
+    /// On Windows this means opening it with an access mode including `GENERIC_EXECUTE`, see
+    /// `std::os::windows::fs::OpenOptionsExt::access_mode`.
+    ///
+    /// The instruction cache is flushed for the memory map on architectures and platforms which
+    /// require it, so code written through it can be executed right away.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails, which can happen for a
+    /// variety of reasons, such as when the file has not been opened with execute permissions.
+    pub fn make_exec(mut self) -> Result<Mmap> {
+        self.inner.make_exec()?;
+        Ok(Mmap { inner: self.inner })
+    }
+
+    /// Changes the protection of the pages covering `offset..offset + len` of the memory map,
+    /// e.g. to make a header read-only while the rest of the memory map stays writable.
+    ///
+    /// Protections apply to whole pages, so when `offset` or `offset + len` are not page aligned,
+    /// the bytes sharing the first and last pages with the range are affected too. This
+    /// corresponds to `mprotect` on unix and `VirtualProtect` on Windows.
+    ///
+    /// The type of the memory map does not change, so writing to a range made read-only through
+    /// it faults with `SIGSEGV` on unix or an access violation on Windows, which terminates the
+    /// process. [`Protection::CopyOnWrite`] is the same as [`Protection::ReadWrite`] here, since a
+    /// protection change can not make a shared memory map copy-on-write, see
+    /// [`make_private()`](MmapMut::make_private()) instead.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails, e.g. when making a range of a
+    /// file-backed memory map executable without the file being opened with execute permissions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::{page_size, MmapMut, Protection};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(2 * page_size())?;
+    /// mmap[..5].copy_from_slice(b"magic");
+    /// mmap.protect_range(0, page_size(), Protection::Read)?;
+    ///
+    /// mmap[page_size()] = 1;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn protect_range(
+        &mut self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.protect_range(offset, len, protection),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Converts a shared, file-backed memory map into a private copy-on-write memory map of the
+    /// same file range.
+    ///
+    /// Existing contents are preserved, but subsequent writes are no longer carried through to the
+    /// underlying file, exactly as if the map had been created with [`MmapOptions::map_copy()`].
+    /// Memory maps which are already private are left unchanged.
+    ///
+    /// The base address of the memory map may change (it does on Windows, where views can not be
+    /// replaced in place), so pointers previously obtained from the map must not be reused.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if the memory map is anonymous, or when the underlying system
+    /// call fails.
+    pub fn make_private(&mut self) -> Result<()> {
+        self.inner.make_private()
+    }
+
+    /// Returns whether the memory map is synchronously durable, as requested with
+    /// [`MmapOptions::sync_on_write()`].
+    ///
+    /// This is `false` when `MAP_SYNC` was not requested, or when the file system did not support
+    /// it and the memory map fell back to a regular shared map. In that case writes are only
+    /// durable after a [`flush()`](MmapMut::flush()).
+    pub fn is_sync_mapped(&self) -> bool {
+        self.inner.is_sync()
+    }
+
+    /// Returns the contents of the memory map as a slice of atomic bytes.
+    ///
+    /// The memory of a shared, file-backed map may be modified at any time by other maps of the
+    /// same file, in or out of process. Plain `&[u8]` and `&mut [u8]` references assume that can
+    /// not happen, so using them while others write is *Undefined Behavior*. Atomic accesses do
+    /// not make that assumption, which makes this the sound way to read and write memory that is
+    /// concurrently modified elsewhere.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapMut::map_anon(16)?;
+    /// let bytes = mmap.as_shared_atomic_slice();
+    /// bytes[0].store(42, Ordering::Release);
+    /// assert_eq!(42, bytes[0].load(Ordering::Acquire));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn as_shared_atomic_slice(&self) -> &[AtomicU8] {
+        // `AtomicU8` has the same size and alignment as `u8`.
+        unsafe { slice::from_raw_parts(self.inner.ptr() as *const AtomicU8, self.inner.len()) }
+    }
+
+    /// Returns a reference to the `AtomicU32` at `offset` bytes into the memory map.
+    ///
+    /// Like [`as_shared_atomic_slice()`](MmapMut::as_shared_atomic_slice()), this is sound even if
+    /// the memory is concurrently modified elsewhere, e.g. for a counter shared with another
+    /// process through a shared memory map. For the counter to actually be shared, the memory map
+    /// must be shared, and the other processes must access it atomically as well.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the 4 bytes at `offset` are out of
+    /// the bounds of the memory map, or not aligned to 4 bytes in memory.
+    pub fn atomic_u32(&self, offset: usize) -> Result<&AtomicU32> {
+        self.atomic_at(offset)
+    }
+
+    /// Returns a reference to the `AtomicU64` at `offset` bytes into the memory map.
+    ///
+    /// See [`atomic_u32()`](MmapMut::atomic_u32()) for details. Only available on platforms with
+    /// 64-bit atomics.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the 8 bytes at `offset` are out of
+    /// the bounds of the memory map, or not aligned to 8 bytes in memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// use mapr::MmapOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mmap = MmapOptions::new().len(4096).map_anon()?;
+    /// let counter = mmap.atomic_u64(8)?;
+    /// counter.fetch_add(1, Ordering::SeqCst);
+    /// assert_eq!(1, counter.load(Ordering::SeqCst));
+    /// assert!(mmap.atomic_u64(4).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(target_has_atomic = "64")]
+    pub fn atomic_u64(&self, offset: usize) -> Result<&AtomicU64> {
+        self.atomic_at(offset)
+    }
+
+    /// Returns a reference to the atomic `T` at `offset` bytes into the memory map.
+    fn atomic_at<T>(&self, offset: usize) -> Result<&T> {
+        match offset.checked_add(mem::size_of::<T>()) {
+            Some(end) if end <= self.len() => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "range is out of the bounds of the memory map",
+                ))
+            }
+        }
+        let ptr = self.inner.ptr() as usize + offset;
+        if ptr % mem::align_of::<T>() != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "offset is not aligned for the atomic type",
+            ));
+        }
+        Ok(unsafe { &*(ptr as *const T) })
+    }
+
+    /// Unmaps the memory map and returns the file backing it.
+    ///
+    /// The returned `File` is an owned duplicate of the handle the memory map was created from,
+    /// so it can be used (e.g. to truncate the file) without reopening it by path. Returns `None`
+    /// for anonymous memory maps.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::OpenOptions;
+    ///
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// # let tempdir = tempdir::TempDir::new("mmap")?;
+    /// let file = OpenOptions::new()
+    ///                        .read(true)
+    ///                        .write(true)
+    ///                        .create(true)
+    ///                        .open(tempdir.path().join("into_backing_file"))?;
+    /// file.set_len(128)?;
+    ///
+    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    /// drop(file);
+    /// mmap[..5].copy_from_slice(b"hello");
+    ///
+    /// let file = mmap.into_backing_file().unwrap();
+    /// file.set_len(5)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_backing_file(self) -> Option<File> {
+        self.inner.into_file()
+    }
+
+    /// Unmaps the memory map, returning any error.
+    ///
+    /// Dropping a memory map also unmaps it, but has to ignore errors. Use this method where a
+    /// failure to unmap must be observed. Unmapping is only attempted once: if it fails, the
+    /// memory map is not unmapped again on drop.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn unmap(self) -> Result<()> {
+        let mut inner = self.inner;
+        inner.close()
+    }
+
+    /// Releases ownership of the memory map without unmapping it, returning a pointer to its
+    /// first byte and its length, e.g. to hand it to C.
+    ///
+    /// The memory map is no longer unmapped on drop, which is now the caller's responsibility,
+    /// e.g. by passing the pointer and length back to [`MmapMut::from_raw()`] or to `munmap`.
+    /// Any retained file handle is closed, and any pages mapped before the page containing the
+    /// pointer or after the memory map, such as guard pages, are unmapped. If the memory map does
+    /// not start on a page boundary, as for file-backed memory maps created at an unaligned
+    /// offset, the underlying memory map starts at the page boundary below the pointer, and
+    /// covers the `len` bytes after it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let (ptr, len) = MmapMut::map_anon(4096)?.into_raw();
+    /// unsafe { *ptr = 42 };
+    ///
+    /// // Later, take ownership back to unmap the memory.
+    /// drop(unsafe { MmapMut::from_raw(ptr, len) });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_raw(self) -> (*mut u8, usize) {
+        self.inner.into_raw()
+    }
+
+    /// Releases ownership of the memory map without unmapping it, returning a mutable slice of it
+    /// which lives for the rest of the program, unless the memory is unmapped.
+    ///
+    /// See [`into_raw()`](MmapMut::into_raw()) for how the memory can be unmapped again.
+    pub fn leak(self) -> &'static mut [u8] {
+        let (ptr, len) = self.into_raw();
+        unsafe { slice::from_raw_parts_mut(ptr, len) }
+    }
+
+    /// Splits the memory map into two regions at `mid`, the first covering `[0, mid)` and the
+    /// second `[mid, len)`.
+    ///
+    /// The regions can be written independently, e.g. from different threads. They share the
+    /// memory map, which is unmapped as a whole once both are dropped. `mid` need not be page
+    /// aligned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    pub fn split_at_mut(self, mid: usize) -> (MmapRegion, MmapRegion) {
+        assert!(mid <= self.len(), "split point is out of bounds");
+        let len = self.len();
+        MmapRegion::new(Arc::new(self.inner), 0, len).split_at_mut(mid)
+    }
+
+    /// Copies the contents of the memory map into a new `Vec<u8>`.
+    ///
+    /// This is equivalent to `self[..].to_vec()` or `self.as_ref().to_vec()`. The pages are read
+    /// in order, so the kernel's readahead handles large file-backed memory maps well.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(4096)?;
+    /// mmap[..5].copy_from_slice(b"hello");
+    /// let contents: Vec<u8> = mmap.to_vec();
+    /// assert_eq!(b"hello", &contents[..5]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_vec(&self) -> Vec<u8> {
+        self[..].to_vec()
+    }
+
+    /// Reinterprets the memory map as a mutable slice of `T`, e.g. to write an array of
+    /// fixed-size records in place.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be plain old data: every bit pattern of `size_of::<T>()` bytes must be a valid
+    /// `T`, and `T` must not contain padding, which holds for integers, floats, and arrays and
+    /// `#[repr(C)]` structs of them without padding, but not for e.g. `bool`, `char`, enums or
+    /// references. The contents are read and written in the native byte order.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the memory map is not aligned for
+    /// `T`, e.g. because it was created at a file offset which is not a multiple of the alignment
+    /// of `T`, if its length is not a multiple of the size of `T`, or if `T` is zero-sized.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(16)?;
+    /// let values: &mut [u64] = unsafe { mmap.as_mut_slice_of()? };
+    /// values[1] = 7;
+    /// assert_eq!(&7u64.to_ne_bytes(), &mmap[8..]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub unsafe fn as_mut_slice_of<T: Copy>(&mut self) -> Result<&mut [T]> {
+        let len = typed_len::<T>(self.as_ptr(), self.len())?;
+        Ok(slice::from_raw_parts_mut(self.as_mut_ptr() as *mut T, len))
+    }
+
+    /// Faults in the pages of the memory map until they are all resident or `deadline` passes.
+    ///
+    /// Pages are touched in order by reading a byte from each, checking the clock periodically.
+    /// Returns `true` if every page was touched, and `false` if the deadline passed first. This
+    /// bounds the time spent warming up a large memory map, e.g. to meet a startup latency budget.
+    ///
+    /// # Errors
+    ///
+    /// Touching the pages does not currently fail, but populating them through the system, e.g.
+    /// with `MADV_POPULATE_READ`, may in the future.
+    pub fn populate_with_deadline(&self, deadline: Instant) -> Result<bool> {
+        Ok(populate_with_deadline(self, deadline))
+    }
+
+    /// Returns the current protection of the pages covering `offset..offset + len` of the memory
+    /// map, e.g. to check the effect of [`MmapMut::protect_range()`].
+    ///
+    /// This reads `/proc/self/maps` on Linux, and calls `VirtualQuery` on Windows. Writable pages
+    /// of private file-backed memory maps are reported as [`Protection::CopyOnWrite`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is empty or out of the
+    /// bounds of the memory map, and of kind `Unsupported` on other platforms. It returns an
+    /// error of kind `Other` if the range spans pages with different protections, or pages with a
+    /// protection no [`Protection`] variant describes, such as inaccessible pages.
+    pub fn protection_of(&self, offset: usize, len: usize) -> Result<Protection> {
+        match offset.checked_add(len) {
+            Some(end) if len > 0 && end <= self.len() => self.inner.protection_of(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is empty or out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Returns whether each page of the memory map is currently resident in memory.
+    ///
+    /// The result has an entry for every page the memory map overlaps, including partially
+    /// overlapped pages at either end. This corresponds to `mincore` on unix.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` on Windows, or when the underlying
+    /// system call fails.
+    pub fn residency(&self) -> Result<Vec<bool>> {
+        self.inner.mincore()
+    }
+
+    /// Returns the fraction of the pages of the memory map which are currently resident in
+    /// memory, between 0.0 and 1.0.
+    ///
+    /// This is cheaper than counting the entries of [`residency()`](Self::residency()), and
+    /// returns 0.0 for an empty memory map.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` on Windows, or when the underlying
+    /// system call fails.
+    pub fn resident_percentage(&self) -> Result<f64> {
+        if self.is_empty() {
+            return Ok(0.0);
+        }
+        self.inner.resident_fraction()
+    }
+
+    /// Faults in every page of the memory map by reading a byte from each.
+    ///
+    /// Unlike [`MmapOptions::populate()`], this works on every platform and on existing memory
+    /// maps. Pages are only read, so copy-on-write pages are not copied.
+    pub fn prefault(&self) {
+        prefault(self);
+    }
+
+    /// Faults in every page overlapping `offset..offset + len` of the memory map by reading a byte
+    /// from each.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map.
+    pub fn prefault_range(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => {
+                prefault(&self[offset..end]);
+                Ok(())
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Copies all of `data` into the memory map, starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput`, leaving the memory map unmodified, if
+    /// `data` does not fit in the memory map at `offset`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(16)?;
+    /// mmap.write_at(4, b"hello")?;
+    ///
+    /// let mut buf = [0; 5];
+    /// assert_eq!(5, mmap.read_at(4, &mut buf)?);
+    /// assert_eq!(b"hello", &buf);
+    ///
+    /// assert!(mmap.write_at(12, b"hello").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        match offset.checked_add(data.len()) {
+            Some(end) if end <= self.len() => {
+                self[offset..end].copy_from_slice(data);
+                Ok(())
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "write is out of bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Copies as much of `src` as fits into the memory map starting at `offset`, returning the
+    /// number of bytes copied.
+    ///
+    /// This is a best-effort variant of [`write_at()`](MmapMut::write_at()) which never fails or
+    /// panics: `src` is truncated at the end of the memory map, and nothing is copied if `offset`
+    /// is at or past the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(8)?;
+    /// assert_eq!(5, mmap.copy_from_slice_at(0, b"hello"));
+    /// assert_eq!(3, mmap.copy_from_slice_at(5, b"world"));
+    /// assert_eq!(b"hellowor", &mmap[..]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn copy_from_slice_at(&mut self, offset: usize, src: &[u8]) -> usize {
+        let len = src.len().min(self.len().saturating_sub(offset));
+        if len > 0 {
+            self[offset..offset + len].copy_from_slice(&src[..len]);
+        }
+        len
+    }
+
+    /// Sets every byte of the memory map to `value`.
+    ///
+    /// This writes to, and so faults in, every page of the memory map. To release the pages of an
+    /// anonymous memory map instead of zeroing them, see [`Advice::DontNeed`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(16)?;
+    /// mmap.fill(0xff);
+    /// assert!(mmap.iter().all(|&b| b == 0xff));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fill(&mut self, value: u8) {
+        // `[u8]::fill` lowers to `memset`.
+        self[..].fill(value);
+    }
+
+    /// Sets the bytes of `offset..offset + len` of the memory map to `value`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or `offset + len` overflows.
+    pub fn fill_range(&mut self, offset: usize, len: usize, value: u8) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => {
+                self[offset..end].fill(value);
+                Ok(())
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Copies bytes starting at `offset` in the memory map into `buf`, returning the number of
+    /// bytes copied.
+    ///
+    /// Fewer than `buf.len()` bytes are copied if the end of the memory map is reached; an
+    /// `offset` equal to the length of the map copies nothing.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `offset` is past the end of the
+    /// memory map.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        read_at(self, offset, buf)
+    }
+
+    /// Uses `mlock` to lock the whole memory map into RAM.
+    ///
+    /// On unix, when locking fails with `EPERM` or `ENOMEM`, the error message includes the
+    /// `RLIMIT_MEMLOCK` soft limit, and the original OS error is available as its source.
+    ///
+    /// On Windows this uses `VirtualLock`, and returns an error of kind `OutOfMemory` if the
+    /// memory map does not fit into the working set quota of the process.
+    ///
+    /// Note this requires privileged access.
+    pub fn mlock(&mut self) -> Result<()> {
+        self.inner.mlock()?;
+
+        Ok(())
+    }
+
+    /// Uses `munlock` to unlock the whole memory map.
+    ///
+    /// On Windows this uses `VirtualUnlock`.
+    ///
+    /// Note this requires privileged access.
+    pub fn munlock(&mut self) -> Result<()> {
+        self.inner.munlock()?;
+
+        Ok(())
+    }
+
+    /// Locks the pages of the memory map into RAM as they are faulted in, rather than faulting
+    /// in every page up front like [`mlock()`](Self::mlock()).
+    ///
+    /// This uses `mlock2` with the `MLOCK_ONFAULT` flag, and is only supported on Linux 4.4 and
+    /// later.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` on other platforms and on older
+    /// kernels, in which case callers may fall back to `mlock()`, or when the underlying system
+    /// call fails.
+    pub fn mlock_on_fault(&mut self) -> Result<()> {
+        self.inner.mlock_on_fault()
+    }
+
+    /// Locks the pages overlapping `offset..offset + len` of the memory map into RAM.
+    ///
+    /// Whole pages are locked, so e.g. locking bytes 100..200 locks the page containing them.
+    /// This uses `mlock` on unix and `VirtualLock` on Windows.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails.
+    pub fn mlock_range(&mut self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.mlock_range(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Unlocks the pages overlapping `offset..offset + len` of the memory map.
+    ///
+    /// This uses `munlock` on unix and `VirtualUnlock` on Windows.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, or when the underlying system call fails.
+    pub fn munlock_range(&mut self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.munlock_range(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Advises the operating system how the memory map will be accessed.
+    ///
+    /// This corresponds to `madvise` on unix, and to `PrefetchVirtualMemory` for
+    /// [`Advice::WillNeed`] on Windows. Advice which the platform does not support results in an
+    /// error rather than being silently ignored.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `Unsupported` if the advice is not available on this
+    /// platform, or when the underlying system call fails.
+    pub fn advise(&self, advice: Advice) -> Result<()> {
+        advise(&self.inner, advice)
+    }
+
+    /// Advises the operating system how the range of the memory map will be accessed.
+    ///
+    /// The advice applies to every page overlapping `offset..offset + len`, so it may extend to
+    /// bytes just outside of the range. This is useful e.g. to prefetch only the pages about to be
+    /// read with [`Advice::WillNeed`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of the bounds of
+    /// the memory map, of kind `Unsupported` if the advice is not available on this platform, or
+    /// when the underlying system call fails.
+    pub fn advise_range(&self, advice: Advice, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => advise_range(&self.inner, advice, offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Resets any access pattern advice given for the memory map back to [`Advice::Normal`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails.
+    pub fn reset_advice(&self) -> Result<()> {
+        self.advise(Advice::Normal)
+    }
+
+    /// Applies `advice` to the memory map for the duration of the closure `f`.
+    ///
+    /// The advice is reset to [`Advice::Normal`] once `f` returns, or if it panics, so long-lived
+    /// memory maps are not left with stale hints.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error if `advice` could not be applied, in which case `f` is not
+    /// run.
+    pub fn with_advice<F, R>(&self, advice: Advice, f: F) -> Result<R>
+    where
+        F: FnOnce(&MmapMut) -> R,
+    {
+        self.advise(advice)?;
+        let _guard = AdviceGuard(&self.inner);
+        Ok(f(self))
+    }
+
+    /// Removes execute permission from the memory map, keeping the rest of the current protection
+    /// of each range, e.g. ranges made read-only with [`protect_range()`](MmapMut::protect_range)
+    /// stay read-only.
+    ///
+    /// This is a defense-in-depth measure for data maps: it is a no-op for memory maps which are
+    /// not executable.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when the underlying system call fails. Reading the current
+    /// protections is only supported on Linux and Windows; other platforms return an error of kind
+    /// [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported).
+    pub fn ensure_no_exec(&self) -> Result<()> {
+        self.inner.ensure_no_exec(true)
+    }
+
+    /// Synchronously collapses the pages in the range into transparent huge pages.
+    ///
+    /// The offset and length must be in the bounds of the memory map. Only the huge page aligned
+    /// regions lying entirely within the range are collapsed.
+    ///
+    /// Unlike waiting for `khugepaged` to collapse pages in the background, this method returns
+    /// once the range is backed by huge pages, which makes it suitable for latency sensitive
+    /// workloads which populate a large anonymous map up front.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of bounds, and an
+    /// error of kind `Unsupported` on platforms other than Linux, or on Linux kernels before 6.1.
+    pub fn collapse_huge(&self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => self.inner.collapse_huge(offset, len),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is out of the bounds of the memory map",
+            )),
+        }
+    }
+
+    /// Resizes the memory map to `new_len` bytes.
+    ///
+    /// On Linux this uses `mremap`, which grows or shrinks the memory map in place where possible
+    /// and moves it otherwise. Other platforms, and shared anonymous maps on Linux, create a new
+    /// memory map of `new_len` bytes instead, copy the contents of anonymous and copy-on-write maps
+    /// over, and unmap the old one; shared file-backed maps keep their contents through the file.
+    /// Either way **pointers into the memory map are invalidated**. The contents up to the smaller
+    /// of the old and new lengths are preserved. If resizing fails, the memory map is left
+    /// unchanged.
+    ///
+    /// Anonymous memory maps shared with child processes are no longer shared once the memory
+    /// map is recreated. On Windows the new memory map is not locked, even if the old one was.
+    ///
+    /// For file-backed memory maps, the file must first be extended with [`File::set_len()`] to
+    /// cover the new length: accessing pages of the memory map past the end of the file results in
+    /// a `SIGBUS` on unix, and creating the new memory map fails on Windows.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if `new_len` is zero, or when the
+    /// underlying system calls fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mapr::MmapMut;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut mmap = MmapMut::map_anon(4096)?;
+    /// mmap[0] = 1;
+    /// mmap.resize(8192)?;
+    /// assert_eq!(8192, mmap.len());
+    /// assert_eq!(1, mmap[0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resize(&mut self, new_len: usize) -> Result<()> {
+        if new_len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map must have a non-zero length",
+            ));
+        }
+        self.inner.resize(new_len)
+    }
+
+    /// Extends `file` to `new_len` bytes with [`File::set_len()`] and resizes the memory map to
+    /// cover it up to the new end of the file.
+    ///
+    /// `file` must be the file this memory map was created from, and the memory map must extend
+    /// to the end of it for the new bytes to be mapped. As with [`resize()`](#method.resize),
+    /// **pointers into the memory map are invalidated**.
+    ///
+    /// If resizing the memory map fails, the file is truncated back to its old length and the
+    /// memory map is left unchanged. If restoring the length fails as well, the returned error
+    /// describes both failures, and the file is left at `new_len` bytes.
+    ///
+    /// The file length and the memory map are not updated atomically with respect to other
+    /// threads or processes. Synchronizing with anything else which maps or resizes the same file
+    /// is the responsibility of the caller.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the memory map is anonymous, or if
+    /// `new_len` would not extend the file past the end of the memory map, and any error returned
+    /// by extending the file or resizing the memory map.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use mapr::MmapMut;
+    /// use std::fs::OpenOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = OpenOptions::new()
+    ///     .read(true)
+    ///     .write(true)
+    ///     .create(true)
+    ///     .open("log.bin")?;
+    /// file.set_len(4096)?;
+    ///
+    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    /// mmap.grow_file_and_remap(&file, 8192)?;
+    /// mmap[4096..].copy_from_slice(&[1; 4096]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn grow_file_and_remap(&mut self, file: &File, new_len: u64) -> Result<()> {
+        if self.inner.file().is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map is not backed by a file",
+            ));
+        }
+        let offset = self.inner.offset();
+        if new_len <= offset + self.len() as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "new file length must extend past the end of the memory map",
+            ));
+        }
+        let map_len = new_len - offset;
+        if map_len > (usize::MAX as u64) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "memory map length overflows usize",
+            ));
+        }
+
+        let old_len = file.metadata()?.len();
+        file.set_len(new_len)?;
+        if let Err(err) = self.resize(map_len as usize) {
+            if let Err(restore_err) = file.set_len(old_len) {
+                return Err(Error::new(
+                    err.kind(),
+                    format!(
+                        "failed to resize memory map ({}), and failed to truncate the file back \
+                         to {} bytes ({}); the file is left at {} bytes",
+                        err, old_len, restore_err, new_len
+                    ),
+                ));
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Deallocates the backing store of the pages in the range, which read back as zeros
+    /// afterwards.
+    ///
+    /// The range must be in the bounds of the memory map, and both `offset` and `len` must be
+    /// page aligned relative to the underlying file, since only whole pages can be removed.
+    ///
+    /// Only supported on Linux for shared memory maps of files on file systems which support
+    /// punching holes, such as tmpfs, hugetlbfs and ext4, where this corresponds to
+    /// `MADV_REMOVE`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error of kind `InvalidInput` if the range is out of bounds or not
+    /// page aligned, and an error of kind `Unsupported` on platforms other than Linux, or if the
+    /// file system does not support punching holes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use mapr::{page_size, MmapMut};
+    /// use std::fs::OpenOptions;
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let file = OpenOptions::new()
+    ///     .read(true)
+    ///     .write(true)
+    ///     .create(true)
+    ///     .open("/dev/shm/punch_hole")?;
+    /// file.set_len(2 * page_size() as u64)?;
+    ///
+    /// let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+    /// mmap.iter_mut().for_each(|b| *b = 0xAB);
+    /// mmap.punch_hole(0, page_size())?;
+    /// assert!(mmap[..page_size()].iter().all(|&b| b == 0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn punch_hole(&mut self, offset: usize, len: usize) -> Result<()> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len() => {}
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "range is out of the bounds of the memory map",
+                ))
+            }
+        }
+        let page_size = page_size();
+        if (self.inner.ptr() as usize + offset) % page_size != 0 || len % page_size != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "range is not page aligned",
+            ));
+        }
+        self.inner.punch_hole(offset, len)
+    }
+}
+
+impl Deref for MmapMut {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.inner.ptr(), self.inner.len()) }
+    }
+}
+
+impl DerefMut for MmapMut {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        #[cfg(all(debug_assertions, feature = "log"))]
+        warn_shared_deref_mut(self.inner.kind());
+        unsafe { slice::from_raw_parts_mut(self.inner.mut_ptr(), self.inner.len()) }
+    }
+}
+
+impl AsRef<[u8]> for MmapMut {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl AsMut<[u8]> for MmapMut {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.deref_mut()
+    }
+}
+
+impl fmt::Debug for MmapMut {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("MmapMut")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Formats the memory map with a human-readable length and its base pointer, for logging.
+///
+/// Use the `Debug` implementation for the exact length.
+///
+/// # Example
+///
+/// ```
+/// use mapr::MmapMut;
+/// #
+/// # fn main() -> std::io::Result<()> {
+/// let mmap = MmapMut::map_anon(4 << 20)?;
+/// assert!(mmap.to_string().starts_with("MmapMut(4.0 MiB @ 0x"));
+/// # Ok(())
+/// # }
+/// ```
+impl fmt::Display for MmapMut {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "MmapMut({} @ {:p})",
+            HumanSize(self.len()),
+            self.as_ptr()
+        )
+    }
+}
+
+/// Memory maps compare equal if their contents are equal, regardless of where they are mapped.
+impl PartialEq for MmapMut {
+    fn eq(&self, other: &MmapMut) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl PartialEq<Mmap> for MmapMut {
+    fn eq(&self, other: &Mmap) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl Eq for MmapMut {}
+
+/// Hashes the contents of the memory map, which takes time linear in its length, and faults in
+/// every page.
+impl Hash for MmapMut {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self[..].hash(state)
+    }
+}
+
+/// The protection of a file-backed memory map, configured with [`MmapOptions::protection()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Protection {
+    /// A read-only memory map, as created by [`MmapOptions::map()`].
+    Read,
+    /// A writable memory map, as created by [`MmapOptions::map_mut()`].
+    ReadWrite,
+    /// A readable and executable memory map, as created by [`MmapOptions::map_exec()`].
+    ReadExec,
+    /// A copy-on-write memory map, as created by [`MmapOptions::map_copy()`].
+    CopyOnWrite,
+}
+
+/// A memory map which is either read-only or writable, as created by [`MmapOptions::map_with()`].
+#[derive(Debug)]
+pub enum AnyMmap {
+    /// A read-only memory map.
+    ReadOnly(Mmap),
+    /// A writable memory map.
+    ReadWrite(MmapMut),
+}
+
+impl Deref for AnyMmap {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match *self {
+            AnyMmap::ReadOnly(ref mmap) => mmap,
+            AnyMmap::ReadWrite(ref mmap) => mmap,
+        }
+    }
+}
+
+impl AsRef<[u8]> for AnyMmap {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+/// Returns the size of a page of memory in bytes.
+///
+/// Memory maps are created, flushed, locked and advised in whole pages, so this is the alignment
+/// used for such ranges, e.g. by [`MmapMut::flush_range()`]. On unix this is
+/// `sysconf(_SC_PAGESIZE)`, and on Windows the `dwPageSize` of `GetSystemInfo`. Note that on
+/// Windows, file offsets of memory maps are aligned to the larger allocation granularity instead.
+///
+/// # Example
+///
+/// ```
+/// let page_size = mapr::page_size();
+/// assert!(page_size.is_power_of_two());
+/// ```
+pub fn page_size() -> usize {
+    #[cfg(unix)]
+    return unix::page_size();
+    #[cfg(windows)]
+    return windows::page_size();
+}
+
+/// The smallest huge page size shift accepted by [`MmapOptions::huge_shift()`], for 1 MiB pages.
+const MIN_HUGE_SHIFT: u8 = 20;
+
+/// The largest huge page size shift accepted by [`MmapOptions::huge_shift()`], for 16 GiB pages.
+const MAX_HUGE_SHIFT: u8 = 34;
+
+/// The size of the huge pages backing a memory map, given with [`MmapOptions::huge()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HugePageSize {
+    /// 2 MiB huge pages.
+    Size2MB,
+    /// 1 GiB huge pages.
+    Size1GB,
+}
+
+impl HugePageSize {
+    /// Returns the size of the huge pages in bytes.
+    pub fn bytes(self) -> usize {
+        1 << self.shift()
+    }
+
+    /// Returns the log2 of the size of the huge pages.
+    fn shift(self) -> u8 {
+        match self {
+            HugePageSize::Size2MB => 21,
+            HugePageSize::Size1GB => 30,
+        }
+    }
+}
+
+/// Access pattern advice for a memory map, given with [`Mmap::advise()`] or
+/// [`MmapMut::advise()`].
+///
+/// On unix these correspond to the `MADV_*` flags accepted by `madvise`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Advice {
+    /// No special treatment. This is the default for every memory map.
+    Normal,
+    /// Pages are expected to be accessed in random order, so read-ahead is less useful.
+    Random,
+    /// Pages are expected to be accessed in sequential order, so aggressive read-ahead is useful
+    /// and pages may be freed soon after they are accessed.
+    Sequential,
+    /// Pages are expected to be accessed soon, so they may be read ahead of time.
+    WillNeed,
+    /// Pages are not expected to be accessed soon, so their memory may be released, e.g. after
+    /// streaming through them.
+    ///
+    /// On unix this corresponds to `MADV_DONTNEED`, which takes effect immediately. For shared
+    /// memory maps it only drops the pages from memory; later accesses read back the contents of
+    /// the file, or of the shared anonymous memory. For private memory maps, including
+    /// copy-on-write maps created with [`MmapOptions::map_copy()`], **modifications are
+    /// discarded**: later accesses read back the underlying file, or zeros for anonymous memory.
+    DontNeed,
+    /// Pages are no longer needed, so their memory may be reclaimed lazily, e.g. when a memory
+    /// allocator releases them for later reuse.
+    ///
+    /// Only supported on Linux, macOS and FreeBSD, where this corresponds to `MADV_FREE`, and on
+    /// Linux only for private anonymous memory maps. Unlike [`Advice::DontNeed`] the pages are
+    /// only freed under memory pressure, so **until the kernel reclaims them, reads may return
+    /// either the old data or zeros**. Writing to a page cancels the advice for that page.
+    Free,
+    /// Pages should be backed by transparent huge pages where possible.
+    ///
+    /// Only supported on Linux, where this corresponds to `MADV_HUGEPAGE`. Unlike
+    /// [`MmapOptions::huge()`], this opts an existing memory map into huge pages without
+    /// reserving them up front.
+    HugePage,
+    /// Pages should not be backed by transparent huge pages.
+    ///
+    /// Only supported on Linux, where this corresponds to `MADV_NOHUGEPAGE`.
+    NoHugePage,
+    /// Pages are not expected to be accessed soon, so they should be reclaimed before other
+    /// memory when the system is under memory pressure. Their contents are preserved.
+    ///
+    /// Only supported on Linux 5.4 and later, where this corresponds to `MADV_COLD`.
+    Cold,
+    /// Pages should be reclaimed right away: they are written back to their file or to swap and
+    /// evicted from the resident set. Their contents are preserved and read back when accessed.
+    ///
+    /// Only supported on Linux 5.4 and later, where this corresponds to `MADV_PAGEOUT`.
+    PageOut,
+    /// Pages should not be inherited by child processes, so a forked child has nothing mapped at
+    /// their address.
+    ///
+    /// Only supported on Linux, where this corresponds to `MADV_DONTFORK`.
+    DontFork,
+    /// Pages should read as zeros in child processes, so that their contents are not inherited
+    /// across `fork`.
+    ///
+    /// Only supported on Linux 4.14 and later, where this corresponds to `MADV_WIPEONFORK`, and
+    /// only for private anonymous memory maps.
+    WipeOnFork,
+}
+
+/// Gives `advice` for the whole memory map.
+fn advise(inner: &MmapInner, advice: Advice) -> Result<()> {
+    advise_range(inner, advice, 0, inner.len())
+}
+
+/// Gives `advice` for `offset..offset + len` of the memory map, which must be in bounds.
+#[cfg(unix)]
+fn advise_range(inner: &MmapInner, advice: Advice, offset: usize, len: usize) -> Result<()> {
+    let flag = match advice {
+        Advice::Normal => libc::MADV_NORMAL,
+        Advice::Random => libc::MADV_RANDOM,
+        Advice::Sequential => libc::MADV_SEQUENTIAL,
+        Advice::WillNeed => libc::MADV_WILLNEED,
+        Advice::DontNeed => libc::MADV_DONTNEED,
+        #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+        Advice::Free => libc::MADV_FREE,
+        #[cfg(target_os = "linux")]
+        Advice::HugePage => libc::MADV_HUGEPAGE,
+        #[cfg(target_os = "linux")]
+        Advice::NoHugePage => libc::MADV_NOHUGEPAGE,
+        #[cfg(target_os = "linux")]
+        Advice::Cold => libc::MADV_COLD,
+        #[cfg(target_os = "linux")]
+        Advice::PageOut => libc::MADV_PAGEOUT,
+        #[cfg(target_os = "linux")]
+        Advice::DontFork => libc::MADV_DONTFORK,
+        #[cfg(target_os = "linux")]
+        Advice::WipeOnFork => libc::MADV_WIPEONFORK,
+        #[allow(unreachable_patterns)]
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "memory map advice is not supported on this platform",
+            ))
+        }
+    };
+    inner
+        .advise_range(flag, offset, len)
+        .map_err(|err| match advice {
+            // Kernels before 5.4 reject the unknown advice with EINVAL.
+            #[cfg(target_os = "linux")]
+            Advice::Cold | Advice::PageOut if err.raw_os_error() == Some(libc::EINVAL) => {
+                Error::new(
+                    ErrorKind::Unsupported,
+                    "MADV_COLD and MADV_PAGEOUT are not supported by this kernel",
+                )
+            }
+            _ => err,
+        })
+}
+
+/// Gives `advice` for `offset..offset + len` of the memory map, which must be in bounds.
+#[cfg(windows)]
+fn advise_range(inner: &MmapInner, advice: Advice, offset: usize, len: usize) -> Result<()> {
+    match advice {
+        // Windows has no persistent access pattern hints, so there is nothing to reset.
+        Advice::Normal => Ok(()),
+        Advice::WillNeed => inner.prefetch(offset, len),
+        _ => Err(Error::new(
+            ErrorKind::Unsupported,
+            "memory map advice is not supported on this platform",
+        )),
+    }
+}
+
+/// Resets the advice of a memory map to [`Advice::Normal`] when dropped.
+struct AdviceGuard<'a>(&'a MmapInner);
+
+impl<'a> Drop for AdviceGuard<'a> {
+    fn drop(&mut self) {
+        let _ = advise(self.0, Advice::Normal);
+    }
+}
+
+/// How the memory of a map is backed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MapKind {
+    /// Anonymous memory, not backed by a file.
+    Anonymous,
+    /// A file, with writes carried through to it.
+    SharedFile,
+    /// A file, with writes private to the map.
+    PrivateFile,
+}
+
+/// Logs a warning the first time a shared file-backed map is mutably dereferenced.
+#[cfg(all(debug_assertions, feature = "log"))]
+fn warn_shared_deref_mut(kind: MapKind) {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if kind == MapKind::SharedFile && !WARNED.swap(true, Ordering::Relaxed) {
+        log::warn!(
+            "mutably dereferencing a shared file-backed memory map; this is undefined behavior if \
+             the file is concurrently modified, see `MmapMut::as_shared_atomic_slice`"
+        );
+    }
+}
+
+/// Formats a byte count in binary units, with one decimal place above a KiB.
+struct HumanSize(usize);
+
+impl fmt::Display for HumanSize {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+        if self.0 < 1024 {
+            return write!(fmt, "{} B", self.0);
+        }
+        let mut size = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        // Sizes which would round up to 1024.0 are shown in the next unit.
+        while size >= 1023.95 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        write!(fmt, "{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Reads a byte from every page of `mem` until done or `deadline` passes.
+fn populate_with_deadline(mem: &[u8], deadline: Instant) -> bool {
+    /// The number of pages touched between checks of the clock.
+    const PAGES_PER_CHECK: usize = 64;
+
+    let page_size = page_size();
+    for (i, offset) in (0..mem.len()).step_by(page_size).enumerate() {
+        if i % PAGES_PER_CHECK == 0 && Instant::now() >= deadline {
+            return false;
+        }
+        unsafe { ptr::read_volatile(mem.as_ptr().add(offset)) };
+    }
+    true
+}
+
+/// Reads a byte from every page overlapping `mem`.
+fn prefault(mem: &[u8]) {
+    if mem.is_empty() {
+        return;
+    }
+    let page_size = page_size();
+    let start = mem.as_ptr() as usize;
+    for page in (start - start % page_size..start + mem.len()).step_by(page_size) {
+        unsafe { ptr::read_volatile(page.max(start) as *const u8) };
+    }
+}
+
+/// Returns the number of `T`s in the `len` bytes at `ptr`, checking that they can be viewed as a
+/// slice of `T`.
+fn typed_len<T>(ptr: *const u8, len: usize) -> Result<usize> {
+    let size = mem::size_of::<T>();
+    if size == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "can not view a memory map as a slice of a zero-sized type",
+        ));
+    }
+    if ptr as usize % mem::align_of::<T>() != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "memory map is not aligned for the type",
+        ));
+    }
+    if len % size != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "memory map length is not a multiple of the size of the type",
+        ));
+    }
+    Ok(len / size)
+}
+
+/// Resolves `range` to the start and end offsets into a memory map of `len` bytes.
+fn resolve_range<R: RangeBounds<usize>>(range: R, len: usize) -> Result<(usize, usize)> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => Some(start),
+        Bound::Excluded(&start) => start.checked_add(1),
+        Bound::Unbounded => Some(0),
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.checked_add(1),
+        Bound::Excluded(&end) => Some(end),
+        Bound::Unbounded => Some(len),
+    };
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end && end <= len => Ok((start, end)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "range is out of the bounds of the memory map",
+        )),
+    }
+}
+
+/// Copies bytes from `src` starting at `offset` into `buf`, returning the number copied.
+fn read_at(src: &[u8], offset: usize, buf: &mut [u8]) -> Result<usize> {
+    if offset > src.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "read is out of bounds of the memory map",
+        ));
+    }
+    let len = buf.len().min(src.len() - offset);
+    buf[..len].copy_from_slice(&src[offset..offset + len]);
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{File, OpenOptions};
+    use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+    use std::ops::Bound;
+    #[cfg(unix)]
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    #[cfg(windows)]
+    use std::os::windows::fs::OpenOptionsExt;
+    #[cfg(target_os = "linux")]
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    #[cfg(unix)]
+    use std::{mem, ptr};
+
+    #[cfg(windows)]
+    use winapi::um::winnt::GENERIC_ALL;
+
+    use super::{
+        page_size, Advice, AnyMmap, GrowableMmap, HugePageSize, HumanSize, MapKind, Mmap,
+        MmapArena, MmapMut, MmapOptions, MmapRegion, Protection, SecureMmapMut,
+    };
+
+    #[test]
+    fn map_file() {
+        let expected_len = 128;
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        file.set_len(expected_len as u64).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        let len = mmap.len();
+        assert_eq!(expected_len, len);
+
+        let zeros = vec![0; len];
+        let incr: Vec<u8> = (0..len as u8).collect();
+
+        // check that the mmap is empty
+        assert_eq!(&zeros[..], &mmap[..]);
+
+        // write values into the mmap
+        (&mut mmap[..]).write_all(&incr[..]).unwrap();
+
+        // read values back
+        assert_eq!(&incr[..], &mmap[..]);
+    }
+
+    /// Checks that a 0-length file will not be mapped.
+    #[test]
+    fn map_empty_file() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let mmap = unsafe { Mmap::map(&file) };
+        assert!(mmap.is_err());
+    }
+
+    #[test]
+    fn map_anon() {
+        let expected_len = 128;
+        let mut mmap = MmapMut::map_anon(expected_len).unwrap();
+        let len = mmap.len();
+        assert_eq!(expected_len, len);
+
+        let zeros = vec![0; len];
+        let incr: Vec<u8> = (0..len as u8).collect();
+
+        // check that the mmap is empty
+        assert_eq!(&zeros[..], &mmap[..]);
+
+        // write values into the mmap
+        (&mut mmap[..]).write_all(&incr[..]).unwrap();
+
+        // read values back
+        assert_eq!(&incr[..], &mmap[..]);
+    }
+
+    #[test]
+    fn map_anon_zero_len() {
+        assert!(MmapOptions::new().map_anon().is_err())
+    }
+
+    #[test]
+    fn file_write() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let write = b"abc123";
+        let mut read = [0u8; 6];
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        (&mut mmap[..]).write_all(write).unwrap();
+        mmap.flush().unwrap();
+
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(write, &read);
+    }
+
+    #[test]
+    fn page_size_power_of_two() {
+        let page_size = crate::page_size();
+        assert!(page_size > 0);
+        assert!(page_size.is_power_of_two());
+        #[cfg(unix)]
+        assert_eq!(
+            unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize,
+            page_size
+        );
+    }
+
+    #[test]
+    fn flush_repeated() {
+        let page_size = page_size();
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4 * page_size as u64).unwrap();
+
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(3)
+                .len(3 * page_size)
+                .map_mut(&file)
+                .unwrap()
+        };
+        for i in 0..1000 {
+            let offset = (i * 37) % (mmap.len() - 1);
+            mmap[offset] = i as u8;
+            mmap.flush_range(offset, 1).unwrap();
+            mmap.flush_async_range(offset, mmap.len() - offset).unwrap();
+            assert_eq!(page_size, crate::page_size());
+        }
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(&mmap[..], &contents[3..3 + 3 * page_size]);
+    }
+
+    #[test]
+    fn address_hint() {
+        let page_size = page_size();
+
+        // A range that was just unmapped is a plausible free address.
+        let hint = MmapMut::map_anon(4 * page_size).unwrap().as_ptr() as usize;
+        let mmap = MmapOptions::new()
+            .address(hint)
+            .len(page_size)
+            .map_anon()
+            .unwrap();
+        assert_eq!(page_size, mmap.len());
+
+        let mmap = MmapOptions::new()
+            .address(0)
+            .len(page_size)
+            .map_anon()
+            .unwrap();
+        assert_eq!(page_size, mmap.len());
+
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let contents: Vec<u8> = (0..2 * page_size).map(|i| i as u8).collect();
+        file.write_all(&contents).unwrap();
+
+        let mmap = unsafe {
+            MmapOptions::new()
+                .address(hint + page_size)
+                .offset(page_size as u64 + 3)
+                .len(page_size - 3)
+                .map(&file)
+                .unwrap()
+        };
+        assert_eq!(&contents[page_size + 3..], &mmap[..]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn fixed_noreplace() {
+        let page_size = page_size();
+        let mmap = MmapMut::map_anon(2 * page_size).unwrap();
+        let addr = mmap.as_ptr() as usize;
+
+        let err = MmapOptions::new()
+            .address(addr + page_size)
+            .fixed_noreplace()
+            .len(page_size)
+            .map_anon()
+            .unwrap_err();
+        assert_eq!(ErrorKind::AlreadyExists, err.kind());
+        assert_eq!(Some(libc::EEXIST), err.raw_os_error());
+
+        drop(mmap);
+        let mmap = MmapOptions::new()
+            .address(addr)
+            .fixed_noreplace()
+            .len(page_size)
+            .map_anon()
+            .unwrap();
+        assert_eq!(addr, mmap.as_ptr() as usize);
+
+        let err = MmapOptions::new()
+            .fixed_noreplace()
+            .len(page_size)
+            .map_anon()
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    fn low_32bit_exec() {
+        let mmap = MmapOptions::new()
+            .low_32bit()
+            .len(page_size())
+            .map_anon()
+            .unwrap();
+        assert!((mmap.as_ptr() as usize) < 0x1_0000_0000);
+        jit(mmap);
+
+        let mut mmap = MmapOptions::new()
+            .low_32bit()
+            .len(page_size())
+            .map_anon()
+            .unwrap();
+        mmap.resize(16 * page_size()).unwrap();
+        assert!((mmap.as_ptr() as usize) < 0x1_0000_0000);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn grows_down() {
+        let err = MmapOptions::new()
+            .grows_down()
+            .len(page_size())
+            .map_anon()
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let mut mmap = match MmapOptions::new()
+            .stack()
+            .grows_down()
+            .private()
+            .len(4 * page_size())
+            .map_anon()
+        {
+            Ok(mmap) => mmap,
+            // Downward growing mappings may be restricted, e.g. by a sandbox.
+            Err(err) => {
+                assert!(
+                    matches!(err.raw_os_error(), Some(libc::EINVAL) | Some(libc::EPERM)),
+                    "{}",
+                    err
+                );
+                return;
+            }
+        };
+        assert!(vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "gd"));
+        mmap.fill(0xab);
+        assert!(mmap.iter().all(|&b| b == 0xab));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn guard_pages() {
+        let page_size = page_size();
+        for &len in &[None, Some(0)] {
+            let mut options = MmapOptions::new();
+            options.guard_pages(2);
+            if let Some(len) = len {
+                options.len(len);
+            }
+            let err = options.map_anon().unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+
+        let mut mmap = MmapOptions::new()
+            .stack()
+            .guard_pages(2)
+            .len(4 * page_size)
+            .map_anon()
+            .unwrap();
+        assert_eq!(4 * page_size, mmap.len());
+        mmap.fill(0xab);
+        assert!(mmap.iter().all(|&b| b == 0xab));
+
+        // Accessing the byte below the memory map kills the child with SIGSEGV.
+        let guard = unsafe { mmap.as_ptr().sub(1) };
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+            0 => unsafe {
+                std::ptr::read_volatile(guard);
+                libc::_exit(0)
+            },
+            pid => {
+                let mut status = 0;
+                assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+                assert!(libc::WIFSIGNALED(status));
+                assert_eq!(libc::SIGSEGV, libc::WTERMSIG(status));
+            }
+        }
+
+        let ptr = mmap.as_ptr() as usize;
+        drop(mmap);
+        let mmap = MmapOptions::new()
+            .address(ptr - 2 * page_size)
+            .fixed_noreplace()
+            .len(6 * page_size)
+            .map_anon()
+            .unwrap();
+        assert_eq!(ptr - 2 * page_size, mmap.as_ptr() as usize);
+    }
+
+    #[test]
+    fn len_for() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        std::fs::write(&path, vec![1; 1000]).unwrap();
+        let file = File::open(&path).unwrap();
+
+        let mut options = MmapOptions::new();
+        options.offset(123);
+        let mmap = unsafe { options.map(&file).unwrap() };
+        assert_eq!(877, options.len_for(&file).unwrap());
+        assert_eq!(mmap.len(), options.len_for(&file).unwrap());
+        assert_eq!(0, options.offset(1000).len_for(&file).unwrap());
+
+        let err = options.offset(1001).len_for(&file).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(10, options.len(10).len_for(&file).unwrap());
+    }
+
+    #[test]
+    fn flush_range() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+        let write = b"abc123";
+
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(2)
+                .len(write.len())
+                .map_mut(&file)
+                .unwrap()
+        };
+        (&mut mmap[..]).write_all(write).unwrap();
+        mmap.flush_range(0, write.len()).unwrap();
+        mmap.flush_range(write.len(), 0).unwrap();
+    }
+
+    #[test]
+    fn flush_range_out_of_bounds() {
+        let mmap = MmapMut::map_anon(page_size()).unwrap();
+        for &(offset, len) in &[
+            (0, page_size() + 1),
+            (page_size(), 1),
+            (page_size() + 1, 0),
+            (1, usize::MAX),
+            (usize::MAX, 2),
+        ] {
+            let err = mmap.flush_range(offset, len).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+            let err = mmap.flush_async_range(offset, len).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+    }
+
+    #[test]
+    fn flush_file() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut first = unsafe { MmapOptions::new().len(64).map_mut(&file).unwrap() };
+        let mut second = unsafe { MmapOptions::new().offset(64).map_mut(&file).unwrap() };
+        (&mut first[..]).write_all(b"abc123").unwrap();
+        (&mut second[..]).write_all(b"xyz789").unwrap();
+        first.flush_file().unwrap();
+
+        let mut read = [0u8; 6];
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(b"abc123", &read);
+        file.seek(SeekFrom::Start(64)).unwrap();
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(b"xyz789", &read);
+
+        let mmap = MmapMut::map_anon(128).unwrap();
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            mmap.flush_file().unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn durability_barrier() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(1024).unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap.copy_from_slice(&[7; 1024]);
+
+        let mut barrier = mmap.durability_barrier();
+        barrier.mark_dirty(600, 100).unwrap();
+        barrier.mark_dirty(0, 10).unwrap();
+        barrier.mark_dirty(300, 50).unwrap();
+        // Adjacent to the first range.
+        barrier.mark_dirty(10, 20).unwrap();
+        // Bridges the last two ranges.
+        barrier.mark_dirty(340, 270).unwrap();
+        barrier.mark_dirty(5, 0).unwrap();
+        assert_eq!(&[0..30, 300..700], barrier.dirty_ranges());
+
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            barrier.mark_dirty(1000, 25).unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            barrier.mark_dirty(1, usize::MAX).unwrap_err().kind()
+        );
+        barrier.commit().unwrap();
+
+        let mut buf = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut buf).unwrap();
+        assert_eq!(&[7; 1024][..], &buf[..]);
+
+        let anon = MmapMut::map_anon(128).unwrap();
+        let err = anon.durability_barrier().commit().unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn flush_reopen() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap[..6].copy_from_slice(b"abc123");
+        mmap.flush().unwrap();
+        mmap.flush_range(0, 6).unwrap();
+        drop(mmap);
+        drop(file);
+
+        let mut buf = [0; 6];
+        File::open(&path).unwrap().read_exact(&mut buf).unwrap();
+        assert_eq!(b"abc123", &buf);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn map_raw_handle() {
+        use std::os::windows::ffi::OsStrExt;
+        use winapi::um::fileapi::{CreateFileW, OPEN_EXISTING};
+        use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+        use winapi::um::winnt::{FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, GENERIC_READ};
+
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        std::fs::write(&path, b"abc123").unwrap();
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let handle = unsafe {
+            CreateFileW(
+                wide.as_ptr(),
+                GENERIC_READ,
+                FILE_SHARE_READ,
+                std::ptr::null_mut(),
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                std::ptr::null_mut(),
+            )
+        };
+        assert_ne!(INVALID_HANDLE_VALUE, handle);
+
+        let err = unsafe { MmapOptions::new().map_raw_handle(handle as _).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let mmap = unsafe {
+            MmapOptions::new()
+                .len(6)
+                .map_raw_handle(handle as _)
+                .unwrap()
+        };
+        // The memory map retains its own duplicate of the handle.
+        assert_ne!(0, unsafe { CloseHandle(handle) });
+        assert_eq!(b"abc123", &mmap[..]);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn flush_copy_read_only() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        std::fs::write(&path, b"abc123").unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+        mmap[..3].copy_from_slice(b"xyz");
+        mmap.flush().unwrap();
+
+        assert_eq!(b"abc123", &std::fs::read(&path).unwrap()[..]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn flush_with_signals() {
+        use std::sync::atomic::AtomicBool;
+
+        extern "C" fn handle_signal(_: libc::c_int) {}
+
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
+            .unwrap();
+        file.set_len(16 * 4096).unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+
+        // Without SA_RESTART, blocking system calls fail with EINTR when the signal arrives.
+        let mut old: libc::sigaction = unsafe { mem::zeroed() };
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as usize;
+            assert_eq!(0, libc::sigaction(libc::SIGURG, &action, &mut old));
+        }
+
+        let done = Arc::new(AtomicBool::new(false));
+        let target = unsafe { libc::pthread_self() };
+        let signaller = {
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    unsafe { libc::pthread_kill(target, libc::SIGURG) };
+                    thread::yield_now();
+                }
+            })
+        };
+
+        let len = mmap.len();
+        let result = (0..1000).try_for_each(|i| {
+            mmap[(i * 97) % len] = i as u8;
+            mmap.flush()
+        });
+        done.store(true, Ordering::Relaxed);
+        signaller.join().unwrap();
+        unsafe { libc::sigaction(libc::SIGURG, &old, ptr::null_mut()) };
+        result.unwrap();
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "freebsd"))]
+    fn map_raw_fd() {
+        let fd = unsafe { libc::memfd_create(b"raw\0".as_ptr() as *const libc::c_char, 0) };
+        assert!(fd >= 0, "{}", std::io::Error::last_os_error());
+        assert_eq!(0, unsafe { libc::ftruncate(fd, 8192) });
+
+        let err = unsafe { MmapOptions::new().map_raw_fd(fd).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let mut mmap = unsafe { MmapOptions::new().len(8192).map_raw_fd_mut(fd).unwrap() };
+        mmap[..5].copy_from_slice(b"hello");
+        let view = unsafe { MmapOptions::new().len(5).map_raw_fd(fd).unwrap() };
+        assert_eq!(b"hello", &view[..]);
+        drop((mmap, view));
+
+        // The descriptor is still open after the memory maps are dropped.
+        assert_eq!(0, unsafe { libc::close(fd) });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn map_device_file() {
+        let file = File::open("/dev/zero").unwrap();
+        let err = unsafe { Mmap::map(&file).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert!(err.to_string().contains("len()"));
+
+        let mmap = unsafe { MmapOptions::new().len(4096).map(&file).unwrap() };
+        assert!(mmap.iter().all(|&b| b == 0));
+
+        // The offset is not checked against the reported length of 0.
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(1 << 20)
+                .len(4096)
+                .map_copy(&file)
+                .unwrap()
+        };
+        assert!(mmap.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn freeze() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut mmap = MmapMut::map_anon(4 * page_size()).unwrap();
+        for (i, b) in mmap.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        let mmap = mmap.freeze().unwrap();
+        assert_send_sync(&mmap);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let mmap = mmap.clone();
+                thread::spawn(move || mmap.iter().enumerate().all(|(i, &b)| b == i as u8))
+            })
+            .collect();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn try_clone_cow() {
+        let contents = std::fs::read("README.md").unwrap();
+        let mmap = unsafe { Mmap::open("README.md").unwrap() };
+
+        let mut clone = mmap.try_clone_cow().unwrap();
+        assert_eq!(&contents[..], &clone[..]);
+        clone[..6].copy_from_slice(b"cloned");
+        assert_eq!(b"cloned", &clone[..6]);
+
+        assert_eq!(&contents[..], &mmap[..]);
+        assert_eq!(contents, std::fs::read("README.md").unwrap());
+
+        let anon = MmapMut::map_anon(16).unwrap().make_read_only().unwrap();
+        let mut clone = anon.try_clone_cow().unwrap();
+        clone[0] = 1;
+        assert_eq!(0, anon[0]);
+    }
+
+    #[test]
+    fn fill() {
+        let mut mmap = MmapMut::map_anon(3 * page_size() + 5).unwrap();
+        mmap.fill(0xab);
+        assert!(mmap.iter().all(|&b| b == 0xab));
+
+        mmap.fill_range(page_size() - 1, 2, 0).unwrap();
+        assert_eq!([0xab, 0, 0, 0xab], mmap[page_size() - 2..page_size() + 2]);
+
+        let len = mmap.len();
+        mmap.fill_range(len, 0, 0).unwrap();
+        for &(offset, len) in &[(len, 1), (1, len), (usize::MAX, 2)] {
+            let err = mmap.fill_range(offset, len, 0).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+        assert_eq!(len - 2, mmap.iter().filter(|&&b| b == 0xab).count());
+    }
+
+    #[test]
+    fn secure_wipe() {
+        let mut key = SecureMmapMut::new(64).unwrap();
+        key.copy_from_slice(&[0x42; 64]);
+        key.wipe();
+        assert!(key.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn secure_zero_on_drop() {
+        // A second memory map of the same memfd observes the memory after the first is unmapped.
+        let (mmap, file) = MmapOptions::new().map_memfd("secure", page_size()).unwrap();
+        let view = unsafe { MmapMut::map_mut(&file).unwrap() };
+
+        let mut key = SecureMmapMut::from(mmap);
+        key.fill(0x42);
+        assert!(view.iter().all(|&b| b == 0x42));
+
+        drop(key);
+        assert!(view.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn flush_data_all() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+
+        mmap[..5].copy_from_slice(b"hello");
+        mmap.flush_data().unwrap();
+        mmap[5..10].copy_from_slice(b"world");
+        mmap.flush_all().unwrap();
+
+        let mut buf = [0; 10];
+        File::open(&path).unwrap().read_exact(&mut buf).unwrap();
+        assert_eq!(b"helloworld", &buf);
+
+        let anon = MmapMut::map_anon(128).unwrap();
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            anon.flush_data().unwrap_err().kind()
+        );
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            anon.flush_all().unwrap_err().kind()
+        );
+    }
+
+    /// Checks that concurrently modified memory maps are all written back.
+    #[test]
+    fn flush_dirty() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let page_size = page_size();
+        let paths: Vec<_> = (0..2)
+            .map(|i| tempdir.path().join(format!("mmap{}", i)))
+            .collect();
+
+        let threads: Vec<_> = paths
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, path)| {
+                thread::spawn(move || {
+                    let file = OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(path)
+                        .unwrap();
+                    file.set_len(16 * page_size as u64).unwrap();
+                    let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+                    for round in 0..16 {
+                        mmap[round * page_size] = i as u8 + 1;
+                        assert_eq!(16, mmap.flush_dirty().unwrap());
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        for (i, path) in paths.iter().enumerate() {
+            let buf = std::fs::read(path).unwrap();
+            for page in 0..16 {
+                assert_eq!(i as u8 + 1, buf[page * page_size]);
+            }
+        }
+    }
+
+    #[test]
+    fn map_copy() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let nulls = b"\0\0\0\0\0\0";
+        let write = b"abc123";
+        let mut read = [0u8; 6];
+
+        let mut mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+
+        (&mut mmap[..]).write_all(write).unwrap();
+        mmap.flush().unwrap();
+
+        // The mmap contains the write
+        (&mmap[..]).read_exact(&mut read).unwrap();
+        assert_eq!(write, &read);
+
+        // The file does not contain the write
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(nulls, &read);
+
+        // another mmap does not contain the write
+        let mmap2 = unsafe { MmapOptions::new().map(&file).unwrap() };
+        (&mmap2[..]).read_exact(&mut read).unwrap();
+        assert_eq!(nulls, &read);
+    }
+
+    #[test]
+    fn map_offset() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+
+        let offset = u32::MAX as u64 + 2;
+        let len = 5432;
+        file.set_len(offset + len as u64).unwrap();
+
+        // Check inferred length mmap.
+        let mmap = unsafe { MmapOptions::new().offset(offset).map_mut(&file).unwrap() };
+        assert_eq!(len, mmap.len());
+
+        // Check explicit length mmap.
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(offset)
+                .len(len)
+                .map_mut(&file)
+                .unwrap()
+        };
+        assert_eq!(len, mmap.len());
+
+        let zeros = vec![0; len];
+        let incr: Vec<_> = (0..len).map(|i| i as u8).collect();
+
+        // check that the mmap is empty
+        assert_eq!(&zeros[..], &mmap[..]);
+
+        // write values into the mmap
+        (&mut mmap[..]).write_all(&incr[..]).unwrap();
+
+        // read values back
+        assert_eq!(&incr[..], &mmap[..]);
+    }
+
+    /// Checks that the bytes between EOF and the end of the final page read as zero, and that
+    /// writes to them are not carried through to the file.
+    #[test]
+    #[cfg(unix)]
+    fn map_past_eof() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[0x11; 100]).unwrap();
+
+        // An inferred length stops at EOF, even when the offset is unaligned.
+        let mmap = unsafe { MmapOptions::new().offset(10).map(&file).unwrap() };
+        assert_eq!(90, mmap.len());
+        drop(mmap);
+
+        // An explicit length may extend into the zero-filled tail of the final page.
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(10)
+                .len(190)
+                .map_mut(&file)
+                .unwrap()
+        };
+        assert_eq!(&[0x11; 90][..], &mmap[..90]);
+        assert_eq!(&[0; 100][..], &mmap[90..]);
+
+        (&mut mmap[90..]).write_all(&[0x22; 100]).unwrap();
+        mmap.flush().unwrap();
+
+        // The file is neither extended nor modified by the writes past EOF.
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(&[0x11; 100][..], &contents[..]);
+    }
+
+    #[test]
+    fn map_offset_past_eof() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let err = unsafe { MmapOptions::new().offset(256).map(&file).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn open() {
+        let mmap = unsafe { Mmap::open("README.md").unwrap() };
+        let contents = std::fs::read("README.md").unwrap();
+        assert_eq!(&contents[..], &mmap[..]);
+
+        let err = unsafe { Mmap::open("does/not/exist").unwrap_err() };
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        File::create(&path).unwrap();
+        let err = unsafe { Mmap::open(&path).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        let err = unsafe { MmapMut::open(&path).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        std::fs::write(&path, b"hello").unwrap();
+        let mut mmap = unsafe { MmapMut::open(&path).unwrap() };
+        mmap[..1].copy_from_slice(b"j");
+        mmap.flush().unwrap();
+        assert_eq!(b"jello", &std::fs::read(&path).unwrap()[..]);
+
+        // The file is opened for writing.
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+        if OpenOptions::new().write(true).open(&path).is_err() {
+            let err = unsafe { MmapMut::open(&path).unwrap_err() };
+            assert_eq!(ErrorKind::PermissionDenied, err.kind());
+        }
+    }
+
+    #[test]
+    fn create() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("scratch");
+
+        let err = unsafe { MmapMut::create(&path, 0).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert!(!path.exists());
+
+        let mut mmap = unsafe { MmapMut::create(&path, 4096).unwrap() };
+        assert_eq!(4096, mmap.len());
+        for (i, b) in mmap.iter_mut().enumerate() {
+            *b = (i % 251) as u8;
+        }
+        mmap.flush().unwrap();
+        drop(mmap);
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(4096, contents.len());
+        assert!(contents
+            .iter()
+            .enumerate()
+            .all(|(i, &b)| b == (i % 251) as u8));
+
+        // Existing files are resized, keeping their contents.
+        let mmap = unsafe { MmapMut::create(&path, 8192).unwrap() };
+        assert_eq!(&contents[..], &mmap[..4096]);
+        assert!(mmap[4096..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn mirrored_anon() {
+        let page_size = page_size();
+        for &len in &[0, 100, page_size + 1] {
+            let err = MmapMut::mirrored_anon(len).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+
+        let len = 2 * page_size;
+        let mut ring = MmapMut::mirrored_anon(len).unwrap();
+        assert_eq!(len, ring.len());
+        let wrapped = unsafe { std::slice::from_raw_parts_mut(ring.as_mut_ptr(), 2 * len) };
+
+        // Write across the wrap boundary, and read it back contiguously.
+        wrapped[len - 3..len + 4].copy_from_slice(b"abc1234");
+        assert_eq!(b"abc", &ring[len - 3..]);
+        assert_eq!(b"1234", &ring[..4]);
+
+        ring[4..8].copy_from_slice(b"wxyz");
+        let wrapped = unsafe { std::slice::from_raw_parts(ring.as_ptr(), 2 * len) };
+        assert_eq!(b"abc1234wxyz", &wrapped[len - 3..len + 8]);
+        assert_eq!(&wrapped[..len], &wrapped[len..]);
+    }
+
+    #[test]
+    fn grow_file_and_remap() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4096).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap.copy_from_slice(&[1; 4096]);
+        for i in 2..5u8 {
+            let old_len = mmap.len();
+            mmap.grow_file_and_remap(&file, old_len as u64 + 4096)
+                .unwrap();
+            assert_eq!(old_len + 4096, mmap.len());
+            assert_eq!(old_len as u64 + 4096, file.metadata().unwrap().len());
+            mmap[old_len..].copy_from_slice(&[i; 4096]);
+        }
+        mmap.flush().unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(4 * 4096, contents.len());
+        for (i, chunk) in contents.chunks(4096).enumerate() {
+            assert!(chunk.iter().all(|&b| b == i as u8 + 1));
+        }
+
+        let err = mmap.grow_file_and_remap(&file, 4096).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(4 * 4096, mmap.len());
+
+        let mut anon = MmapMut::map_anon(4096).unwrap();
+        let err = anon.grow_file_and_remap(&file, 8 * 4096).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(4 * 4096, file.metadata().unwrap().len());
+    }
+
+    #[test]
+    fn resize_anon() {
+        for &private in &[false, true] {
+            let mut options = MmapOptions::new();
+            if private {
+                options.private();
+            }
+            let mut mmap = options.len(4096).map_anon().unwrap();
+            for (i, b) in mmap.iter_mut().enumerate() {
+                *b = (i % 251) as u8;
+            }
+
+            mmap.resize(3 * 4096 + 7).unwrap();
+            assert_eq!(3 * 4096 + 7, mmap.len());
+            assert!(mmap[..4096]
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| b == (i % 251) as u8));
+            assert!(mmap[4096..].iter().all(|&b| b == 0));
+            let last = mmap.len() - 1;
+            mmap[last] = 0xff;
+
+            mmap.resize(1000).unwrap();
+            assert_eq!(1000, mmap.len());
+            assert!(mmap.iter().enumerate().all(|(i, &b)| b == (i % 251) as u8));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn resize() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4096).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap[..5].copy_from_slice(b"hello");
+        let err = mmap.resize(0).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(4096, mmap.len());
+
+        file.set_len(8192).unwrap();
+        mmap.resize(8192).unwrap();
+        assert_eq!(8192, mmap.len());
+        assert_eq!(b"hello", &mmap[..5]);
+        mmap[4096..4101].copy_from_slice(b"world");
+        mmap.flush().unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(b"world", &contents[4096..4101]);
+
+        mmap.resize(100).unwrap();
+        assert_eq!(100, mmap.len());
+        assert_eq!(b"hello", &mmap[..5]);
+
+        // Offsets which are not page aligned are preserved.
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(4099)
+                .len(2)
+                .map_mut(&file)
+                .unwrap()
+        };
+        mmap.resize(5).unwrap();
+        assert_eq!(b"ld\0\0\0", &mmap[..]);
+        mmap[..].copy_from_slice(b"LDWOR");
+        mmap.flush().unwrap();
+        assert_eq!(b"worLDWOR", &std::fs::read(&path).unwrap()[4096..4104]);
+    }
+
+    #[test]
+    fn file_dropped_after_map() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        drop(file);
+
+        mmap[..5].copy_from_slice(b"hello");
+        mmap.flush().unwrap();
+        // Syncing the file relies on the duplicated descriptor.
+        mmap.flush_all().unwrap();
+        mmap.flush_data().unwrap();
+        drop(mmap);
+
+        let mmap = unsafe { Mmap::map(&File::open(&path).unwrap()).unwrap() };
+        assert_eq!(b"hello", &mmap[..5]);
+    }
+
+    #[test]
+    fn into_backing_file() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        drop(file);
+        (&mut mmap[..]).write_all(b"abc123").unwrap();
+        mmap.flush().unwrap();
+
+        let mut file = mmap.into_backing_file().expect("file-backed map");
+        file.set_len(3).unwrap();
+        assert_eq!(3, file.metadata().unwrap().len());
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(b"abc", &contents[..]);
+
+        let mmap = MmapMut::map_anon(128).unwrap();
+        assert!(mmap.into_backing_file().is_none());
+    }
+
+    #[test]
+    fn make_private() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut read = [0u8; 6];
+
+        let mut mmap = unsafe { MmapOptions::new().offset(3).map_mut(&file).unwrap() };
+        (&mut mmap[..]).write_all(b"abc123").unwrap();
+        mmap.flush().unwrap();
+
+        mmap.make_private().unwrap();
+        assert_eq!(b"abc123", &mmap[..6]);
+
+        (&mut mmap[..]).write_all(b"xyz789").unwrap();
+        mmap.flush().unwrap();
+        assert_eq!(b"xyz789", &mmap[..6]);
+
+        // The file only contains the write made before the conversion.
+        file.seek(SeekFrom::Start(3)).unwrap();
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(b"abc123", &read);
+
+        // Converting again is a no-op which keeps the private modifications.
+        mmap.make_private().unwrap();
+        assert_eq!(b"xyz789", &mmap[..6]);
+
+        let mut mmap = MmapMut::map_anon(128).unwrap();
+        assert!(mmap.make_private().is_err());
+    }
+
+    #[test]
+    fn lock_noreserve() {
+        let err = MmapOptions::new()
+            .len(128)
+            .lock()
+            .noreserve()
+            .map_anon()
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn map_copy_rejects_shared_options() {
+        let file = File::open("README.md").unwrap();
+
+        let err = unsafe {
+            MmapOptions::new()
+                .sync_on_write()
+                .map_copy(&file)
+                .unwrap_err()
+        };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let err = unsafe {
+            MmapOptions::new()
+                .lock()
+                .noreserve()
+                .map_copy(&file)
+                .unwrap_err()
+        };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn lock_windows() {
+        use winapi::shared::winerror::ERROR_PRIVILEGE_NOT_HELD;
+
+        let mut mmap = match MmapOptions::new().len(4096).lock().map_anon() {
+            Ok(mmap) => mmap,
+            // Locking may exceed the working set quota, or not be permitted.
+            Err(ref err) if err.kind() == ErrorKind::OutOfMemory => return,
+            Err(ref err) if err.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD as i32) => return,
+            Err(err) => panic!("{}", err),
+        };
+        mmap[0] = 1;
+        mmap.munlock().unwrap();
+        mmap.mlock().unwrap();
+        mmap.munlock().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn mlock_range() {
+        let page_size = page_size();
+        let mut mmap = MmapMut::map_anon(4 * page_size).unwrap();
+        let err = mmap.mlock_range(page_size, 4 * page_size).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        match mmap.mlock_range(100, 100) {
+            Ok(()) => {}
+            // Locking may not be permitted in this environment.
+            Err(ref err) if err.raw_os_error() == Some(libc::EAGAIN) => return,
+            Err(ref err) if err.kind() == ErrorKind::PermissionDenied => return,
+            Err(err) => panic!("{}", err),
+        }
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            format!("{} kB", page_size / 1024),
+            smaps_field(mmap.as_ptr(), "Locked")
+        );
+
+        // A range straddling a page boundary locks both pages.
+        mmap.mlock_range(2 * page_size - 1, 2).unwrap();
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            format!("{} kB", 3 * page_size / 1024),
+            smaps_field(mmap.as_ptr(), "Locked")
+        );
+
+        mmap.munlock_range(0, 4 * page_size).unwrap();
+        #[cfg(target_os = "linux")]
+        assert_eq!("0 kB", smaps_field(mmap.as_ptr(), "Locked"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mlock_on_fault() {
+        let mut mmap = MmapMut::map_anon(16 * page_size()).unwrap();
+        match mmap.mlock_on_fault() {
+            Ok(()) => {}
+            // Locking may not be permitted in this environment, or supported by the kernel.
+            Err(ref err) if err.raw_os_error() == Some(libc::EAGAIN) => return,
+            Err(ref err) if err.kind() == ErrorKind::PermissionDenied => return,
+            Err(ref err) if err.kind() == ErrorKind::Unsupported => return,
+            Err(err) => panic!("{}", err),
+        }
+        // Pages are only locked once they are faulted in.
+        assert_eq!("0 kB", smaps_field(mmap.as_ptr(), "Locked"));
+        mmap[0] = 1;
+        assert_eq!(
+            format!("{} kB", page_size() / 1024),
+            smaps_field(mmap.as_ptr(), "Locked")
+        );
+        assert!(vm_flags(mmap.as_ptr()).contains(&"lf".to_string()));
+        mmap.munlock().unwrap();
+    }
+
+    /// Changes `RLIMIT_MEMLOCK` for the whole process, which makes other tests locking memory
+    /// fail while it runs, so run it on its own with `cargo test -- --ignored mlock_limit`.
+    #[test]
+    #[ignore]
+    #[cfg(unix)]
+    fn mlock_limit() {
+        const LIMIT: libc::rlim_t = 64 * 1024;
+
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(0, unsafe {
+            libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit)
+        });
+        if limit.rlim_max != libc::RLIM_INFINITY && limit.rlim_max < LIMIT {
+            return;
+        }
+
+        let mut mmap = MmapMut::map_anon(1 << 20).unwrap();
+        let tiny = libc::rlimit {
+            rlim_cur: LIMIT,
+            rlim_max: limit.rlim_max,
+        };
+        assert_eq!(0, unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &tiny) });
+        let result = mmap.mlock();
+        assert_eq!(0, unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) });
+
+        // Privileged processes are not subject to the limit.
+        if let Err(err) = result {
+            assert!(
+                err.to_string()
+                    .contains("would exceed RLIMIT_MEMLOCK (64.0 KiB)"),
+                "{}",
+                err
+            );
+            let source = std::error::Error::source(&err)
+                .and_then(|source| source.downcast_ref::<std::io::Error>())
+                .unwrap();
+            assert!(matches!(
+                source.raw_os_error(),
+                Some(libc::EPERM) | Some(libc::ENOMEM)
+            ));
+        }
+    }
+
+    /// Checks that `lock()` faults in every page of the map at creation time.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn lock_prefaults() {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        let len = 16 * page_size;
+
+        let mmap = match MmapOptions::new().len(len).lock().map_anon() {
+            Ok(mmap) => mmap,
+            // Locking may not be permitted in this environment.
+            Err(ref err) if err.raw_os_error() == Some(libc::EAGAIN) => return,
+            Err(ref err) if err.raw_os_error() == Some(libc::EPERM) => return,
+            Err(err) => panic!("{}", err),
+        };
+
+        let mut residency = vec![0u8; len / page_size];
+        let result = unsafe {
+            libc::mincore(
+                mmap.as_ptr() as *mut libc::c_void,
+                len,
+                residency.as_mut_ptr(),
+            )
+        };
+        assert_eq!(0, result);
+        assert!(residency.iter().all(|page| page & 1 == 1));
+    }
+
+    #[test]
+    fn write_at_read_at() {
+        let mut mmap = MmapMut::map_anon(16).unwrap();
+        mmap.write_at(10, b"abc123").unwrap();
+        assert_eq!(b"abc123", &mmap[10..]);
+
+        let mut read = [0u8; 6];
+        assert_eq!(6, mmap.read_at(10, &mut read).unwrap());
+        assert_eq!(b"abc123", &read);
+
+        // Reads are truncated at the end of the map.
+        let mut read = [0u8; 6];
+        assert_eq!(3, mmap.read_at(13, &mut read).unwrap());
+        assert_eq!(b"123", &read[..3]);
+        assert_eq!(0, mmap.read_at(16, &mut read).unwrap());
+
+        let mmap = mmap.make_read_only().unwrap();
+        assert_eq!(6, mmap.read_at(10, &mut read).unwrap());
+        assert_eq!(b"abc123", &read);
+    }
+
+    #[test]
+    fn write_at_read_at_out_of_bounds() {
+        let mut mmap = MmapMut::map_anon(16).unwrap();
+        let mut read = [0u8; 6];
+
+        let err = mmap.write_at(11, b"abc123").unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        let err = mmap.write_at(usize::MAX, b"abc123").unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(&[0; 16], &mmap[..]);
+
+        let err = mmap.read_at(17, &mut read).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn auto_protection() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut options = MmapOptions::new();
+        match unsafe { options.map_with(&file).unwrap() } {
+            AnyMmap::ReadOnly(_) => (),
+            AnyMmap::ReadWrite(_) => panic!("map without auto protection is writable"),
+        }
+
+        options.auto_protection();
+        match unsafe { options.map_with(&file).unwrap() } {
+            AnyMmap::ReadWrite(mut mmap) => (&mut mmap[..]).write_all(b"abc123").unwrap(),
+            AnyMmap::ReadOnly(_) => panic!("map of a writable file is read-only"),
+        }
+
+        let file = OpenOptions::new().read(true).open(&path).unwrap();
+        match unsafe { options.map_with(&file).unwrap() } {
+            AnyMmap::ReadOnly(mmap) => assert_eq!(b"abc123", &mmap[..6]),
+            AnyMmap::ReadWrite(_) => panic!("map of a read-only file is writable"),
+        }
+    }
+
+    #[test]
+    fn protection() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut options = OpenOptions::new();
+        #[cfg(windows)]
+        options.access_mode(GENERIC_ALL);
+        let file = options
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut options = MmapOptions::new();
+        options.auto_protection();
+
+        match unsafe { options.protection(Protection::ReadWrite).map_with(&file) }.unwrap() {
+            AnyMmap::ReadWrite(mut mmap) => mmap[..6].copy_from_slice(b"abc123"),
+            AnyMmap::ReadOnly(_) => panic!("read-write map is read-only"),
+        }
+        match unsafe { options.protection(Protection::Read).map_with(&file) }.unwrap() {
+            AnyMmap::ReadOnly(mmap) => assert_eq!(b"abc123", &mmap[..6]),
+            AnyMmap::ReadWrite(_) => panic!("read map is writable"),
+        }
+        match unsafe { options.protection(Protection::ReadExec).map_with(&file) }.unwrap() {
+            AnyMmap::ReadOnly(mmap) => assert_eq!(b"abc123", &mmap[..6]),
+            AnyMmap::ReadWrite(_) => panic!("read-exec map is writable"),
+        }
+        match unsafe { options.protection(Protection::CopyOnWrite).map_with(&file) }.unwrap() {
+            AnyMmap::ReadWrite(mut mmap) => {
+                mmap[..6].copy_from_slice(b"xyz789");
+                assert_eq!(b"xyz789", &mmap[..6]);
+            }
+            AnyMmap::ReadOnly(_) => panic!("copy-on-write map is read-only"),
+        }
+        assert_eq!(b"abc123", &std::fs::read(&path).unwrap()[..6]);
+
+        let err = unsafe {
+            MmapOptions::new()
+                .deny_exec()
+                .protection(Protection::ReadExec)
+                .map_with(&file)
+        }
+        .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    /// Returns the value of the `/proc/self/smaps` field for the mapping containing `ptr`.
+    #[cfg(target_os = "linux")]
+    fn smaps_field(ptr: *const u8, field: &str) -> String {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let addr = ptr as usize;
+        let mut in_mapping = false;
+        for line in smaps.lines() {
+            let range = line.split_whitespace().next().unwrap_or("");
+            if let Some((start, end)) = range.split_once('-') {
+                if let (Ok(start), Ok(end)) = (
+                    usize::from_str_radix(start, 16),
+                    usize::from_str_radix(end, 16),
+                ) {
+                    in_mapping = start <= addr && addr < end;
+                    continue;
+                }
+            }
+            if in_mapping {
+                if let Some(value) = line
+                    .strip_prefix(field)
+                    .and_then(|rest| rest.strip_prefix(':'))
+                {
+                    return value.trim().to_string();
+                }
+            }
+        }
+        panic!("no {} found for {:?}", field, ptr);
+    }
+
+    /// Returns the `VmFlags` reported in `/proc/self/smaps` for the mapping containing `ptr`.
+    #[cfg(target_os = "linux")]
+    fn vm_flags(ptr: *const u8) -> Vec<String> {
+        smaps_field(ptr, "VmFlags")
+            .split_whitespace()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn advise() {
+        let mmap = unsafe { Mmap::map(&File::open("README.md").unwrap()).unwrap() };
+        #[cfg(unix)]
+        {
+            mmap.advise(Advice::Sequential).unwrap();
+            mmap.advise(Advice::Random).unwrap();
+        }
+        #[cfg(windows)]
+        {
+            let err = mmap.advise(Advice::Sequential).unwrap_err();
+            assert_eq!(ErrorKind::Unsupported, err.kind());
+        }
+        mmap.advise(Advice::WillNeed).unwrap();
+        mmap.reset_advice().unwrap();
+    }
+
+    /// Returns whether each page of `mem` is resident in memory.
+    #[cfg(target_os = "linux")]
+    fn resident_pages(mem: &[u8]) -> Vec<bool> {
+        let page_size = page_size();
+        let mut residency = vec![0u8; mem.len().div_ceil(page_size)];
+        let result = unsafe {
+            libc::mincore(
+                mem.as_ptr() as *mut libc::c_void,
+                mem.len(),
+                residency.as_mut_ptr(),
+            )
+        };
+        assert_eq!(0, result);
+        residency.iter().map(|page| page & 1 == 1).collect()
+    }
+
+    #[test]
+    fn advise_range() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.write_all(&vec![0xAB; 4 << 20]).unwrap();
+        file.sync_all().unwrap();
+
+        // Drop the file from the page cache, where the file system allows it.
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        let err = mmap.advise_range(Advice::WillNeed, 4 << 20, 1).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        let err = mmap
+            .advise_range(Advice::WillNeed, 1, usize::MAX)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        #[cfg(target_os = "linux")]
+        let evicted = !resident_pages(&mmap).iter().any(|&resident| resident);
+
+        mmap.advise_range(Advice::WillNeed, 1_000_000, 4096)
+            .unwrap();
+
+        #[cfg(target_os = "linux")]
+        {
+            let page_size = page_size();
+            let range = 1_000_000 / page_size..(1_004_096 - 1) / page_size + 1;
+            // Read ahead happens in the background, so wait for it to complete.
+            for _ in 0..100 {
+                if resident_pages(&mmap)[range.clone()].iter().all(|&r| r) {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            let resident = resident_pages(&mmap);
+            assert!(resident[range.clone()].iter().all(|&r| r));
+            if evicted {
+                assert!(!resident[0]);
+                assert!(!resident[resident.len() - 1]);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn advise_dont_need() {
+        let page_size = page_size();
+        let mut mmap = MmapOptions::new()
+            .len(4 * page_size)
+            .private()
+            .map_anon()
+            .unwrap();
+        mmap.iter_mut().for_each(|b| *b = 0xAB);
+
+        // Only the pages overlapping the range are released.
+        mmap.advise_range(Advice::DontNeed, page_size + 1, page_size - 2)
+            .unwrap();
+        assert!(mmap[..page_size].iter().all(|&b| b == 0xAB));
+        assert!(mmap[page_size..2 * page_size].iter().all(|&b| b == 0));
+        assert!(mmap[2 * page_size..].iter().all(|&b| b == 0xAB));
+
+        // Copy-on-write maps lose their modifications.
+        let file = File::open("README.md").unwrap();
+        let mut mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+        let original = mmap[..8].to_vec();
+        mmap[..8].copy_from_slice(b"modified");
+        mmap.advise_range(Advice::DontNeed, 0, 8).unwrap();
+        assert_eq!(&original[..], &mmap[..8]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn advise_huge_page() {
+        let mmap = MmapOptions::new()
+            .len(4 << 20)
+            .private()
+            .map_anon()
+            .unwrap();
+        assert_eq!(0, mmap.as_ptr() as usize % page_size());
+
+        mmap.advise(Advice::HugePage).unwrap();
+        assert!(vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "hg"));
+        mmap.advise(Advice::NoHugePage).unwrap();
+        assert!(vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "nh"));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    fn advise_free() {
+        let mut mmap = MmapOptions::new()
+            .len(page_size())
+            .private()
+            .map_anon()
+            .unwrap();
+        mmap[0] = 1;
+        mmap.advise(Advice::Free).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn advise_cold_page_out() {
+        let len = 16 << 20;
+        let mut mmap = MmapOptions::new().len(len).private().map_anon().unwrap();
+        mmap.iter_mut().step_by(page_size()).for_each(|b| *b = 0xAB);
+
+        for &advice in &[Advice::Cold, Advice::PageOut] {
+            match mmap.advise(advice) {
+                Ok(()) => {}
+                Err(ref err) if err.kind() == std::io::ErrorKind::Unsupported => return,
+                Err(err) => panic!("{:?}: {}", advice, err),
+            }
+        }
+        // Paged out contents are preserved.
+        assert!(mmap.iter().step_by(page_size()).all(|&b| b == 0xAB));
+
+        // Clean file-backed pages are evicted from the resident set.
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
+            .unwrap();
+        file.set_len(len as u64).unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap.iter_mut().step_by(page_size()).for_each(|b| *b = 0xAB);
+        mmap.flush().unwrap();
+        mmap.advise(Advice::PageOut).unwrap();
+        assert!(!resident_pages(&mmap).iter().all(|&resident| resident));
+        assert!(mmap.iter().step_by(page_size()).all(|&b| b == 0xAB));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn punch_hole() {
+        let dir = std::path::Path::new("/dev/shm");
+        if !dir.is_dir() {
+            return;
+        }
+        let tempdir = tempdir::TempDir::new_in(dir, "mmap").unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
+            .unwrap();
+        let page_size = page_size();
+        file.set_len(3 * page_size as u64).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap.iter_mut().for_each(|b| *b = 0xAB);
+
+        for &(offset, len) in &[(1, page_size), (page_size, 1), (page_size, 3 * page_size)] {
+            let err = mmap.punch_hole(offset, len).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+
+        mmap.punch_hole(page_size, page_size).unwrap();
+        assert!(mmap[..page_size].iter().all(|&b| b == 0xAB));
+        assert!(mmap[page_size..2 * page_size].iter().all(|&b| b == 0));
+        assert!(mmap[2 * page_size..].iter().all(|&b| b == 0xAB));
+
+        // The hole is punched in the file, not just in the memory map.
+        let mut contents = Vec::new();
+        (&file).read_to_end(&mut contents).unwrap();
+        assert_eq!(&mmap[..], &contents[..]);
+    }
+
+    #[test]
+    fn map_populate() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.write_all(&[0xAB; 1 << 20]).unwrap();
+
+        let mmap = unsafe { MmapOptions::new().populate().map(&file).unwrap() };
+        assert!(mmap.iter().all(|&b| b == 0xAB));
+        #[cfg(target_os = "linux")]
+        assert!(resident_pages(&mmap).iter().all(|&resident| resident));
+
+        let mmap = MmapOptions::new()
+            .len(1 << 20)
+            .populate()
+            .map_anon()
+            .unwrap();
+        #[cfg(target_os = "linux")]
+        assert!(resident_pages(&mmap).iter().all(|&resident| resident));
+        assert!(mmap.iter().all(|&b| b == 0));
+    }
+
+    /// Forks, runs `child` in the child process and returns whether it returned `true`.
+    ///
+    /// `child` must only call async-signal-safe functions.
+    #[cfg(target_os = "linux")]
+    fn fork_check<F: FnOnce() -> bool>(child: F) -> bool {
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+            0 => unsafe { libc::_exit(if child() { 0 } else { 1 }) },
+            pid => {
+                let mut status = 0;
+                assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+                libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn map_anon_shared_fork() {
+        let mmap = MmapOptions::new()
+            .private()
+            .map_anon_shared(page_size())
+            .unwrap();
+        assert_eq!(page_size(), mmap.len());
+
+        let ptr = mmap.as_ptr() as *mut u8;
+        assert!(fork_check(|| {
+            unsafe { ptr.add(100).write_volatile(0xCD) };
+            true
+        }));
+        assert_eq!(0xCD, mmap[100]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn from_raw() {
+        let page_size = page_size();
+        let raw = |prot| unsafe {
+            let ptr = libc::mmap(
+                ptr::null_mut(),
+                2 * page_size,
+                prot,
+                libc::MAP_PRIVATE | libc::MAP_ANON,
+                -1,
+                0,
+            );
+            assert_ne!(libc::MAP_FAILED, ptr);
+            ptr
+        };
+        let mapped = |ptr: *mut libc::c_void| {
+            // mincore fails with ENOMEM for unmapped addresses.
+            let mut residency = [0; 2];
+            unsafe { libc::mincore(ptr, 2 * page_size, residency.as_mut_ptr() as *mut _) == 0 }
+        };
+
+        let ptr = raw(libc::PROT_READ | libc::PROT_WRITE);
+        let mut mmap = unsafe { MmapMut::from_raw(ptr as *mut u8, 2 * page_size) };
+        assert_eq!(2 * page_size, mmap.len());
+        assert_eq!(ptr as *const u8, mmap.as_ptr());
+        mmap[page_size..page_size + 6].copy_from_slice(b"abc123");
+        let mmap = mmap.make_read_only().unwrap();
+        assert_eq!(b"abc123", &mmap[page_size..page_size + 6]);
+        assert!(mapped(ptr));
+        drop(mmap);
+        assert!(!mapped(ptr));
+
+        let ptr = raw(libc::PROT_READ);
+        let mmap = unsafe { Mmap::from_raw(ptr as *mut u8, 2 * page_size) };
+        assert!(mmap.iter().all(|&b| b == 0));
+        mmap.unmap().unwrap();
+        assert!(!mapped(ptr));
+    }
+
+    #[test]
+    fn eq_hash() {
+        use std::collections::HashSet;
+
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let map_file = |name: &str, contents: &[u8]| {
+            let path = tempdir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            unsafe { Mmap::open(path).unwrap() }
+        };
+        let a = map_file("a", b"hello world");
+        let b = map_file("b", b"hello world");
+        let c = map_file("c", b"hello WORLD");
+        let d = map_file("d", b"hello");
+
+        assert_ne!(a.as_ptr(), b.as_ptr());
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+
+        let mut mmap = MmapMut::map_anon(11).unwrap();
+        mmap.copy_from_slice(b"hello world");
+        assert!(a == mmap);
+        assert!(mmap == b);
+        assert!(mmap != c);
+        let mut other = MmapMut::map_anon(11).unwrap();
+        other.copy_from_slice(b"hello world");
+        assert_eq!(mmap, other);
+
+        let set: HashSet<Mmap> = vec![a, b, c, d].into_iter().collect();
+        assert_eq!(3, set.len());
+        assert!(set.contains(&mmap.make_read_only().unwrap()));
+        let set: HashSet<MmapMut> = vec![other].into_iter().collect();
+        assert_eq!(1, set.len());
+    }
+
+    #[test]
+    fn reader() {
+        let mmap = unsafe { Mmap::open("README.md").unwrap() };
+        let mut reader = mmap.reader();
+        let mut buf = [0; 6];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(b"# mapr", &buf);
+        assert_eq!(6, reader.position());
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        reader.read_exact(&mut buf[..4]).unwrap();
+        assert_eq!(b"mapr", &buf[..4]);
+
+        // Seeking past the end is allowed, and reads return EOF.
+        let end = mmap.len() as i64;
+        assert_eq!(end as u64 + 10, reader.seek(SeekFrom::End(10)).unwrap());
+        assert_eq!(0, reader.read(&mut buf).unwrap());
+        assert!(reader.seek(SeekFrom::Current(-2 * end - 100)).is_err());
+    }
+
+    #[test]
+    fn as_slice_of() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let values: Vec<u32> = (1..=1000).collect();
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mmap = unsafe { Mmap::open(&path).unwrap() };
+        let typed: &[u32] = unsafe { mmap.as_slice_of().unwrap() };
+        assert_eq!(&values[..], typed);
+        assert_eq!(500_500u32, typed.iter().sum());
+
+        // The length must be a multiple of the size.
+        let err = unsafe { mmap.as_slice_of::<[u8; 3]>().unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        let err = unsafe { mmap.as_slice_of::<()>().unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        // The memory map must be aligned.
+        let file = File::open(&path).unwrap();
+        let mmap = unsafe { MmapOptions::new().offset(2).len(8).map(&file).unwrap() };
+        let err = unsafe { mmap.as_slice_of::<u32>().unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        assert_eq!(4, unsafe { mmap.as_slice_of::<u16>().unwrap() }.len());
+    }
+
+    #[test]
+    fn as_mut_slice_of() {
+        const N: usize = 512;
+
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len((N * 8 + 4) as u64).unwrap();
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        let err = unsafe { mmap.as_mut_slice_of::<u64>().unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let mut mmap = unsafe { MmapOptions::new().len(N * 8).map_mut(&file).unwrap() };
+        let values: &mut [u64] = unsafe { mmap.as_mut_slice_of().unwrap() };
+        assert_eq!(N, values.len());
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = (i as u64) << 32 | 0xAB;
+        }
+        for (i, chunk) in mmap.chunks(8).enumerate() {
+            assert_eq!(((i as u64) << 32 | 0xAB).to_ne_bytes(), chunk);
+        }
+        mmap.flush().unwrap();
+        assert_eq!(&mmap[..], &std::fs::read(&path).unwrap()[..N * 8]);
+
+        // An unaligned offset makes the base unaligned for u64.
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(4)
+                .len(N * 8)
+                .map_mut(&file)
+                .unwrap()
+        };
+        let err = unsafe { mmap.as_mut_slice_of::<u64>().unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        let values: &mut [u32] = unsafe { mmap.as_mut_slice_of().unwrap() };
+        assert_eq!(
+            ((1u64 << 32 | 0xAB).to_ne_bytes())[..4],
+            values[1].to_ne_bytes()
+        );
+    }
+
+    #[test]
+    fn atomic_counters() {
+        let mmap = Arc::new(MmapMut::map_anon(page_size()).unwrap());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let mmap = mmap.clone();
+                thread::spawn(move || {
+                    let counter = mmap.atomic_u64(8).unwrap();
+                    let small = mmap.atomic_u32(4).unwrap();
+                    for _ in 0..1000 {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        small.fetch_add(2, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(4000, mmap.atomic_u64(8).unwrap().load(Ordering::SeqCst));
+        assert_eq!(8000, mmap.atomic_u32(4).unwrap().load(Ordering::SeqCst));
+        assert_eq!(4000u64.to_ne_bytes(), mmap[8..16]);
+
+        for &offset in &[1, 4, 12, page_size() - 4, page_size(), usize::MAX] {
+            let err = mmap.atomic_u64(offset).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+        for &offset in &[2, page_size(), usize::MAX - 1] {
+            let err = mmap.atomic_u32(offset).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+        assert!(mmap.atomic_u32(page_size() - 4).is_ok());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn atomic_counter_fork() {
+        let mmap = MmapOptions::new().map_anon_shared(page_size()).unwrap();
+        let counter = mmap.atomic_u64(0).unwrap();
+        assert!(fork_check(|| {
+            counter.fetch_add(5, Ordering::SeqCst);
+            true
+        }));
+        assert_eq!(5, counter.fetch_add(1, Ordering::SeqCst));
+    }
+
+    #[test]
+    fn split_at_mut() {
+        let len = 3 * page_size() + 100;
+        let mmap = MmapMut::map_anon(len).unwrap();
+        let ptr = mmap.as_ptr();
+        let mid = page_size() + 17;
+        let (left, right) = mmap.split_at_mut(mid);
+        assert_eq!(mid, left.len());
+        assert_eq!(len - mid, right.len());
+        assert_eq!(ptr, left.as_ptr());
+        assert_eq!(unsafe { ptr.add(mid) }, right.as_ptr());
+
+        let fill = |mut region: MmapRegion, value: u8| {
+            thread::spawn(move || {
+                region.iter_mut().for_each(|b| *b = value);
+                region
+            })
+        };
+        let left = fill(left, 1);
+        let right = fill(right, 2);
+        let left = left.join().unwrap();
+        let right = right.join().unwrap();
+        assert!(left.iter().all(|&b| b == 1));
+        assert!(right.iter().all(|&b| b == 2));
+
+        // Regions can be split further, and keep the memory map alive.
+        let (first, second) = right.split_at_mut(10);
+        drop(left);
+        drop(first);
+        assert_eq!(len - mid - 10, second.len());
+        assert!(second.iter().all(|&b| b == 2));
+
+        let (empty, all) = MmapMut::map_anon(10).unwrap().split_at_mut(0);
+        assert!(empty.is_empty());
+        assert_eq!(10, all.len());
+        let result = std::panic::catch_unwind(|| MmapMut::map_anon(10).unwrap().split_at_mut(11));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn slice() {
+        let mmap = unsafe { Mmap::open("README.md").unwrap() };
+        let len = mmap.len();
+        assert_eq!(b"mapr", mmap.slice(2..6).unwrap());
+        assert_eq!(b"mapr", mmap.slice(2..=5).unwrap());
+        assert_eq!(b"# mapr", mmap.slice(..6).unwrap());
+        assert_eq!(&mmap[..], mmap.slice(..).unwrap());
+        assert_eq!(&mmap[len - 1..], mmap.slice(len - 1..).unwrap());
+        assert!(mmap.slice(len..).unwrap().is_empty());
+
+        for range in &[
+            (Bound::Included(0), Bound::Excluded(len + 1)),
+            (Bound::Excluded(len), Bound::Unbounded),
+            (Bound::Included(6), Bound::Excluded(2)),
+            (Bound::Unbounded, Bound::Included(usize::MAX)),
+            (Bound::Excluded(usize::MAX), Bound::Unbounded),
+        ] {
+            let err = mmap.slice(*range).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+    }
+
+    #[test]
+    fn sub_map() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"0123456789abcdefghij").unwrap();
+
+        // The stored offset of the original memory map is taken into account.
+        let mmap = unsafe { MmapOptions::new().offset(5).map(&file).unwrap() };
+        let sub = mmap.sub_map(3..8).unwrap();
+        assert_eq!(b"89abc", &sub[..]);
+        assert_ne!(unsafe { mmap.as_ptr().add(3) }, sub.as_ptr());
+        drop(mmap);
+
+        // The sub-map can be made writable on its own.
+        let mut sub = sub.make_mut().unwrap();
+        sub.copy_from_slice(b"ABCDE");
+        sub.flush().unwrap();
+        assert_eq!(b"01234567ABCDEdefghij", &std::fs::read(&path).unwrap()[..]);
+
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        for range in &[
+            (Bound::Included(3), Bound::Excluded(3)),
+            (Bound::Included(10), Bound::Excluded(21)),
+        ] {
+            let err = mmap.sub_map(*range).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+        let anon = MmapMut::map_anon(10).unwrap().make_read_only().unwrap();
+        let err = anon.sub_map(..5).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn flush_unaligned_range() {
+        let page_size = page_size();
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(4 * page_size as u64).unwrap();
+
+        // Neither the memory map nor the flushed ranges start on a page boundary.
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .offset(100)
+                .len(3 * page_size)
+                .map_mut(&file)
+                .unwrap()
+        };
+        let offset = page_size + 7;
+        mmap[offset..offset + 6].copy_from_slice(b"abc123");
+        mmap.flush_range(offset, 6).unwrap();
+        mmap[5..11].copy_from_slice(b"xyz789");
+        mmap.flush_async_range(5, 6).unwrap();
+        mmap.flush_async().unwrap();
+        mmap.flush_range(0, 1).unwrap();
+        mmap.flush_async_range(3 * page_size - 1, 1).unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(b"abc123", &contents[100 + offset..106 + offset]);
+        assert_eq!(b"xyz789", &contents[105..111]);
+    }
+
+    #[test]
+    fn to_vec() {
+        let mut contents = Vec::new();
+        File::open("README.md")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+
+        let mmap = unsafe { Mmap::open("README.md").unwrap() };
+        assert_eq!(contents, mmap.to_vec());
+        let mmap = unsafe { MmapOptions::new().map_copy(&File::open("README.md").unwrap()) };
+        assert_eq!(contents, mmap.unwrap().to_vec());
+    }
+
+    #[test]
+    fn clone_file() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.write_all(b"hello world").unwrap();
+
+        let mmap = unsafe { MmapOptions::new().offset(6).map(&file).unwrap() };
+        drop(file);
+        let clone = mmap.clone();
+        assert_ne!(mmap.as_ptr(), clone.as_ptr());
+        assert_eq!(MapKind::SharedFile, clone.inner.kind());
+        drop(mmap);
+        assert_eq!(b"world", &clone[..]);
+
+        // The clone maps the file, so it reflects changes to it.
+        let mut file = clone.inner.file().unwrap();
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.write_all(b"WORLD").unwrap();
+        assert_eq!(b"WORLD", &clone[..]);
+    }
+
+    #[test]
+    fn clone_anon() {
+        let mut mmap = MmapMut::map_anon(3 * page_size()).unwrap();
+        mmap[..5].copy_from_slice(b"hello");
+        let mmap = mmap.make_read_only().unwrap();
+
+        let clone = mmap.clone();
+        assert_ne!(mmap.as_ptr(), clone.as_ptr());
+        assert_eq!(&mmap[..], &clone[..]);
+        mmap.unmap().unwrap();
+        assert_eq!(b"hello", &clone[..5]);
+        assert_eq!(3 * page_size(), clone.len());
+    }
+
+    #[test]
+    fn leak() {
+        let mut mmap = MmapMut::map_anon(page_size()).unwrap();
+        mmap[..5].copy_from_slice(b"hello");
+        let leaked: &'static mut [u8] = mmap.leak();
+        assert_eq!(b"hello", &leaked[..5]);
+        leaked[5..11].copy_from_slice(b"abc123");
+
+        let mmap = unsafe { MmapMut::from_raw(leaked.as_mut_ptr(), leaked.len()) };
+        assert_eq!(b"helloabc123", &mmap[..11]);
+        let mmap = mmap.make_read_only().unwrap();
+        let leaked: &'static [u8] = mmap.leak();
+        assert_eq!(b"helloabc123", &leaked[..11]);
+
+        let (ptr, len) =
+            unsafe { Mmap::from_raw(leaked.as_ptr() as *mut u8, leaked.len()) }.into_raw();
+        assert_eq!(page_size(), len);
+        unsafe { Mmap::from_raw(ptr as *mut u8, len) }
+            .unmap()
+            .unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn into_raw_mirrored() {
+        let page_size = page_size();
+        let mmap = MmapMut::mirrored_anon(page_size).unwrap();
+        let (ptr, len) = mmap.into_raw();
+        assert_eq!(page_size, len);
+
+        // The mirror is unmapped, so that the pointer and length cover the whole memory map.
+        let mut residency = 0u8;
+        let mirror = unsafe { ptr.add(page_size) } as *mut libc::c_void;
+        assert_eq!(-1, unsafe {
+            libc::mincore(mirror, page_size, &mut residency as *mut _ as *mut _)
+        });
+        unsafe { MmapMut::from_raw(ptr, len) }.unmap().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn into_raw_guard_pages() {
+        let page_size = page_size();
+        let mmap = MmapOptions::new()
+            .guard_pages(2)
+            .len(page_size)
+            .map_anon()
+            .unwrap();
+        let (ptr, len) = mmap.into_raw();
+        assert_eq!(page_size, len);
+
+        // The guard pages are unmapped along with the rest of the head.
+        let mut residency = 0u8;
+        let guard = unsafe { ptr.sub(2 * page_size) } as *mut libc::c_void;
+        assert_eq!(-1, unsafe {
+            libc::mincore(guard, 2 * page_size, &mut residency as *mut _ as *mut _)
+        });
+        unsafe { MmapMut::from_raw(ptr, len) }.unmap().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn into_raw_unaligned_offset() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        std::fs::write(&path, vec![0xAB; 2 * page_size()]).unwrap();
+
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(page_size() as u64 + 100)
+                .map(&File::open(&path).unwrap())
+                .unwrap()
+        };
+        let (ptr, len) = mmap.into_raw();
+        assert_eq!(page_size() - 100, len);
+        assert_eq!(100, ptr as usize % page_size());
+
+        let mmap = unsafe { Mmap::from_raw(ptr as *mut u8, len) };
+        assert!(mmap.iter().all(|&b| b == 0xAB));
+        mmap.unmap().unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn advise_dont_fork() {
+        let page_size = page_size();
+        let mut mmap = MmapMut::map_anon(page_size).unwrap();
+        mmap[0] = 0xAB;
+        mmap.advise(Advice::DontFork).unwrap();
+
+        let ptr = mmap.as_ptr() as *mut libc::c_void;
+        assert!(fork_check(|| {
+            // mincore fails with ENOMEM for unmapped addresses.
+            let mut residency = 0u8;
+            unsafe { libc::mincore(ptr, page_size, &mut residency) == -1 }
+        }));
+        assert_eq!(0xAB, mmap[0]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn advise_wipe_on_fork() {
+        let mut mmap = MmapOptions::new()
+            .len(page_size())
+            .private()
+            .map_anon()
+            .unwrap();
+        mmap.iter_mut().for_each(|b| *b = 0xAB);
+        match mmap.advise(Advice::WipeOnFork) {
+            Ok(()) => {}
+            // Kernels before 4.14 do not support MADV_WIPEONFORK.
+            Err(ref err) if err.raw_os_error() == Some(libc::EINVAL) => return,
+            Err(err) => panic!("{}", err),
+        }
+
+        let ptr = mmap.as_ptr();
+        let len = mmap.len();
+        assert!(fork_check(|| unsafe {
+            std::slice::from_raw_parts(ptr, len).iter().all(|&b| b == 0)
+        }));
+        assert!(mmap.iter().all(|&b| b == 0xAB));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn with_advice() {
+        let mmap = MmapMut::map_anon(4096).unwrap();
+
+        let flags = mmap
+            .with_advice(Advice::Sequential, |mmap| vm_flags(mmap.as_ptr()))
+            .unwrap();
+        assert!(flags.iter().any(|flag| flag == "sr"));
+        assert!(!vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "sr"));
+
+        // The advice is also reset when the closure panics.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            mmap.with_advice(Advice::Random, |_| panic!("oops"))
+        }));
+        assert!(result.is_err());
+        assert!(!vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "rr"));
+    }
+
+    /// Sends `fd` over the unix socket `socket`.
+    #[cfg(unix)]
+    fn send_fd(socket: RawFd, fd: RawFd) {
+        unsafe {
+            let mut byte = 0u8;
+            let mut iov = libc::iovec {
+                iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+                iov_len: 1,
+            };
+            let mut control = [0u8; 64];
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+            assert_eq!(1, libc::sendmsg(socket, &msg, 0));
+        }
+    }
+
+    /// Receives a file descriptor sent with `send_fd` from the unix socket `socket`.
+    #[cfg(unix)]
+    fn recv_fd(socket: RawFd) -> RawFd {
+        unsafe {
+            let mut byte = 0u8;
+            let mut iov = libc::iovec {
+                iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+                iov_len: 1,
+            };
+            let mut control = [0u8; 64];
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = control.len() as _;
+
+            assert_eq!(1, libc::recvmsg(socket, &mut msg, 0));
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            assert!(!cmsg.is_null());
+            assert_eq!(libc::SCM_RIGHTS, (*cmsg).cmsg_type);
+            ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd)
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn map_anon_shared_fd() {
+        let (mut mmap, fd) = MmapOptions::new().map_anon_shared_fd(4096).unwrap();
+        assert_eq!(4096, mmap.len());
+
+        let mut sockets = [0; 2];
+        let result =
+            unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, sockets.as_mut_ptr()) };
+        assert_eq!(0, result);
+        let (sender, receiver) =
+            unsafe { (File::from_raw_fd(sockets[0]), File::from_raw_fd(sockets[1])) };
+
+        send_fd(sender.as_raw_fd(), fd.as_raw_fd());
+        drop(fd);
+        let file = unsafe { File::from_raw_fd(recv_fd(receiver.as_raw_fd())) };
+
+        let mut other = unsafe { MmapMut::map_mut(&file).unwrap() };
+        assert_eq!(4096, other.len());
+
+        (&mut mmap[..]).write_all(b"abc123").unwrap();
+        assert_eq!(b"abc123", &other[..6]);
+        (&mut other[6..]).write_all(b"xyz789").unwrap();
+        assert_eq!(b"xyz789", &mmap[6..12]);
+
+        assert!(MmapOptions::new().map_anon_shared_fd(0).is_err());
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn map_memfd_huge_invalid() {
+        const HUGE_PAGE: usize = 2 << 20;
+
+        let options = MmapOptions::new();
+        let err = options.map_memfd_huge("mapr", HUGE_PAGE).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let err = MmapOptions::new()
+            .len(HUGE_PAGE + 4096)
+            .map_memfd_huge("mapr", HUGE_PAGE)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let err = MmapOptions::new()
+            .len(HUGE_PAGE)
+            .map_memfd_huge("mapr", 4096)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    /// Requires a reserved huge page, e.g. `echo 2 > /proc/sys/vm/nr_hugepages`.
+    #[test]
+    #[ignore]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn map_memfd_huge() {
+        const HUGE_PAGE: usize = 2 << 20;
+
+        let (mut mmap, fd) = MmapOptions::new()
+            .len(HUGE_PAGE)
+            .map_memfd_huge("mapr", HUGE_PAGE)
+            .unwrap();
+        assert_eq!(HUGE_PAGE, mmap.len());
+        assert_eq!(0, mmap.as_ptr() as usize % HUGE_PAGE);
+        assert!(vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "ht"));
+
+        let file = File::from(fd);
+        let other = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap[..5].copy_from_slice(b"hello");
+        assert_eq!(b"hello", &other[..5]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn map_memfd() {
+        let (mut mmap, mut file) = MmapOptions::new().map_memfd("mapr", 4096).unwrap();
+        assert_eq!(4096, mmap.len());
+        assert_eq!(4096, file.metadata().unwrap().len());
+
+        mmap[..6].copy_from_slice(b"abc123");
+        let mut contents = [0; 6];
+        file.read_exact(&mut contents).unwrap();
+        assert_eq!(b"abc123", &contents);
+
+        file.write_all(b"xyz").unwrap();
+        assert_eq!(b"abc123xyz", &mmap[..9]);
+
+        let err = MmapOptions::new().map_memfd("mapr", 0).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        let err = MmapOptions::new().map_memfd("ma\0pr", 4096).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "android")))]
+    fn map_shared_named() {
+        let name = format!("mapr-test-{}", std::process::id());
+        let err = MmapOptions::new()
+            .map_shared_named(&name, 4096, false)
+            .unwrap_err();
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        let mut mmap = MmapOptions::new()
+            .map_shared_named(&name, 4096, true)
+            .unwrap();
+        // The leading slash is optional.
+        let other = MmapOptions::new()
+            .map_shared_named(&format!("/{}", name), 4096, false)
+            .unwrap();
+        mmap[..6].copy_from_slice(b"abc123");
+        assert_eq!(b"abc123", &other[..6]);
+
+        let err = MmapOptions::new()
+            .map_shared_named(&name, 8192, false)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        for bad in &["", "/", "a/b", "//a", "a\0b"] {
+            let err = MmapOptions::new()
+                .map_shared_named(bad, 4096, true)
+                .unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+
+        MmapOptions::unlink_shared(&name).unwrap();
+        assert_eq!(b"abc123", &other[..6]);
+        let err = MmapOptions::unlink_shared(&name).unwrap_err();
+        assert_eq!(ErrorKind::NotFound, err.kind());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn map_shared_named_windows() {
+        let name = format!("mapr-test-{}", std::process::id());
+        let err = MmapOptions::new()
+            .map_shared_named(&name, 4096, false)
+            .unwrap_err();
+        assert_eq!(ErrorKind::NotFound, err.kind());
+
+        let mut mmap = MmapOptions::new()
+            .map_shared_named(&name, 4096, true)
+            .unwrap();
+        // Existing sections are opened, with or without create.
+        let other = MmapOptions::new()
+            .map_shared_named(&format!("/{}", name), 4096, false)
+            .unwrap();
+        let third = MmapOptions::new()
+            .map_shared_named(&name, 100, true)
+            .unwrap();
+        mmap[..6].copy_from_slice(b"abc123");
+        assert_eq!(b"abc123", &other[..6]);
+        assert_eq!(b"abc123", &third[..6]);
+
+        let err = MmapOptions::new()
+            .map_shared_named(&name, 8192, true)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        MmapOptions::unlink_shared(&name).unwrap();
+        assert!(MmapOptions::unlink_shared("a/b").is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn seal_memfd() {
+        use crate::{seal, seals, Seals};
+
+        let (mmap, file) = MmapOptions::new().map_memfd("mapr", 4096).unwrap();
+        assert_eq!(Seals::empty(), seals(&file).unwrap());
+
+        seal(&file, Seals::GROW).unwrap();
+        assert!(seals(&file).unwrap().contains(Seals::GROW));
+        assert!(file.set_len(8192).is_err());
+
+        // Writes can not be sealed while a shared writable memory map exists.
+        let err = seal(&file, Seals::WRITE).unwrap_err();
+        assert_eq!(Some(libc::EBUSY), err.raw_os_error());
+        drop(mmap);
+        seal(&file, Seals::WRITE | Seals::SEAL).unwrap();
+        assert!(unsafe { MmapMut::map_mut(&file) }.is_err());
+        assert!(unsafe { Mmap::map(&file) }.is_ok());
+        assert!(seal(&file, Seals::SHRINK).is_err());
+
+        // Files created without MFD_ALLOW_SEALING can not be sealed.
+        let (_mmap, fd) = MmapOptions::new().map_anon_shared_fd(4096).unwrap();
+        let err = seal(&File::from(fd), Seals::GROW).unwrap_err();
+        assert_eq!(Some(libc::EPERM), err.raw_os_error());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn huge_aligned_past_eof() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.set_len(4096).unwrap();
+
+        let err = unsafe {
+            MmapOptions::new()
+                .huge(Some(HugePageSize::Size2MB))
+                .huge_aligned()
+                .offset(100)
+                .len(100)
+                .map(&file)
+                .unwrap_err()
+        };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn huge_page_sizes() {
+        for &size in &[HugePageSize::Size2MB, HugePageSize::Size1GB] {
+            let mut mmap = match MmapOptions::new()
+                .len(size.bytes())
+                .huge(Some(size))
+                .map_anon()
+            {
+                Ok(mmap) => mmap,
+                // No huge pages of this size are reserved, or the size is not supported.
+                Err(ref err)
+                    if err.raw_os_error() == Some(libc::ENOMEM)
+                        || err.raw_os_error() == Some(libc::EINVAL) =>
+                {
+                    continue
+                }
+                Err(err) => panic!("{:?}: {}", size, err),
+            };
+            mmap[0] = 1;
+            mmap[size.bytes() - 1] = 2;
+            assert_eq!(&[1, 2], &[mmap[0], mmap[size.bytes() - 1]]);
+        }
+
+        let mmap = MmapOptions::new().len(4096).huge(None).map_anon().unwrap();
+        assert_eq!(4096, mmap.len());
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn huge_shift() {
+        for &shift in &[0, 19, 35, 63] {
+            let err = MmapOptions::new()
+                .len(4096)
+                .huge_shift(shift)
+                .map_anon()
+                .unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+        let err = MmapOptions::new()
+            .len(2 << 20)
+            .huge(Some(HugePageSize::Size2MB))
+            .huge_shift(21)
+            .map_anon()
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        match MmapOptions::new().len(2 << 20).huge_shift(21).map_anon() {
+            Ok(mut mmap) => {
+                mmap[0] = 1;
+                assert_eq!(1, mmap[0]);
+                assert_eq!("2048 kB", smaps_field(mmap.as_ptr(), "KernelPageSize"));
+            }
+            // No 2 MiB huge pages are reserved.
+            Err(ref err)
+                if err.raw_os_error() == Some(libc::ENOMEM)
+                    || err.raw_os_error() == Some(libc::EINVAL) => {}
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn map_anon_huge() {
+        const HUGE_PAGE: usize = 2 << 20;
+
+        for &len in &[None, Some(0), Some(4096), Some(HUGE_PAGE + 4096)] {
+            let mut options = MmapOptions::new();
+            if let Some(len) = len {
+                options.len(len);
+            }
+            let err = options.map_anon_huge(HugePageSize::Size2MB).unwrap_err();
+            assert_eq!(ErrorKind::InvalidInput, err.kind());
+        }
+
+        let result = MmapOptions::new()
+            .len(HUGE_PAGE)
+            .huge_shift(30)
+            .map_anon_huge(HugePageSize::Size2MB);
+        let nr_hugepages = std::fs::read_to_string("/proc/sys/vm/nr_hugepages")
+            .ok()
+            .and_then(|nr| nr.trim().parse::<usize>().ok());
+        if nr_hugepages == Some(0) {
+            assert_eq!(ErrorKind::OutOfMemory, result.unwrap_err().kind());
+            return;
+        }
+        let mut mmap = match result {
+            Ok(mmap) => mmap,
+            // The reserved huge pages may be in use elsewhere.
+            Err(ref err) if err.kind() == ErrorKind::OutOfMemory => return,
+            Err(err) => panic!("{}", err),
+        };
+        mmap[HUGE_PAGE - 1] = 1;
+        assert_eq!("2048 kB", smaps_field(mmap.as_ptr(), "KernelPageSize"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn huge_u8() {
+        let debug = |options: &MmapOptions| format!("{:?}", options);
+        assert_eq!(
+            debug(MmapOptions::new().huge(Some(HugePageSize::Size2MB))),
+            debug(MmapOptions::new().huge_u8(1))
+        );
+        assert_eq!(
+            debug(MmapOptions::new().huge(Some(HugePageSize::Size1GB))),
+            debug(MmapOptions::new().huge_u8(2))
+        );
+        assert_eq!(
+            debug(&MmapOptions::new()),
+            debug(MmapOptions::new().huge_u8(0))
+        );
+    }
+
+    /// Requires two reserved huge pages, e.g. `echo 2 > /proc/sys/vm/nr_hugepages`.
+    #[test]
+    #[ignore]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn huge_aligned() {
+        const HUGE_PAGE: usize = 2 << 20;
+
+        let file = super::unix::huge_memfd("mapr", HUGE_PAGE, 2 * HUGE_PAGE as u64).unwrap();
+        let mut full = unsafe {
+            MmapOptions::new()
+                .huge(Some(HugePageSize::Size2MB))
+                .map_mut(&file)
+                .unwrap()
+        };
+        for (i, b) in full.iter_mut().enumerate() {
+            *b = (i % 251) as u8;
+        }
+
+        // An unaligned offset within the second huge page.
+        let offset = HUGE_PAGE + 3 * 4096 + 7;
+        let mmap = unsafe {
+            MmapOptions::new()
+                .huge(Some(HugePageSize::Size2MB))
+                .huge_aligned()
+                .offset(offset as u64)
+                .len(100)
+                .map(&file)
+                .unwrap()
+        };
+        assert_eq!(100, mmap.len());
+        assert_eq!(&full[offset..offset + 100], &mmap[..]);
+
+        // A range straddling both huge pages.
+        let offset = HUGE_PAGE - 50;
+        let mut mmap = unsafe {
+            MmapOptions::new()
+                .huge(Some(HugePageSize::Size2MB))
+                .huge_aligned()
+                .offset(offset as u64)
+                .len(100)
+                .map_mut(&file)
+                .unwrap()
+        };
+        assert_eq!(&full[offset..offset + 100], &mmap[..]);
+        mmap[0] = 0xff;
+        assert_eq!(0xff, full[offset]);
+        drop(mmap);
+
+        // Without alignment the unaligned offset is rejected by the kernel.
+        assert!(unsafe {
+            MmapOptions::new()
+                .huge(Some(HugePageSize::Size2MB))
+                .offset(offset as u64)
+                .len(100)
+                .map(&file)
+                .is_err()
+        });
+    }
+
+    #[test]
+    fn map_kind() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        assert_eq!(MapKind::SharedFile, mmap.inner.kind());
+        let mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+        assert_eq!(MapKind::PrivateFile, mmap.inner.kind());
+        let mmap = MmapMut::map_anon(128).unwrap();
+        assert_eq!(MapKind::Anonymous, mmap.inner.kind());
+    }
+
+    #[test]
+    fn shared_atomic_slice() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let first = unsafe { MmapMut::map_mut(&file).unwrap() };
+        let second = unsafe { MmapMut::map_mut(&file).unwrap() };
+        let first = first.as_shared_atomic_slice();
+        let second = second.as_shared_atomic_slice();
+        assert_eq!(128, first.len());
+
+        first[7].store(42, Ordering::SeqCst);
+        assert_eq!(42, second[7].load(Ordering::SeqCst));
+        second[7].fetch_add(1, Ordering::SeqCst);
+        assert_eq!(43, first[7].load(Ordering::SeqCst));
+    }
+
+    /// Requires a DAX filesystem, e.g. on a pmem device, mounted at the directory named by the
+    /// `MAPR_DAX_DIR` environment variable.
+    #[test]
+    #[ignore]
+    #[cfg(target_os = "linux")]
+    fn sync_on_write_dax() {
+        let dir = match std::env::var_os("MAPR_DAX_DIR") {
+            Some(dir) => dir,
+            None => return,
+        };
+        let tempdir = tempdir::TempDir::new_in(dir, "mmap").unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
+            .unwrap();
+        file.set_len(page_size() as u64).unwrap();
+
+        let mut mmap = unsafe { MmapOptions::new().sync_on_write().map_mut(&file).unwrap() };
+        assert!(mmap.is_sync_mapped());
+        assert_eq!(MapKind::SharedFile, mmap.inner.kind());
+        assert_eq!(Protection::ReadWrite, mmap.protection_of(0, 1).unwrap());
+
+        (&mut mmap[..]).write_all(b"abc123").unwrap();
+        let view = unsafe { Mmap::map(&file).unwrap() };
+        assert_eq!(b"abc123", &view[..6]);
+
+        mmap.make_private().unwrap();
+        assert_eq!(MapKind::PrivateFile, mmap.inner.kind());
+        assert!(!mmap.is_sync_mapped());
+    }
+
+    /// Checks that `sync_on_write()` falls back to a regular map on tmpfs, which has no DAX
+    /// support, and reports it.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn sync_on_write_fallback() {
+        let tempdir = match tempdir::TempDir::new_in("/dev/shm", "mmap") {
+            Ok(tempdir) => tempdir,
+            // No tmpfs mounted at /dev/shm.
+            Err(_) => return,
+        };
+        let path = tempdir.path().join("mmap");
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut mmap = unsafe { MmapOptions::new().sync_on_write().map_mut(&file).unwrap() };
+        assert!(!mmap.is_sync_mapped());
+        (&mut mmap[..]).write_all(b"abc123").unwrap();
+        mmap.flush().unwrap();
+
+        let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        assert!(!mmap.is_sync_mapped());
+        assert_eq!(b"abc123", &mmap[..6]);
+    }
+
+    #[test]
+    fn arena_alloc_across_commit_boundary() {
+        let page_size = page_size();
+        let mut arena = MmapArena::new(16 * page_size).unwrap();
+        assert_eq!(0, arena.committed());
+
+        let first = arena.alloc(page_size - 100).unwrap();
+        assert!(first.iter().all(|&b| b == 0));
+        first.iter_mut().for_each(|b| *b = 1);
+        assert_eq!(page_size, arena.committed());
+
+        // The second allocation straddles the first and second pages.
+        let second = arena.alloc(200).unwrap();
+        assert!(second.iter().all(|&b| b == 0));
+        second.iter_mut().for_each(|b| *b = 2);
+        assert_eq!(2 * page_size, arena.committed());
+        assert_eq!(page_size + 100, arena.used());
+
+        assert!(arena.alloc(16 * page_size).is_err());
+        assert!(arena.alloc(usize::MAX).is_err());
+        assert_eq!(page_size + 100, arena.used());
+    }
+
+    #[test]
+    fn arena_reset() {
+        let page_size = page_size();
+        let mut arena = MmapArena::new(4 * page_size).unwrap();
+
+        arena
+            .alloc(3 * page_size)
+            .unwrap()
+            .iter_mut()
+            .for_each(|b| *b = 42);
+        assert_eq!(3 * page_size, arena.committed());
+
+        arena.reset().unwrap();
+        assert_eq!(0, arena.used());
+        assert_eq!(0, arena.committed());
+
+        // Memory allocated after a reset is zeroed again.
+        let buf = arena.alloc(4 * page_size).unwrap();
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn growable_push_across_page_boundary() {
+        let page_size = page_size();
+        let mut buf = GrowableMmap::new(16 * page_size).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(0, buf.capacity());
+        assert_eq!(16 * page_size, buf.max_capacity());
+
+        buf.push_slice(&vec![1; page_size - 100]).unwrap();
+        assert_eq!(page_size, buf.capacity());
+        let ptr = buf.as_ptr();
+        let first = &buf[0] as *const u8;
+
+        // The second push straddles the first and second pages.
+        buf.push_slice(&[2; 200]).unwrap();
+        buf.push_slice(&vec![3; 3 * page_size]).unwrap();
+        assert_eq!(4 * page_size + 100, buf.len());
+        assert_eq!(5 * page_size, buf.capacity());
+
+        // Growing never moves the buffer.
+        assert_eq!(ptr, buf.as_ptr());
+        assert_eq!(1, unsafe { *first });
+        assert!(buf[..page_size - 100].iter().all(|&b| b == 1));
+        assert!(buf[page_size - 100..page_size + 100]
+            .iter()
+            .all(|&b| b == 2));
+        assert!(buf[page_size + 100..].iter().all(|&b| b == 3));
+    }
+
+    #[test]
+    fn growable_reserve() {
+        let page_size = page_size();
+        let mut buf = GrowableMmap::new(4 * page_size).unwrap();
+
+        buf.reserve(page_size + 1).unwrap();
+        assert_eq!(0, buf.len());
+        assert_eq!(2 * page_size, buf.capacity());
+
+        buf.push_slice(&vec![1; 3 * page_size]).unwrap();
+        let err = buf.reserve(page_size + 1).unwrap_err();
+        assert_eq!(ErrorKind::OutOfMemory, err.kind());
+        let err = buf.push_slice(&vec![2; page_size + 1]).unwrap_err();
+        assert_eq!(ErrorKind::OutOfMemory, err.kind());
+        assert_eq!(3 * page_size, buf.len());
+        assert!(buf.reserve(usize::MAX).is_err());
+
+        buf.push_slice(&vec![2; page_size]).unwrap();
+        assert_eq!(4 * page_size, buf.capacity());
+        assert_eq!(buf.max_capacity(), buf.len());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn collapse_huge() {
+        const HUGE_PAGE: usize = 2 << 20;
+
+        // Map an extra huge page so that an aligned huge page lies entirely within the map. The
+        // map is private, since shared anonymous memory is accounted as shmem rather than anon.
+        let mut mmap = MmapOptions::new()
+            .len(2 * HUGE_PAGE)
+            .private()
+            .map_anon()
+            .unwrap();
+        let offset = (HUGE_PAGE - mmap.as_ptr() as usize % HUGE_PAGE) % HUGE_PAGE;
+        mmap.iter_mut().for_each(|b| *b = 1);
+
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            mmap.collapse_huge(1, 2 * HUGE_PAGE).unwrap_err().kind()
+        );
+
+        // Collapsing fails on kernels before 6.1, or when huge pages are unavailable.
+        if mmap.collapse_huge(offset, HUGE_PAGE).is_ok() {
+            let huge = smaps_field(mmap.as_ptr(), "AnonHugePages");
+            assert_ne!("0 kB", huge);
+        }
+        assert!(mmap.iter().all(|&b| b == 1));
+    }
+
+    /// Checks that none of the data constructors request execute permission.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn data_maps_not_exec() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let not_exec = |ptr: *const u8| !vm_flags(ptr).iter().any(|flag| flag == "ex");
+
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        assert!(not_exec(mmap.as_ptr()));
+        let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        assert!(not_exec(mmap.as_ptr()));
+        let mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+        assert!(not_exec(mmap.as_ptr()));
+        let mmap = unsafe { MmapOptions::new().map_with(&file).unwrap() };
+        assert!(not_exec(mmap.as_ptr()));
+        let mmap = MmapMut::map_anon(128).unwrap();
+        assert!(not_exec(mmap.as_ptr()));
+        let mmap = MmapOptions::new().stack().len(128).map_anon().unwrap();
+        assert!(not_exec(mmap.as_ptr()));
+        let (mmap, _fd) = MmapOptions::new().map_anon_shared_fd(128).unwrap();
+        assert!(not_exec(mmap.as_ptr()));
+        let mut arena = MmapArena::new(128).unwrap();
+        assert!(not_exec(arena.alloc(128).unwrap().as_ptr()));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn ensure_no_exec() {
+        let mmap = MmapMut::map_anon(128).unwrap().make_exec().unwrap();
+        assert!(vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "ex"));
+        mmap.ensure_no_exec().unwrap();
+        assert!(!vm_flags(mmap.as_ptr()).iter().any(|flag| flag == "ex"));
+
+        let mmap = mmap.make_mut().unwrap();
+        mmap.ensure_no_exec().unwrap();
+        let flags = vm_flags(mmap.as_ptr());
+        assert!(!flags.iter().any(|flag| flag == "ex"));
+        assert!(flags.iter().any(|flag| flag == "wr"));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", windows))]
+    fn ensure_no_exec_keeps_protect_range() {
+        let page_size = page_size();
+        let mut mmap = MmapMut::map_anon(3 * page_size).unwrap();
+        mmap.protect_range(0, page_size, Protection::Read).unwrap();
+        mmap.protect_range(page_size, page_size, Protection::ReadExec)
+            .unwrap();
+        mmap.ensure_no_exec().unwrap();
+        assert_eq!(
+            Protection::Read,
+            mmap.protection_of(0, 2 * page_size).unwrap()
+        );
+        assert_eq!(
+            Protection::ReadWrite,
+            mmap.protection_of(2 * page_size, page_size).unwrap()
+        );
+    }
+
+    #[test]
+    fn deny_exec() {
+        let file = File::open("README.md").unwrap();
+        let err = unsafe { MmapOptions::new().deny_exec().map_exec(&file).unwrap_err() };
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let mmap = unsafe { MmapOptions::new().deny_exec().map(&file).unwrap() };
+        mmap.ensure_no_exec().unwrap();
+    }
+
+    #[test]
+    fn human_size() {
+        let format = |size| HumanSize(size).to_string();
+        assert_eq!("0 B", format(0));
+        assert_eq!("1023 B", format(1023));
+        assert_eq!("1.0 KiB", format(1024));
+        assert_eq!("1.5 KiB", format(1536));
+        assert_eq!("1023.9 KiB", format(1023 * 1024 + 921));
+        assert_eq!("1.0 MiB", format((1 << 20) - 1));
+        assert_eq!("1.0 MiB", format(1 << 20));
+        assert_eq!("4.0 MiB", format(4 << 20));
+        assert_eq!("1.0 GiB", format(1 << 30));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!("2048.0 TiB", format(1 << 51));
+    }
 
-#[cfg(test)]
-mod test {
-    use std::fs::OpenOptions;
-    use std::io::{Read, Write};
-    #[cfg(windows)]
-    use std::os::windows::fs::OpenOptionsExt;
-    use std::sync::Arc;
-    use std::thread;
+    #[test]
+    fn display() {
+        let mmap = MmapMut::map_anon(3 << 10).unwrap();
+        let expected = format!("MmapMut(3.0 KiB @ {:p})", mmap.as_ptr());
+        assert_eq!(expected, mmap.to_string());
 
-    #[cfg(windows)]
-    use winapi::um::winnt::GENERIC_ALL;
+        let mmap = mmap.make_read_only().unwrap();
+        let expected = format!("Mmap(3.0 KiB @ {:p})", mmap.as_ptr());
+        assert_eq!(expected, mmap.to_string());
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn map_o_path() {
+        use std::ffi::CString;
+
+        let path = CString::new("README.md").unwrap();
+        let fd = unsafe {
+            libc::openat(
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                libc::O_PATH | libc::O_CLOEXEC,
+            )
+        };
+        assert!(fd >= 0);
+        let file = unsafe { File::from_raw_fd(fd) };
 
-    use super::{Mmap, MmapMut, MmapOptions};
+        let err = unsafe { Mmap::map(&file).unwrap_err() };
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
+        assert!(err.to_string().contains("O_PATH"));
+    }
 
     #[test]
-    fn map_file() {
-        let expected_len = 128;
+    #[cfg(unix)]
+    fn map_access_mode() {
         let tempdir = tempdir::TempDir::new("mmap").unwrap();
         let path = tempdir.path().join("mmap");
-
         let file = OpenOptions::new()
-            .read(true)
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&path)
             .unwrap();
+        file.set_len(128).unwrap();
 
-        file.set_len(expected_len as u64).unwrap();
+        let err = unsafe { Mmap::map(&file).unwrap_err() };
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
 
-        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
-        let len = mmap.len();
-        assert_eq!(expected_len, len);
+        let file = File::open(&path).unwrap();
+        let err = unsafe { MmapMut::map_mut(&file).unwrap_err() };
+        assert_eq!(ErrorKind::PermissionDenied, err.kind());
 
-        let zeros = vec![0; len];
-        let incr: Vec<u8> = (0..len as u8).collect();
+        // Private maps only need read access.
+        unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+    }
 
-        // check that the mmap is empty
-        assert_eq!(&zeros[..], &mmap[..]);
+    #[test]
+    fn copy_from_slice_at() {
+        let mut mmap = MmapMut::map_anon(8).unwrap();
 
-        // write values into the mmap
-        (&mut mmap[..]).write_all(&incr[..]).unwrap();
+        // Exact fit.
+        assert_eq!(8, mmap.copy_from_slice_at(0, b"abcdefgh"));
+        assert_eq!(b"abcdefgh", &mmap[..]);
 
-        // read values back
-        assert_eq!(&incr[..], &mmap[..]);
+        // Offset.
+        assert_eq!(2, mmap.copy_from_slice_at(3, b"xy"));
+        assert_eq!(b"abcxyfgh", &mmap[..]);
+
+        // Truncated.
+        assert_eq!(2, mmap.copy_from_slice_at(6, b"1234"));
+        assert_eq!(b"abcxyf12", &mmap[..]);
+
+        // At and past the end.
+        assert_eq!(0, mmap.copy_from_slice_at(8, b"z"));
+        assert_eq!(0, mmap.copy_from_slice_at(usize::MAX, b"z"));
+        assert_eq!(0, mmap.copy_from_slice_at(0, b""));
+        assert_eq!(b"abcxyf12", &mmap[..]);
     }
 
-    /// Checks that a 0-length file will not be mapped.
     #[test]
-    fn map_empty_file() {
-        let tempdir = tempdir::TempDir::new("mmap").unwrap();
-        let path = tempdir.path().join("mmap");
+    #[cfg(feature = "rayon")]
+    fn par_chunks() {
+        use rayon::iter::ParallelIterator;
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&path)
-            .unwrap();
-        let mmap = unsafe { Mmap::map(&file) };
-        assert!(mmap.is_err());
+        let mut mmap = MmapMut::map_anon(10 * page_size() + 123).unwrap();
+        for (i, b) in mmap.iter_mut().enumerate() {
+            *b = (i * 7 % 256) as u8;
+        }
+        let mmap = mmap.make_read_only().unwrap();
+
+        let checksum = |chunk: &[u8]| chunk.iter().map(|&b| b as u64).sum::<u64>();
+        let serial = checksum(&mmap);
+        for &chunk_size in &[0, 1, page_size(), 3 * page_size() + 1, usize::MAX / 2] {
+            let parallel: u64 = mmap.par_chunks(chunk_size).map(checksum).sum();
+            assert_eq!(serial, parallel);
+        }
+
+        // Every chunk but the last covers whole pages.
+        let lens: Vec<usize> = mmap.par_chunks(100).map(|chunk| chunk.len()).collect();
+        assert_eq!(11, lens.len());
+        assert!(lens[..10].iter().all(|&len| len == page_size()));
+        assert_eq!(123, lens[10]);
     }
 
     #[test]
-    fn map_anon() {
-        let expected_len = 128;
-        let mut mmap = MmapMut::map_anon(expected_len).unwrap();
-        let len = mmap.len();
-        assert_eq!(expected_len, len);
+    fn unmap() {
+        let mmap = MmapMut::map_anon(4096).unwrap();
+        mmap.unmap().unwrap();
 
-        let zeros = vec![0; len];
-        let incr: Vec<u8> = (0..len as u8).collect();
+        let mmap = unsafe { Mmap::map(&File::open("README.md").unwrap()).unwrap() };
+        mmap.unmap().unwrap();
+    }
 
-        // check that the mmap is empty
-        assert_eq!(&zeros[..], &mmap[..]);
+    #[test]
+    #[cfg(unix)]
+    fn unmap_releases_memory() {
+        let page_size = page_size();
+        let mapped = |ptr: *const u8| {
+            // mincore fails with ENOMEM for unmapped addresses.
+            let mut residency = 0u8;
+            unsafe {
+                libc::mincore(ptr as *mut _, page_size, &mut residency as *mut _ as *mut _) == 0
+            }
+        };
 
-        // write values into the mmap
-        (&mut mmap[..]).write_all(&incr[..]).unwrap();
+        let mmap = MmapMut::map_anon(page_size).unwrap();
+        let ptr = mmap.as_ptr();
+        assert!(mapped(ptr));
+        mmap.unmap().unwrap();
+        assert!(!mapped(ptr));
 
-        // read values back
-        assert_eq!(&incr[..], &mmap[..]);
+        let mmap = unsafe { Mmap::map(&File::open("README.md").unwrap()).unwrap() };
+        let ptr = mmap.as_ptr();
+        mmap.unmap().unwrap();
+        assert!(!mapped(ptr));
     }
 
     #[test]
-    fn map_anon_zero_len() {
-        assert!(MmapOptions::new().map_anon().is_err())
+    #[cfg(unix)]
+    fn unmap_error() {
+        let mut mmap = MmapMut::map_anon(4096).unwrap();
+        mmap.inner.inject_unmap_error();
+        assert_eq!(ErrorKind::InvalidInput, mmap.unmap().unwrap_err().kind());
+
+        // Dropping a map which failed to unmap does not panic.
+        let mut mmap = MmapMut::map_anon(4096).unwrap();
+        mmap.inner.inject_unmap_error();
+        drop(mmap);
+
+        // Dropping an already unmapped map is a no-op.
+        let mut mmap = MmapMut::map_anon(4096).unwrap();
+        mmap.inner.close().unwrap();
+        mmap.inner.close().unwrap();
+        drop(mmap);
     }
 
+    /// Checks that a map which fails to unmap while the stack unwinds does not abort the process
+    /// with a double panic.
     #[test]
-    fn file_write() {
-        let tempdir = tempdir::TempDir::new("mmap").unwrap();
-        let path = tempdir.path().join("mmap");
+    #[cfg(unix)]
+    fn unmap_error_while_unwinding() {
+        let result = std::panic::catch_unwind(|| {
+            let mut mmap = MmapMut::map_anon(4096).unwrap();
+            mmap.inner.inject_unmap_error();
+            panic!("unwinding with a live memory map");
+        });
+        assert!(result.is_err());
+    }
 
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&path)
+    #[test]
+    fn populate_with_deadline() {
+        let mmap = MmapOptions::new()
+            .len(1 << 30)
+            .noreserve()
+            .map_anon()
             .unwrap();
-        file.set_len(128).unwrap();
+        assert!(!mmap.populate_with_deadline(Instant::now()).unwrap());
 
-        let write = b"abc123";
-        let mut read = [0u8; 6];
+        let mmap = MmapMut::map_anon(16 * page_size()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert!(mmap.populate_with_deadline(deadline).unwrap());
+        let mmap = mmap.make_read_only().unwrap();
+        assert!(mmap.populate_with_deadline(deadline).unwrap());
+    }
 
-        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
-        (&mut mmap[..]).write_all(write).unwrap();
-        mmap.flush().unwrap();
+    #[test]
+    fn prefault() {
+        let file = File::open("README.md").unwrap();
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        mmap.prefault();
+        mmap.prefault_range(1, mmap.len() - 1).unwrap();
+        mmap.prefault_range(mmap.len(), 0).unwrap();
+        let err = mmap.prefault_range(1, mmap.len()).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+        #[cfg(target_os = "linux")]
+        assert!(resident_pages(&mmap).iter().all(|&resident| resident));
 
-        file.read(&mut read).unwrap();
-        assert_eq!(write, &read);
+        // Prefaulting a copy-on-write memory map does not copy its pages.
+        let mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
+        mmap.prefault();
+        #[cfg(target_os = "linux")]
+        assert_eq!("0 kB", smaps_field(mmap.as_ptr(), "Private_Dirty"));
     }
 
     #[test]
-    fn flush_range() {
-        let tempdir = tempdir::TempDir::new("mmap").unwrap();
-        let path = tempdir.path().join("mmap");
+    #[cfg(unix)]
+    fn residency() {
+        let page_size = page_size();
+        let mut mmap = MmapMut::map_anon(4 * page_size).unwrap();
+        assert_eq!(4, mmap.residency().unwrap().len());
+        mmap[page_size] = 1;
+        assert!(mmap.residency().unwrap()[1]);
+        mmap.prefault();
+        assert!(mmap.residency().unwrap().iter().all(|&resident| resident));
 
+        // Partially overlapped pages at either end are included.
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(&path)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
             .unwrap();
-        file.set_len(128).unwrap();
-        let write = b"abc123";
-
-        let mut mmap = unsafe {
+        file.set_len(3 * page_size as u64).unwrap();
+        let mmap = unsafe {
             MmapOptions::new()
-                .offset(2)
-                .len(write.len())
-                .map_mut(&file)
+                .offset(page_size as u64 / 2)
+                .len(2 * page_size)
+                .map(&file)
                 .unwrap()
         };
-        (&mut mmap[..]).write_all(write).unwrap();
-        mmap.flush_range(0, write.len()).unwrap();
+        mmap.prefault();
+        assert_eq!(vec![true; 3], mmap.residency().unwrap());
     }
 
     #[test]
-    fn map_copy() {
+    #[cfg(unix)]
+    fn resident_percentage() {
+        let page_size = page_size();
         let tempdir = tempdir::TempDir::new("mmap").unwrap();
-        let path = tempdir.path().join("mmap");
-
         let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(&path)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
             .unwrap();
-        file.set_len(128).unwrap();
-
-        let nulls = b"\0\0\0\0\0\0";
-        let write = b"abc123";
-        let mut read = [0u8; 6];
-
-        let mut mmap = unsafe { MmapOptions::new().map_copy(&file).unwrap() };
-
-        (&mut mmap[..]).write(write).unwrap();
-        mmap.flush().unwrap();
+        file.write_all(&vec![0xAB; 64 * page_size]).unwrap();
+        file.sync_all().unwrap();
 
-        // The mmap contains the write
-        (&mmap[..]).read(&mut read).unwrap();
-        assert_eq!(write, &read);
+        let mmap = unsafe { Mmap::map(&file).unwrap() };
+        mmap.advise(Advice::Random).unwrap();
+        mmap.prefault_range(0, 32 * page_size).unwrap();
+        // Pages of the file may already be resident in the page cache.
+        let percentage = mmap.resident_percentage().unwrap();
+        assert!((0.5..=1.0).contains(&percentage), "{}", percentage);
 
-        // The file does not contain the write
-        file.read(&mut read).unwrap();
-        assert_eq!(nulls, &read);
+        let mmap = MmapMut::map_anon(64 * page_size).unwrap();
+        assert_eq!(0.0, mmap.resident_percentage().unwrap());
+        mmap.prefault_range(0, 32 * page_size).unwrap();
+        assert_eq!(0.5, mmap.resident_percentage().unwrap());
 
-        // another mmap does not contain the write
-        let mmap2 = unsafe { MmapOptions::new().map(&file).unwrap() };
-        (&mmap2[..]).read(&mut read).unwrap();
-        assert_eq!(nulls, &read);
+        let mmap = unsafe { MmapOptions::new().offset(1).len(0).map(&file).unwrap() };
+        assert_eq!(0.0, mmap.resident_percentage().unwrap());
     }
 
     #[test]
-    fn map_offset() {
+    fn map_mut_owned() {
         let tempdir = tempdir::TempDir::new("mmap").unwrap();
-        let path = tempdir.path().join("mmap");
-
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(&path)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
             .unwrap();
+        file.set_len(128).unwrap();
 
-        let offset = u32::max_value() as u64 + 2;
-        let len = 5432;
-        file.set_len(offset + len as u64).unwrap();
-
-        // Check inferred length mmap.
-        let mmap = unsafe { MmapOptions::new().offset(offset).map_mut(&file).unwrap() };
-        assert_eq!(len, mmap.len());
-
-        // Check explicit length mmap.
-        let mut mmap = unsafe {
-            MmapOptions::new()
-                .offset(offset)
-                .len(len)
-                .map_mut(&file)
-                .unwrap()
-        };
-        assert_eq!(len, mmap.len());
-
-        let zeros = vec![0; len];
-        let incr: Vec<_> = (0..len).map(|i| i as u8).collect();
-
-        // check that the mmap is empty
-        assert_eq!(&zeros[..], &mmap[..]);
-
-        // write values into the mmap
-        (&mut mmap[..]).write_all(&incr[..]).unwrap();
+        let mut mmap = unsafe { MmapOptions::new().offset(64).map_mut_owned(file).unwrap() };
+        assert_eq!(64, mmap.len());
+        mmap[..5].copy_from_slice(b"hello");
+        mmap.flush().unwrap();
+        assert_eq!(128, mmap.file().metadata().unwrap().len());
 
-        // read values back
-        assert_eq!(&incr[..], &mmap[..]);
+        let mut file = mmap.into_inner();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(b"hello", &contents[64..69]);
     }
 
     #[test]
@@ -961,19 +7767,26 @@ mod test {
     fn sync_send() {
         let mmap = Arc::new(MmapMut::map_anon(129).unwrap());
         thread::spawn(move || {
-            &mmap[..];
+            let _ = &mmap[..];
         });
     }
 
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    fn jit_x86(mut mmap: MmapMut) {
+    /// Writes a function returning `0xAB` into the memory map, makes it executable and calls it.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn jit(mut mmap: MmapMut) {
         use std::mem;
-        mmap[0] = 0xB8; // mov eax, 0xAB
-        mmap[1] = 0xAB;
-        mmap[2] = 0x00;
-        mmap[3] = 0x00;
-        mmap[4] = 0x00;
-        mmap[5] = 0xC3; // ret
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        let code = [
+            0xB8, 0xAB, 0x00, 0x00, 0x00, // mov eax, 0xAB
+            0xC3, // ret
+        ];
+        #[cfg(target_arch = "aarch64")]
+        let code = [
+            0x60, 0x15, 0x80, 0x52, // mov w0, #0xAB
+            0xC0, 0x03, 0x5F, 0xD6, // ret
+        ];
+        mmap[..code.len()].copy_from_slice(&code);
 
         let mmap = mmap.make_exec().expect("make_exec");
 
@@ -981,16 +7794,9 @@ mod test {
         assert_eq!(jitfn(), 0xab);
     }
 
-    #[test]
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    fn jit_x86_anon() {
-        jit_x86(MmapMut::map_anon(4096).unwrap());
-    }
-
-    #[test]
-    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    fn jit_x86_file() {
-        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+    /// Creates a file suitable for mapping as executable.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn jit_file(tempdir: &tempdir::TempDir) -> File {
         let mut options = OpenOptions::new();
         #[cfg(windows)]
         options.access_mode(GENERIC_ALL);
@@ -999,11 +7805,75 @@ mod test {
             .read(true)
             .write(true)
             .create(true)
-            .open(&tempdir.path().join("jit_x86"))
+            .truncate(true)
+            .open(tempdir.path().join("jit"))
             .expect("open");
 
         file.set_len(4096).expect("set_len");
-        jit_x86(unsafe { MmapMut::map_mut(&file).expect("map_mut") });
+        file
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn jit_anon() {
+        jit(MmapMut::map_anon(4096).unwrap());
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn jit_file_backed() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let file = jit_file(&tempdir);
+        jit(unsafe { MmapMut::map_mut(&file).expect("map_mut") });
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn jit_copy() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let file = jit_file(&tempdir);
+        jit(unsafe { MmapOptions::new().map_copy(&file).expect("map_copy") });
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    fn jit_private() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let file = jit_file(&tempdir);
+        let mut mmap = unsafe { MmapMut::map_mut(&file).expect("map_mut") };
+        mmap.make_private().expect("make_private");
+        jit(mmap);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn map_exec_requires_execute_access() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(tempdir.path().join("mmap"))
+            .unwrap();
+        file.set_len(4096).unwrap();
+
+        assert!(unsafe { MmapOptions::new().map_exec(&file) }.is_err());
+        let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        assert!(mmap.make_exec().is_err());
+    }
+
+    #[test]
+    #[cfg(all(windows, any(target_arch = "x86", target_arch = "x86_64")))]
+    fn jit_map_exec() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let mut file = jit_file(&tempdir);
+        file.write_all(&[0xB8, 0xAB, 0x00, 0x00, 0x00, 0xC3])
+            .unwrap();
+
+        let mmap = unsafe { MmapOptions::new().map_exec(&file).unwrap() };
+        let jitfn: extern "C" fn() -> u8 = unsafe { std::mem::transmute(mmap.as_ptr()) };
+        assert_eq!(jitfn(), 0xab);
     }
 
     #[test]
@@ -1019,9 +7889,10 @@ mod test {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&path)
             .expect("open");
-        file.set_len(256 as u64).expect("set_len");
+        file.set_len(256).expect("set_len");
 
         let mmap = unsafe { MmapMut::map_mut(&file).expect("map_mut") };
 
@@ -1031,20 +7902,20 @@ mod test {
         let write = b"abc123";
         let mut read = [0u8; 6];
 
-        (&mut mmap[..]).write(write).unwrap();
+        (&mut mmap[..]).write_all(write).unwrap();
         mmap.flush().unwrap();
 
         // The mmap contains the write
-        (&mmap[..]).read(&mut read).unwrap();
+        (&mmap[..]).read_exact(&mut read).unwrap();
         assert_eq!(write, &read);
 
         // The file should contain the write
-        file.read(&mut read).unwrap();
+        file.read_exact(&mut read).unwrap();
         assert_eq!(write, &read);
 
         // another mmap should contain the write
         let mmap2 = unsafe { MmapOptions::new().map(&file).unwrap() };
-        (&mmap2[..]).read(&mut read).unwrap();
+        (&mmap2[..]).read_exact(&mut read).unwrap();
         assert_eq!(write, &read);
 
         let mmap = mmap.make_exec().expect("make_exec");
@@ -1065,9 +7936,10 @@ mod test {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&path)
             .expect("open");
-        file.set_len(256 as u64).expect("set_len");
+        file.set_len(256).expect("set_len");
 
         let mmap = unsafe { MmapOptions::new().map_copy(&file).expect("map_mut") };
 
@@ -1078,20 +7950,20 @@ mod test {
         let write = b"abc123";
         let mut read = [0u8; 6];
 
-        (&mut mmap[..]).write(write).unwrap();
+        (&mut mmap[..]).write_all(write).unwrap();
         mmap.flush().unwrap();
 
         // The mmap contains the write
-        (&mmap[..]).read(&mut read).unwrap();
+        (&mmap[..]).read_exact(&mut read).unwrap();
         assert_eq!(write, &read);
 
         // The file does not contain the write
-        file.read(&mut read).unwrap();
+        file.read_exact(&mut read).unwrap();
         assert_eq!(nulls, &read);
 
         // another mmap does not contain the write
         let mmap2 = unsafe { MmapOptions::new().map(&file).unwrap() };
-        (&mmap2[..]).read(&mut read).unwrap();
+        (&mmap2[..]).read_exact(&mut read).unwrap();
         assert_eq!(nulls, &read);
 
         let mmap = mmap.make_exec().expect("make_exec");
@@ -1099,6 +7971,139 @@ mod test {
         drop(mmap);
     }
 
+    #[test]
+    fn map_mut_private() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(256).unwrap();
+
+        let mmap = unsafe { MmapOptions::new().private().map_mut(&file).unwrap() };
+        assert_eq!(MapKind::PrivateFile, mmap.inner.kind());
+
+        // Changing the protection keeps the memory map copy-on-write.
+        let mmap = mmap.make_read_only().unwrap();
+        let mut mmap = mmap.make_mut().unwrap();
+        mmap[..6].copy_from_slice(b"abc123");
+        mmap.flush().unwrap();
+        assert_eq!(b"abc123", &mmap[..6]);
+
+        let mut read = [0u8; 6];
+        file.read_exact(&mut read).unwrap();
+        assert_eq!(&[0u8; 6], &read);
+        let other = unsafe { MmapOptions::new().map(&file).unwrap() };
+        assert_eq!(&[0u8; 6], &other[..6]);
+    }
+
+    #[test]
+    fn protect_range() {
+        let page_size = page_size();
+        let mut mmap = MmapMut::map_anon(3 * page_size).unwrap();
+        mmap[..5].copy_from_slice(b"magic");
+
+        mmap.protect_range(0, page_size, Protection::Read).unwrap();
+        mmap[page_size] = 1;
+        mmap[3 * page_size - 1] = 2;
+        assert_eq!(b"magic", &mmap[..5]);
+
+        // Writing to the read-only page kills the child with SIGSEGV.
+        #[cfg(target_os = "linux")]
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+            0 => unsafe {
+                std::ptr::write_volatile(mmap.as_mut_ptr(), 0);
+                libc::_exit(0)
+            },
+            pid => {
+                let mut status = 0;
+                assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+                assert!(libc::WIFSIGNALED(status));
+                assert_eq!(libc::SIGSEGV, libc::WTERMSIG(status));
+            }
+        }
+
+        // An unaligned range covers the pages it overlaps.
+        mmap.protect_range(page_size - 1, 2, Protection::ReadWrite)
+            .unwrap();
+        mmap[0] = b'M';
+        mmap[2 * page_size - 1] = 3;
+        assert_eq!(b"Magic", &mmap[..5]);
+
+        let err = mmap
+            .protect_range(page_size, 2 * page_size + 1, Protection::Read)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn protection_of() {
+        let page_size = page_size();
+        let mut mmap = MmapMut::map_anon(3 * page_size).unwrap();
+        assert_eq!(
+            Protection::ReadWrite,
+            mmap.protection_of(0, 3 * page_size).unwrap()
+        );
+
+        mmap.protect_range(page_size, page_size, Protection::Read)
+            .unwrap();
+        assert_eq!(
+            Protection::Read,
+            mmap.protection_of(page_size + 1, page_size - 1).unwrap()
+        );
+        assert_eq!(
+            Protection::ReadWrite,
+            mmap.protection_of(2 * page_size, page_size).unwrap()
+        );
+        let err = mmap.protection_of(0, 2 * page_size).unwrap_err();
+        assert_eq!(ErrorKind::Other, err.kind());
+        let err = mmap.protection_of(page_size, 0).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let mmap = mmap.make_exec().unwrap();
+        assert_eq!(
+            Protection::ReadExec,
+            mmap.protection_of(0, mmap.len()).unwrap()
+        );
+
+        let mmap = unsafe { MmapOptions::new().map_copy(&File::open("README.md").unwrap()) };
+        let mmap = mmap.unwrap();
+        assert_eq!(Protection::CopyOnWrite, mmap.protection_of(0, 1).unwrap());
+    }
+
+    #[test]
+    fn invalidate() {
+        let tempdir = tempdir::TempDir::new("mmap").unwrap();
+        let path = tempdir.path().join("mmap");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(128).unwrap();
+
+        let mut writer = unsafe { MmapMut::map_mut(&file).unwrap() };
+        let reader = unsafe { Mmap::map(&file).unwrap() };
+        assert_eq!(&[0u8; 6], &reader[..6]);
+
+        writer[..6].copy_from_slice(b"abc123");
+        writer.flush().unwrap();
+        reader.invalidate().unwrap();
+        assert_eq!(b"abc123", &reader[..6]);
+
+        writer.invalidate().unwrap();
+        assert_eq!(b"abc123", &writer[..6]);
+    }
+
     #[test]
     fn mprotect_anon() {
         let mmap = MmapMut::map_anon(256).expect("map_mut");
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Result;
+use std::ops::{Deref, DerefMut};
+
+use crate::MmapMut;
+
+/// A writable memory map which owns the file backing it.
+///
+/// Dropping the memory map also closes the file, which makes this convenient for short-lived
+/// memory maps. [`into_inner()`](MmapMutOwned::into_inner()) unmaps the memory map and returns
+/// the file instead.
+///
+/// Use [`MmapOptions::map_mut_owned()`](crate::MmapOptions::map_mut_owned()) to create an owned
+/// memory map.
+///
+/// ## Example
+///
+/// ```
+/// use std::fs::OpenOptions;
+/// use std::io::Read;
+///
+/// use mapr::MmapOptions;
+/// #
+/// # fn main() -> std::io::Result<()> {
+/// # let tempdir = tempdir::TempDir::new("mmap")?;
+/// let file = OpenOptions::new()
+///     .read(true)
+///     .write(true)
+///     .create(true)
+///     .truncate(true)
+///     .open(tempdir.path().join("owned"))?;
+/// file.set_len(5)?;
+///
+/// let mut mmap = unsafe { MmapOptions::new().map_mut_owned(file)? };
+/// mmap.copy_from_slice(b"hello");
+/// mmap.flush()?;
+///
+/// let mut contents = String::new();
+/// mmap.into_inner().read_to_string(&mut contents)?;
+/// assert_eq!("hello", contents);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MmapMutOwned {
+    mmap: MmapMut,
+    file: File,
+}
+
+impl MmapMutOwned {
+    pub(crate) fn new(mmap: MmapMut, file: File) -> MmapMutOwned {
+        MmapMutOwned { mmap, file }
+    }
+
+    /// Flushes outstanding memory map modifications to disk, see [`MmapMut::flush()`].
+    pub fn flush(&self) -> Result<()> {
+        self.mmap.flush()
+    }
+
+    /// Asynchronously flushes outstanding memory map modifications to disk, see
+    /// [`MmapMut::flush_async()`].
+    pub fn flush_async(&self) -> Result<()> {
+        self.mmap.flush_async()
+    }
+
+    /// Returns the file backing the memory map.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Unmaps the memory map and returns the file backing it.
+    ///
+    /// Modifications which were not flushed are still written back to the file eventually, but
+    /// not necessarily before this method returns.
+    pub fn into_inner(self) -> File {
+        drop(self.mmap);
+        self.file
+    }
+}
+
+impl Deref for MmapMutOwned {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl DerefMut for MmapMutOwned {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+impl AsRef<[u8]> for MmapMutOwned {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl AsMut<[u8]> for MmapMutOwned {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.deref_mut()
+    }
+}
+
+impl fmt::Debug for MmapMutOwned {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("MmapMutOwned")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len())
+            .field("file", &self.file)
+            .finish()
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::slice;
+use std::sync::Arc;
+
+use crate::MmapInner;
+
+/// A writable region of a memory map, created by [`MmapMut::split_at_mut()`].
+///
+/// The regions split from a memory map cover disjoint byte ranges of it, so they can be handed to
+/// different threads and written concurrently. They share ownership of the memory map, which is
+/// unmapped once the last of them is dropped.
+///
+/// [`MmapMut::split_at_mut()`]: crate::MmapMut::split_at_mut()
+///
+/// ## Example
+///
+/// ```
+/// use std::thread;
+///
+/// use mapr::MmapMut;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let (mut left, mut right) = MmapMut::map_anon(8192)?.split_at_mut(4096);
+///
+/// let handle = thread::spawn(move || {
+///     right.fill(2);
+///     right
+/// });
+/// left.fill(1);
+/// let right = handle.join().unwrap();
+///
+/// assert!(left.iter().all(|&b| b == 1));
+/// assert!(right.iter().all(|&b| b == 2));
+/// # Ok(())
+/// # }
+/// ```
+pub struct MmapRegion {
+    inner: Arc<MmapInner>,
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl MmapRegion {
+    pub(crate) fn new(inner: Arc<MmapInner>, offset: usize, len: usize) -> MmapRegion {
+        debug_assert!(offset + len <= inner.len());
+        let ptr = unsafe { (inner.ptr() as *mut u8).add(offset) };
+        MmapRegion { inner, ptr, len }
+    }
+
+    /// Splits the region into two at `mid`, the first covering `[0, mid)` and the second
+    /// `[mid, len)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    pub fn split_at_mut(self, mid: usize) -> (MmapRegion, MmapRegion) {
+        assert!(mid <= self.len, "split point is out of bounds");
+        let offset = self.ptr as usize - self.inner.ptr() as usize;
+        let right = MmapRegion::new(self.inner.clone(), offset + mid, self.len - mid);
+        let left = MmapRegion::new(self.inner, offset, mid);
+        (left, right)
+    }
+}
+
+// The region has exclusive access to its bytes, and `MmapInner` is `Send` and `Sync`.
+unsafe impl Send for MmapRegion {}
+unsafe impl Sync for MmapRegion {}
+
+impl Deref for MmapRegion {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl DerefMut for MmapRegion {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl AsRef<[u8]> for MmapRegion {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl AsMut<[u8]> for MmapRegion {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.deref_mut()
+    }
+}
+
+impl fmt::Debug for MmapRegion {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("MmapRegion")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::Result;
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::unix;
+
+/// A set of seals restricting how a memfd file may be modified, applied with [`seal()`].
+///
+/// Seals can only be added, never removed, so a process receiving a sealed file can rely on them,
+/// e.g. to map a read-only snapshot shared by an untrusted process without the contents changing
+/// or the file shrinking under it.
+///
+/// These correspond to the `F_SEAL_*` flags accepted by `fcntl(F_ADD_SEALS)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Seals(libc::c_int);
+
+impl Seals {
+    /// Prevents adding any further seals.
+    pub const SEAL: Seals = Seals(libc::F_SEAL_SEAL);
+    /// Prevents shrinking the file.
+    pub const SHRINK: Seals = Seals(libc::F_SEAL_SHRINK);
+    /// Prevents growing the file.
+    pub const GROW: Seals = Seals(libc::F_SEAL_GROW);
+    /// Prevents writing to the file, including through shared writable memory maps.
+    ///
+    /// Adding this seal fails while a shared writable memory map of the file exists.
+    pub const WRITE: Seals = Seals(libc::F_SEAL_WRITE);
+
+    /// Returns the empty set of seals.
+    pub fn empty() -> Seals {
+        Seals(0)
+    }
+
+    /// Returns whether every seal in `other` is also in `self`.
+    pub fn contains(self, other: Seals) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Seals {
+    type Output = Seals;
+
+    fn bitor(self, rhs: Seals) -> Seals {
+        Seals(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Seals {
+    fn bitor_assign(&mut self, rhs: Seals) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Adds `seals` to the seals of a memfd file.
+///
+/// The file must have been created with `MFD_ALLOW_SEALING`, as by
+/// [`MmapOptions::map_memfd()`](crate::MmapOptions::map_memfd()).
+///
+/// # Errors
+///
+/// This method returns the OS error of `fcntl(F_ADD_SEALS)`: `EINVAL` if the file does not
+/// support sealing, `EPERM` if the file was created without `MFD_ALLOW_SEALING` or has been
+/// sealed with [`Seals::SEAL`], and `EBUSY` when adding [`Seals::WRITE`] while a shared writable
+/// memory map of the file exists.
+///
+/// # Example
+///
+/// ```
+/// use mapr::{seal, MmapOptions, Seals};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let (mmap, file) = MmapOptions::new().map_memfd("snapshot", 4096)?;
+/// drop(mmap);
+///
+/// seal(&file, Seals::SHRINK | Seals::GROW | Seals::WRITE | Seals::SEAL)?;
+/// assert!(file.set_len(8192).is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub fn seal(file: &File, seals: Seals) -> Result<()> {
+    unix::add_seals(file, seals.0)
+}
+
+/// Returns the seals of a memfd file.
+///
+/// # Errors
+///
+/// This method returns the OS error of `fcntl(F_GET_SEALS)`, e.g. `EINVAL` if the file does not
+/// support sealing.
+pub fn seals(file: &File) -> Result<Seals> {
+    unix::get_seals(file).map(Seals)
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::io::Result;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::{compiler_fence, Ordering};
+
+use crate::MmapMut;
+
+/// A writable memory map for sensitive data, such as key material, which is zeroed before it is
+/// unmapped.
+///
+/// The bytes are wiped with volatile writes when the memory map is dropped, so the compiler can
+/// not optimize the wipe away, and the memory is never returned to the operating system with the
+/// data still in it.
+///
+/// [`SecureMmapMut::new()`] creates an anonymous memory map locked into RAM, so the data is not
+/// written to swap either. An existing memory map can be converted with [`From`], in which case it
+/// is only wiped on drop: it is not locked, and for file-backed memory maps the zeros are written
+/// through to the file.
+///
+/// ## Example
+///
+/// ```
+/// use mapr::SecureMmapMut;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut key = SecureMmapMut::new(32)?;
+/// key.copy_from_slice(&[0x42; 32]);
+/// // The key is zeroed and unmapped here.
+/// drop(key);
+/// # Ok(())
+/// # }
+/// ```
+pub struct SecureMmapMut {
+    mmap: MmapMut,
+}
+
+impl SecureMmapMut {
+    /// Creates an anonymous memory map of `len` bytes, locked into RAM with
+    /// [`MmapMut::mlock()`].
+    ///
+    /// # Errors
+    ///
+    /// This method returns an error when mapping or locking the memory fails, e.g. when locking
+    /// it would exceed `RLIMIT_MEMLOCK` on unix.
+    pub fn new(len: usize) -> Result<SecureMmapMut> {
+        let mut mmap = MmapMut::map_anon(len)?;
+        mmap.mlock()?;
+        Ok(SecureMmapMut { mmap })
+    }
+
+    /// Zeroes every byte of the memory map with volatile writes.
+    ///
+    /// This is done automatically when the memory map is dropped.
+    pub fn wipe(&mut self) {
+        for byte in self.mmap.iter_mut() {
+            unsafe { ptr::write_volatile(byte, 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl From<MmapMut> for SecureMmapMut {
+    fn from(mmap: MmapMut) -> SecureMmapMut {
+        SecureMmapMut { mmap }
+    }
+}
+
+impl Drop for SecureMmapMut {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+impl Deref for SecureMmapMut {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+impl DerefMut for SecureMmapMut {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+impl AsRef<[u8]> for SecureMmapMut {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl AsMut<[u8]> for SecureMmapMut {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.deref_mut()
+    }
+}
+
+// The contents are deliberately left out.
+impl fmt::Debug for SecureMmapMut {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SecureMmapMut")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len())
+            .finish()
+    }
+}
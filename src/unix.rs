@@ -1,8 +1,13 @@
 extern crate libc;
 
-use std::fs::File;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::{io, ptr};
+use std::error::Error;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fmt, io, process, ptr};
+
+use crate::{HumanSize, MapKind, Protection};
 
 #[cfg(any(
     all(target_os = "linux", not(target_arch = "mips")),
@@ -18,28 +23,195 @@ const MAP_STACK: libc::c_int = libc::MAP_STACK;
 )))]
 const MAP_STACK: libc::c_int = 0;
 
-
 #[cfg(any(
     all(target_os = "linux", not(target_arch = "mips")),
-    target_os = "freebsd",
     target_os = "android"
 ))]
 const MAP_LOCKED: libc::c_int = libc::MAP_LOCKED;
-const MAP_HUGETLB: libc::c_int = libc::MAP_HUGETLB;
-const MAP_HUGE_1GB: libc::c_int = libc::MAP_HUGE_1GB;
-const MAP_HUGE_2MB: libc::c_int = libc::MAP_HUGE_2MB;
-const MAP_NORESERVE: libc::c_int = libc::MAP_NORESERVE;
 
 #[cfg(not(any(
     all(target_os = "linux", not(target_arch = "mips")),
-    target_os = "freebsd",
     target_os = "android"
 )))]
 const MAP_LOCKED: libc::c_int = 0;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAP_HUGETLB: libc::c_int = libc::MAP_HUGETLB;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAP_HUGE_SHIFT: libc::c_int = libc::MAP_HUGE_SHIFT;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAP_NORESERVE: libc::c_int = libc::MAP_NORESERVE;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MAP_HUGETLB: libc::c_int = 0;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MAP_HUGE_SHIFT: libc::c_int = 0;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MAP_NORESERVE: libc::c_int = 0;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MAP_POPULATE: libc::c_int = libc::MAP_POPULATE;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MAP_POPULATE: libc::c_int = 0;
+
+#[cfg(target_os = "linux")]
+const MAP_FIXED_NOREPLACE: libc::c_int = libc::MAP_FIXED_NOREPLACE;
+
+#[cfg(not(target_os = "linux"))]
+const MAP_FIXED_NOREPLACE: libc::c_int = 0;
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const MAP_32BIT: libc::c_int = libc::MAP_32BIT;
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+const MAP_32BIT: libc::c_int = 0;
+
+#[cfg(target_os = "linux")]
+const MAP_GROWSDOWN: libc::c_int = libc::MAP_GROWSDOWN;
+
+#[cfg(not(target_os = "linux"))]
+const MAP_GROWSDOWN: libc::c_int = 0;
+
+// MAP_SYNC shares its value with MAP_HUGETLB on MIPS, where it is not supported.
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "mips", target_arch = "mips64"))
+))]
+const MAP_SYNC: libc::c_int = 0x80000;
+#[cfg(all(
+    target_os = "linux",
+    not(any(target_arch = "mips", target_arch = "mips64"))
+))]
+const MAP_SHARED_VALIDATE: libc::c_int = libc::MAP_SHARED_VALIDATE;
+
+#[cfg(not(all(
+    target_os = "linux",
+    not(any(target_arch = "mips", target_arch = "mips64"))
+)))]
+const MAP_SYNC: libc::c_int = 0;
+#[cfg(not(all(
+    target_os = "linux",
+    not(any(target_arch = "mips", target_arch = "mips64"))
+)))]
+const MAP_SHARED_VALIDATE: libc::c_int = libc::MAP_SHARED;
+
+// The bits of the flags selecting the type of the map; MAP_SHARED_VALIDATE sets both MAP_SHARED
+// and MAP_PRIVATE, so a map is private only when the type is exactly MAP_PRIVATE.
+#[cfg(target_os = "linux")]
+const MAP_TYPE: libc::c_int = libc::MAP_TYPE;
+
+#[cfg(not(target_os = "linux"))]
+const MAP_TYPE: libc::c_int = libc::MAP_SHARED | libc::MAP_PRIVATE;
+
+/// Returns whether `flags` create a private, copy-on-write map.
+fn is_private(flags: libc::c_int) -> bool {
+    flags & MAP_TYPE == libc::MAP_PRIVATE
+}
+
+/// An `mlock` failure annotated with the `RLIMIT_MEMLOCK` soft limit which likely caused it.
+#[derive(Debug)]
+struct MemlockError {
+    limit: libc::rlim_t,
+    source: io::Error,
+}
+
+impl fmt::Display for MemlockError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "mlock failed: would exceed RLIMIT_MEMLOCK ({}): {}",
+            HumanSize(self.limit as usize),
+            self.source
+        )
+    }
+}
+
+impl Error for MemlockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Adds the `RLIMIT_MEMLOCK` soft limit to an `mlock` failure, if the limit could have caused it.
+///
+/// The original error, including its OS error code, stays available through `Error::source`.
+fn memlock_error(err: io::Error) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::EPERM) | Some(libc::ENOMEM) => {}
+        _ => return err,
+    }
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return err;
+    }
+    io::Error::new(
+        err.kind(),
+        MemlockError {
+            limit: limit.rlim_cur,
+            source: err,
+        },
+    )
+}
+
+/// Returns the regions listed in `/proc/self/maps` overlapping `start..end`, clipped to that
+/// range, in ascending order of address, along with their permissions, e.g. `rw-p`.
+#[cfg(target_os = "linux")]
+fn mapped_regions(start: usize, end: usize) -> io::Result<Vec<(usize, usize, String)>> {
+    let mut regions = Vec::new();
+    for line in fs::read_to_string("/proc/self/maps")?.lines() {
+        let mut fields = line.split_whitespace();
+        let (range, perms) = match (fields.next(), fields.next()) {
+            (Some(range), Some(perms)) => (range, perms),
+            _ => continue,
+        };
+        let (region_start, region_end) = match range.split_once('-') {
+            Some((region_start, region_end)) => (
+                usize::from_str_radix(region_start, 16).unwrap_or(usize::MAX),
+                usize::from_str_radix(region_end, 16).unwrap_or(0),
+            ),
+            None => continue,
+        };
+        if region_start >= end {
+            break;
+        }
+        if region_end > start {
+            regions.push((
+                region_start.max(start),
+                region_end.min(end),
+                perms.to_string(),
+            ));
+        }
+    }
+    Ok(regions)
+}
+
+/// Returns the `mmap` flags selecting huge pages of `1 << shift` bytes, if any.
+fn huge_flags(huge_shift: Option<u8>) -> libc::c_int {
+    match huge_shift {
+        // Huge pages are not supported on this platform.
+        Some(_) if MAP_HUGETLB == 0 => 0,
+        Some(shift) => MAP_HUGETLB | (libc::c_int::from(shift) << MAP_HUGE_SHIFT),
+        None => 0,
+    }
+}
+
 pub struct MmapInner {
+    file: Option<File>,
     ptr: *mut libc::c_void,
     len: usize,
+    offset: u64,
+    flags: libc::c_int,
+    /// Bytes mapped before `ptr`.
+    head: usize,
+    /// Bytes mapped after `ptr + len`.
+    tail: usize,
+    unmapped: bool,
 }
 
 impl MmapInner {
@@ -47,10 +219,11 @@ impl MmapInner {
     ///
     /// This is a thin wrapper around the `mmap` sytem call.
     fn new(
+        addr: usize,
         len: usize,
         prot: libc::c_int,
         flags: libc::c_int,
-        file: RawFd,
+        file: Option<&File>,
         offset: u64,
     ) -> io::Result<MmapInner> {
         let alignment = offset % page_size() as u64;
@@ -64,158 +237,323 @@ impl MmapInner {
             ));
         }
 
-        unsafe {
-            let ptr = libc::mmap(
-                ptr::null_mut(),
-                aligned_len as libc::size_t,
-                prot,
-                flags,
-                file,
-                aligned_offset as libc::off_t,
-            );
+        if let Some(file) = file {
+            let write = prot & libc::PROT_WRITE != 0 && !is_private(flags);
+            check_access(file, write)?;
+        }
 
-            if ptr == libc::MAP_FAILED {
-                Err(io::Error::last_os_error())
-            } else {
-                Ok(MmapInner {
-                    ptr: ptr.offset(alignment as isize),
-                    len: len,
-                })
+        // Duplicate the descriptor before mapping, so the map stays independent of `file`.
+        let fd: RawFd = file.map_or(-1, |file| file.as_raw_fd());
+        let file = match file {
+            Some(file) => Some(file.try_clone()?),
+            None => None,
+        };
+
+        unsafe {
+            let mut retries = 0;
+            let ptr = loop {
+                let ptr = libc::mmap(
+                    addr as *mut libc::c_void,
+                    aligned_len as libc::size_t,
+                    prot,
+                    flags,
+                    fd,
+                    aligned_offset as libc::off_t,
+                );
+                if ptr != libc::MAP_FAILED {
+                    break ptr;
+                }
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::EINTR) || retries == EINTR_RETRIES {
+                    return Err(err);
+                }
+                retries += 1;
+            };
+            // Kernels before 4.17 ignore MAP_FIXED_NOREPLACE, and treat the address as a hint.
+            #[cfg(target_os = "linux")]
+            if flags & MAP_FIXED_NOREPLACE != 0 && ptr as usize != addr {
+                libc::munmap(ptr, aligned_len as libc::size_t);
+                return Err(io::Error::from_raw_os_error(libc::EEXIST));
             }
+            Ok(MmapInner {
+                file,
+                ptr: ptr.offset(alignment as isize),
+                len,
+                offset,
+                // Remapping, e.g. to resize, must not be pinned to the original address.
+                flags: flags & !MAP_FIXED_NOREPLACE,
+                head: alignment as usize,
+                tail: 0,
+                unmapped: false,
+            })
         }
     }
 
-    pub fn map(len: usize, file: &File, offset: u64, locked: bool, private: bool, huge: u8, noreserve: bool) -> io::Result<MmapInner> {
-        let locked = if locked { MAP_LOCKED } else { 0 };
-        let private = if private { libc::MAP_PRIVATE } else { libc::MAP_SHARED };
-        let huge = match huge {
-            1 => MAP_HUGETLB | MAP_HUGE_2MB,
-            2 => MAP_HUGETLB | MAP_HUGE_1GB,
-            _ => 0,
+    /// Maps `len` bytes of `file` starting at `offset` with `protection`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_file(
+        len: usize,
+        file: &File,
+        offset: u64,
+        protection: Protection,
+        locked: bool,
+        private: bool,
+        huge_shift: Option<u8>,
+        noreserve: bool,
+        populate: bool,
+        sync: bool,
+        addr: usize,
+        fixed_noreplace: bool,
+        low_32bit: bool,
+    ) -> io::Result<MmapInner> {
+        let prot = match protection {
+            Protection::Read => libc::PROT_READ,
+            Protection::ReadExec => libc::PROT_READ | libc::PROT_EXEC,
+            Protection::ReadWrite | Protection::CopyOnWrite => libc::PROT_READ | libc::PROT_WRITE,
         };
-        let noreserve = if noreserve { MAP_NORESERVE } else { 0 };
-        MmapInner::new(
-            len,
-            libc::PROT_READ,
-            locked | private | huge | noreserve,
-            file.as_raw_fd(),
-            offset,
-        )
-    }
-
-    pub fn map_exec(len: usize, file: &File, offset: u64, locked: bool, private: bool, huge: u8, noreserve: bool) -> io::Result<MmapInner> {
+        let private = private || protection == Protection::CopyOnWrite;
         let locked = if locked { MAP_LOCKED } else { 0 };
-        let private = if private { libc::MAP_PRIVATE } else { libc::MAP_SHARED };
-        let huge = match huge {
-            1 => MAP_HUGETLB | MAP_HUGE_2MB,
-            2 => MAP_HUGETLB | MAP_HUGE_1GB,
-            _ => 0,
-        };
+        let huge = huge_flags(huge_shift);
         let noreserve = if noreserve { MAP_NORESERVE } else { 0 };
-        MmapInner::new(
-            len,
-            libc::PROT_READ | libc::PROT_EXEC,
-            locked | private | huge | noreserve,
-            file.as_raw_fd(),
-            offset,
-        )
-    }
-
-    pub fn map_mut(len: usize, file: &File, offset: u64, locked: bool, private: bool, huge: u8, noreserve: bool) -> io::Result<MmapInner> {
-        let locked = if locked { MAP_LOCKED } else { 0 };
-        let private = if private { libc::MAP_PRIVATE } else { libc::MAP_SHARED };
-        let huge = match huge {
-            1 => MAP_HUGETLB | MAP_HUGE_2MB,
-            2 => MAP_HUGETLB | MAP_HUGE_1GB,
-            _ => 0,
+        let populate = if populate { MAP_POPULATE } else { 0 };
+        let fixed = if fixed_noreplace {
+            MAP_FIXED_NOREPLACE
+        } else {
+            0
         };
-        let noreserve = if noreserve { MAP_NORESERVE } else { 0 };
-        MmapInner::new(
-            len,
-            libc::PROT_READ | libc::PROT_WRITE,
-            locked | private | huge | noreserve,
-            file.as_raw_fd(),
-            offset,
-        )
-    }
+        let low = if low_32bit { MAP_32BIT } else { 0 };
+        let flags = locked | huge | noreserve | populate | fixed | low;
 
-    pub fn map_copy(len: usize, file: &File, offset: u64, locked: bool, huge: u8, noreserve: bool) -> io::Result<MmapInner> {
-        let locked = if locked { MAP_LOCKED } else { 0 };
-        let huge = match huge {
-            1 => MAP_HUGETLB | MAP_HUGE_2MB,
-            2 => MAP_HUGETLB | MAP_HUGE_1GB,
-            _ => 0,
+        if sync && protection == Protection::ReadWrite && !private && MAP_SYNC != 0 {
+            let result = MmapInner::new(
+                addr,
+                len,
+                prot,
+                MAP_SHARED_VALIDATE | MAP_SYNC | flags,
+                Some(file),
+                offset,
+            );
+            match result {
+                // The file system does not support synchronous page faults, or the kernel predates
+                // MAP_SYNC; fall back to a regular shared map, which `is_sync()` reports.
+                Err(ref err)
+                    if err.raw_os_error() == Some(libc::EOPNOTSUPP)
+                        || err.raw_os_error() == Some(libc::EINVAL) => {}
+                result => return result,
+            }
+        }
+        let private = if private {
+            libc::MAP_PRIVATE
+        } else {
+            libc::MAP_SHARED
         };
-        let noreserve = if noreserve { MAP_NORESERVE } else { 0 };
-        MmapInner::new(
-            len,
-            libc::PROT_READ | libc::PROT_WRITE,
-            libc::MAP_PRIVATE | locked | huge | noreserve,
-            file.as_raw_fd(),
-            offset,
-        )
+        MmapInner::new(addr, len, prot, private | flags, Some(file), offset)
     }
 
     /// Open an anonymous memory map.
-    pub fn map_anon(len: usize, stack: bool, locked: bool, private: bool, huge: u8, noreserve: bool) -> io::Result<MmapInner> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_anon(
+        len: usize,
+        stack: bool,
+        grows_down: bool,
+        locked: bool,
+        private: bool,
+        huge_shift: Option<u8>,
+        noreserve: bool,
+        populate: bool,
+        addr: usize,
+        fixed_noreplace: bool,
+        low_32bit: bool,
+    ) -> io::Result<MmapInner> {
         let stack = if stack { MAP_STACK } else { 0 };
+        let grows_down = if grows_down { MAP_GROWSDOWN } else { 0 };
         let locked = if locked { MAP_LOCKED } else { 0 };
-        let private = if private { libc::MAP_PRIVATE } else { libc::MAP_SHARED };
-        let huge = match huge {
-            1 => MAP_HUGETLB | MAP_HUGE_2MB,
-            2 => MAP_HUGETLB | MAP_HUGE_1GB,
-            _ => 0,
+        let private = if private {
+            libc::MAP_PRIVATE
+        } else {
+            libc::MAP_SHARED
         };
+        let huge = huge_flags(huge_shift);
         let noreserve = if noreserve { MAP_NORESERVE } else { 0 };
+        let populate = if populate { MAP_POPULATE } else { 0 };
+        let fixed = if fixed_noreplace {
+            MAP_FIXED_NOREPLACE
+        } else {
+            0
+        };
+        let low = if low_32bit { MAP_32BIT } else { 0 };
         MmapInner::new(
+            addr,
             len,
             libc::PROT_READ | libc::PROT_WRITE,
-            libc::MAP_ANON | stack | locked | private | huge | noreserve,
-            -1,
+            libc::MAP_ANON
+                | stack
+                | grows_down
+                | locked
+                | private
+                | huge
+                | noreserve
+                | populate
+                | fixed
+                | low,
+            None,
             0,
         )
     }
 
     pub fn flush(&self, offset: usize, len: usize) -> io::Result<()> {
-        let alignment = (self.ptr as usize + offset) % page_size();
-        let offset = offset as isize - alignment as isize;
-        let len = len + alignment;
-        let result =
-            unsafe { libc::msync(self.ptr.offset(offset), len as libc::size_t, libc::MS_SYNC) };
-        if result == 0 {
-            Ok(())
-        } else {
-            Err(io::Error::last_os_error())
-        }
+        self.msync(offset, len, libc::MS_SYNC)
     }
 
     pub fn flush_async(&self, offset: usize, len: usize) -> io::Result<()> {
-        let alignment = offset % page_size();
-        let aligned_offset = offset - alignment;
-        let aligned_len = len + alignment;
-        let result = unsafe {
+        self.msync(offset, len, libc::MS_ASYNC)
+    }
+
+    pub fn invalidate(&self) -> io::Result<()> {
+        self.msync(0, self.len, libc::MS_INVALIDATE)
+    }
+
+    /// Calls `msync` on the pages covering `offset..offset + len`, starting at the page boundary
+    /// at or below `ptr + offset`, since the pointer itself need not be page aligned.
+    fn msync(&self, offset: usize, len: usize, flags: libc::c_int) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
+        retry_eintr(|| unsafe {
             libc::msync(
-                self.ptr.offset(aligned_offset as isize),
-                aligned_len as libc::size_t,
-                libc::MS_ASYNC,
+                self.ptr.add(offset).sub(alignment),
+                (len + alignment) as libc::size_t,
+                flags,
             )
-        };
-        if result == 0 {
-            Ok(())
-        } else {
-            Err(io::Error::last_os_error())
+        })
+    }
+
+    /// Wraps the existing memory map of `len` bytes at `ptr`, which is unmapped on drop.
+    ///
+    /// The memory map is treated as shared anonymous memory, so that it is never grown in place.
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> MmapInner {
+        debug_assert!(len > 0);
+        MmapInner {
+            file: None,
+            ptr: ptr as *mut libc::c_void,
+            len,
+            offset: 0,
+            flags: libc::MAP_SHARED | libc::MAP_ANON,
+            // The memory map starts at the page boundary at or below `ptr`.
+            head: ptr as usize % page_size(),
+            tail: 0,
+            unmapped: false,
+        }
+    }
+
+    /// Reserves `len` bytes of address space without backing them with memory.
+    ///
+    /// The pages are inaccessible until they are committed with `commit`.
+    pub fn reserve(len: usize) -> io::Result<MmapInner> {
+        MmapInner::new(
+            0,
+            len,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON | MAP_NORESERVE,
+            None,
+            0,
+        )
+    }
+
+    /// Maps a shared anonymous file of `len` bytes twice into adjacent address space, so that the
+    /// `len` bytes after the memory map mirror it.
+    ///
+    /// `len` must be a non-zero multiple of the page size.
+    pub fn mirrored(len: usize) -> io::Result<MmapInner> {
+        let file = anonymous_file(len as u64)?;
+        let mut inner = MmapInner::new(
+            0,
+            2 * len,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANON | MAP_NORESERVE,
+            None,
+            0,
+        )?;
+        for half in 0..2 {
+            let ptr = unsafe {
+                libc::mmap(
+                    inner.ptr.add(half * len),
+                    len as libc::size_t,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_FIXED,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
         }
+        inner.file = Some(file);
+        inner.flags = libc::MAP_SHARED;
+        inner.len = len;
+        inner.tail = len;
+        Ok(inner)
+    }
+
+    /// Makes the reserved pages covering `offset..offset + len` readable and writable.
+    pub fn commit(&mut self, offset: usize, len: usize) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
+        let ptr = unsafe { self.ptr.add(offset).offset(-(alignment as isize)) };
+        retry_eintr(|| unsafe {
+            libc::mprotect(ptr, len + alignment, libc::PROT_READ | libc::PROT_WRITE)
+        })
     }
 
-    fn mprotect(&mut self, prot: libc::c_int) -> io::Result<()> {
+    /// Releases the memory backing every page of a reserved map, making the pages inaccessible
+    /// until they are committed again.
+    pub fn decommit(&mut self) -> io::Result<()> {
+        let alignment = self.ptr as usize % page_size();
         unsafe {
-            let alignment = self.ptr as usize % page_size();
-            let ptr = self.ptr.offset(-(alignment as isize));
-            let len = self.len + alignment;
-            if libc::mprotect(ptr, len, prot) == 0 {
-                Ok(())
-            } else {
+            let ptr = libc::mmap(
+                self.ptr.offset(-(alignment as isize)),
+                (self.len + alignment) as libc::size_t,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANON | MAP_NORESERVE | libc::MAP_FIXED,
+                -1,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
                 Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn mprotect(&self, prot: libc::c_int) -> io::Result<()> {
+        self.mprotect_range(0, self.len, prot)
+    }
+
+    /// Calls `mprotect` with `prot` for the pages covering `offset..offset + len`.
+    fn mprotect_range(&self, offset: usize, len: usize, prot: libc::c_int) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
+        let ptr = unsafe { self.ptr.add(offset).offset(-(alignment as isize)) };
+        retry_eintr(|| unsafe { libc::mprotect(ptr, len + alignment, prot) })
+    }
+
+    pub fn protect_range(
+        &mut self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> io::Result<()> {
+        match protection {
+            Protection::Read => self.mprotect_range(offset, len, libc::PROT_READ),
+            Protection::ReadWrite | Protection::CopyOnWrite => {
+                self.mprotect_range(offset, len, libc::PROT_READ | libc::PROT_WRITE)
+            }
+            Protection::ReadExec => {
+                self.mprotect_range(offset, len, libc::PROT_READ | libc::PROT_EXEC)?;
+                #[cfg(target_arch = "aarch64")]
+                unsafe {
+                    let start = self.ptr as usize + offset;
+                    clear_icache(start, start + len)
+                };
+                Ok(())
             }
         }
     }
@@ -225,13 +563,99 @@ impl MmapInner {
     }
 
     pub fn make_exec(&mut self) -> io::Result<()> {
-        self.mprotect(libc::PROT_READ | libc::PROT_EXEC)
+        self.mprotect(libc::PROT_READ | libc::PROT_EXEC)?;
+        // Code written through the data cache is not guaranteed to be visible to instruction
+        // fetches on architectures without a coherent instruction cache.
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            clear_icache(self.ptr as usize, self.ptr as usize + self.len)
+        };
+        Ok(())
     }
 
     pub fn make_mut(&mut self) -> io::Result<()> {
         self.mprotect(libc::PROT_READ | libc::PROT_WRITE)
     }
 
+    /// Removes `PROT_EXEC` from the pages of the memory map, keeping the rest of the protection of
+    /// each region listed in `/proc/self/maps`, e.g. ranges made read-only with `protect_range`.
+    #[cfg(target_os = "linux")]
+    pub fn ensure_no_exec(&self, _writable: bool) -> io::Result<()> {
+        let start = self.ptr as usize;
+        for (region_start, region_end, perms) in
+            mapped_regions(start - start % page_size(), start + self.len)?
+        {
+            let perms = perms.as_bytes();
+            if perms.get(2) != Some(&b'x') {
+                continue;
+            }
+            let mut prot = libc::PROT_NONE;
+            if perms[0] == b'r' {
+                prot |= libc::PROT_READ;
+            }
+            if perms[1] == b'w' {
+                prot |= libc::PROT_WRITE;
+            }
+            retry_eintr(|| unsafe {
+                libc::mprotect(
+                    region_start as *mut libc::c_void,
+                    region_end - region_start,
+                    prot,
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The protection of each page can not be queried, so only read-only memory maps, whose pages
+    /// are all readable, can have execute permission removed without widening any protection.
+    #[cfg(not(target_os = "linux"))]
+    pub fn ensure_no_exec(&self, writable: bool) -> io::Result<()> {
+        if writable {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "removing execute permission from writable memory maps is only supported on Linux \
+                 and Windows",
+            ));
+        }
+        self.mprotect(libc::PROT_READ)
+    }
+
+    /// Replaces the shared file mapping in place with a private copy-on-write mapping of the same
+    /// range. Maps which are already private are left untouched.
+    pub fn make_private(&mut self) -> io::Result<()> {
+        let fd = match (self.kind(), &self.file) {
+            (MapKind::SharedFile, Some(file)) => file.as_raw_fd(),
+            (MapKind::PrivateFile, _) => return Ok(()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "anonymous memory maps can not be made private",
+                ))
+            }
+        };
+
+        let alignment = self.offset % page_size() as u64;
+        let flags = (self.flags & !(MAP_TYPE | MAP_SYNC)) | libc::MAP_PRIVATE;
+        unsafe {
+            let ptr = libc::mmap(
+                self.ptr.offset(-(alignment as isize)),
+                (self.len + alignment as usize) as libc::size_t,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags | libc::MAP_FIXED,
+                fd,
+                (self.offset - alignment) as libc::off_t,
+            );
+
+            if ptr == libc::MAP_FAILED {
+                Err(io::Error::last_os_error())
+            } else {
+                self.flags = flags;
+                Ok(())
+            }
+        }
+    }
+
     #[inline]
     pub fn ptr(&self) -> *const u8 {
         self.ptr as *const u8
@@ -247,16 +671,340 @@ impl MmapInner {
         self.len
     }
 
-    pub fn mlock(&self) -> io::Result<()> {
+    /// Returns how the memory of the map is backed.
+    pub fn kind(&self) -> MapKind {
+        if self.file.is_none() {
+            MapKind::Anonymous
+        } else if is_private(self.flags) {
+            MapKind::PrivateFile
+        } else {
+            MapKind::SharedFile
+        }
+    }
+
+    /// Returns whether the memory map was created with `MAP_SYNC`.
+    #[cfg(all(
+        target_os = "linux",
+        not(any(target_arch = "mips", target_arch = "mips64"))
+    ))]
+    #[inline]
+    pub fn is_sync(&self) -> bool {
+        self.flags & MAP_SYNC != 0
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        not(any(target_arch = "mips", target_arch = "mips64"))
+    )))]
+    #[inline]
+    pub fn is_sync(&self) -> bool {
+        false
+    }
+
+    /// Returns whether each page of the memory map is resident in memory.
+    pub fn mincore(&self) -> io::Result<Vec<bool>> {
+        Ok(self
+            .mincore_vec()?
+            .iter()
+            .map(|page| page & 1 == 1)
+            .collect())
+    }
+
+    /// Returns the fraction of the pages of the memory map which are resident in memory.
+    pub fn resident_fraction(&self) -> io::Result<f64> {
+        let residency = self.mincore_vec()?;
+        let resident = residency.iter().filter(|&page| page & 1 == 1).count();
+        Ok(resident as f64 / residency.len() as f64)
+    }
+
+    /// Returns the `mincore` vector of the memory map, whose low bits indicate residency.
+    fn mincore_vec(&self) -> io::Result<Vec<u8>> {
+        let alignment = self.ptr as usize % page_size();
+        let len = self.len + alignment;
+        let mut residency = vec![0u8; len.div_ceil(page_size())];
+        unsafe {
+            let ptr = self.ptr.offset(-(alignment as isize));
+            if libc::mincore(ptr, len, residency.as_mut_ptr() as _) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(residency)
+    }
+
+    /// Returns the protection of the pages covering `offset..offset + len`, as listed in
+    /// `/proc/self/maps`.
+    #[cfg(target_os = "linux")]
+    pub fn protection_of(&self, offset: usize, len: usize) -> io::Result<Protection> {
+        let start = self.ptr as usize + offset;
+        let end = start + len;
+        let mut covered = start - start % page_size();
+        let mut protection = None;
+        for (region_start, region_end, perms) in mapped_regions(covered, end)? {
+            // A gap between the regions is not mapped.
+            if region_start > covered {
+                break;
+            }
+            let region_protection = match &perms[..perms.len().min(3)] {
+                "r--" => Protection::Read,
+                "rw-" if self.kind() == MapKind::PrivateFile => Protection::CopyOnWrite,
+                "rw-" => Protection::ReadWrite,
+                "r-x" => Protection::ReadExec,
+                _ => {
+                    return Err(io::Error::other(format!(
+                        "memory map pages have a protection ({}) without a Protection variant",
+                        perms
+                    )))
+                }
+            };
+            if protection.is_some_and(|protection| protection != region_protection) {
+                return Err(io::Error::other(
+                    "memory map range spans pages with different protections",
+                ));
+            }
+            protection = Some(region_protection);
+            covered = region_end;
+        }
+        match protection {
+            Some(protection) if covered >= end => Ok(protection),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "memory map range is not listed in /proc/self/maps",
+            )),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn protection_of(&self, _offset: usize, _len: usize) -> io::Result<Protection> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "querying the protection of memory maps is only supported on Linux and Windows",
+        ))
+    }
+
+    /// Returns the offset into the file at which the memory map starts.
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the retained file handle if the memory map is file-backed.
+    #[inline]
+    pub fn file(&self) -> Option<&File> {
+        self.file.as_ref()
+    }
+
+    /// Unmaps the memory map, returning the retained file handle if it is file-backed.
+    pub fn into_file(mut self) -> Option<File> {
+        self.file.take()
+    }
+
+    /// Releases ownership of the memory map without unmapping it, returning its pointer and
+    /// length. The retained file handle is closed.
+    ///
+    /// Whole pages mapped before the page containing the pointer or past the end of the memory
+    /// map are unmapped, so that the memory map can later be unmapped with `munmap` from the page
+    /// containing the pointer for `len` bytes.
+    pub fn into_raw(mut self) -> (*mut u8, usize) {
+        let start = self.ptr as usize - self.head;
+        let page_start = self.ptr as usize / page_size() * page_size();
+        if page_start > start {
+            unsafe { libc::munmap(start as *mut libc::c_void, page_start - start) };
+        }
+        let end = self.ptr as usize + self.len;
+        let page_end = end.div_ceil(page_size()) * page_size();
+        if end + self.tail > page_end {
+            unsafe {
+                libc::munmap(
+                    page_end as *mut libc::c_void,
+                    (end + self.tail - page_end) as libc::size_t,
+                );
+            }
+        }
+        self.unmapped = true;
+        (self.ptr as *mut u8, self.len)
+    }
+
+    /// Unmaps the memory map. Once called, the memory map is never unmapped again, even if
+    /// unmapping failed.
+    pub fn close(&mut self) -> io::Result<()> {
+        if self.unmapped {
+            return Ok(());
+        }
+        self.unmapped = true;
+        let result = unsafe {
+            libc::munmap(
+                self.ptr.sub(self.head),
+                (self.head + self.len + self.tail) as libc::size_t,
+            )
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Misaligns the recorded start of the mapping, so that unmapping it fails.
+    #[cfg(test)]
+    pub fn inject_unmap_error(&mut self) {
+        self.head += 1;
+    }
+
+    /// Calls `madvise` with `advice` for the pages covering `offset..offset + len`.
+    pub fn advise_range(&self, advice: libc::c_int, offset: usize, len: usize) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
         unsafe {
-            if libc::mlock(self.ptr, self.len) == 0 {
+            let ptr = self.ptr.add(offset).offset(-(alignment as isize));
+            if libc::madvise(ptr, len + alignment, advice) == 0 {
                 Ok(())
             } else {
                 Err(io::Error::last_os_error())
             }
         }
     }
-    
+
+    #[cfg(target_os = "linux")]
+    pub fn collapse_huge(&self, offset: usize, len: usize) -> io::Result<()> {
+        // Defined here since not every libc target exposes it.
+        const MADV_COLLAPSE: libc::c_int = 25;
+
+        let alignment = (self.ptr as usize + offset) % page_size();
+        unsafe {
+            let ptr = self.ptr.add(offset).offset(-(alignment as isize));
+            if libc::madvise(ptr, len + alignment, MADV_COLLAPSE) == 0 {
+                return Ok(());
+            }
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // Kernels before 6.1 reject the unknown advice with EINVAL.
+            Some(libc::EINVAL) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "MADV_COLLAPSE is not supported by this kernel",
+            )),
+            _ => Err(err),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn collapse_huge(&self, _offset: usize, _len: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "MADV_COLLAPSE is only supported on Linux",
+        ))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn punch_hole(&mut self, offset: usize, len: usize) -> io::Result<()> {
+        unsafe {
+            if libc::madvise(self.ptr.add(offset), len, libc::MADV_REMOVE) == 0 {
+                return Ok(());
+            }
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EOPNOTSUPP) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the file system of the memory map does not support punching holes",
+            )),
+            _ => Err(err),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn punch_hole(&mut self, _offset: usize, _len: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "MADV_REMOVE is only supported on Linux",
+        ))
+    }
+
+    /// Resizes the memory map to `new_len` bytes, moving it if it can not be resized in place.
+    ///
+    /// Any bytes mapped after the memory map are unmapped.
+    #[cfg(target_os = "linux")]
+    pub fn resize(&mut self, new_len: usize) -> io::Result<()> {
+        // Shared anonymous memory is backed by a fixed-size object, so growing the mapping of it
+        // would only add pages which fault with SIGBUS.
+        if self.file.is_none() && !is_private(self.flags) {
+            return self.resize_by_copy(new_len);
+        }
+        // mremap may move a memory map created with MAP_32BIT out of the low 2 GiB.
+        #[cfg(target_arch = "x86_64")]
+        if self.flags & MAP_32BIT != 0 {
+            return self.resize_by_copy(new_len);
+        }
+
+        let old_ptr = unsafe { self.ptr.offset(-(self.head as isize)) };
+        let old_len = self.head + self.len + self.tail;
+        let ptr =
+            unsafe { libc::mremap(old_ptr, old_len, self.head + new_len, libc::MREMAP_MAYMOVE) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        self.ptr = unsafe { ptr.add(self.head) };
+        self.len = new_len;
+        self.tail = 0;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn resize(&mut self, new_len: usize) -> io::Result<()> {
+        self.resize_by_copy(new_len)
+    }
+
+    /// Resizes the memory map to `new_len` bytes by creating a new memory map and copying the
+    /// contents over.
+    fn resize_by_copy(&mut self, new_len: usize) -> io::Result<()> {
+        let new = MmapInner::new(
+            0,
+            new_len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            self.flags,
+            self.file.as_ref(),
+            self.offset,
+        )?;
+        // Shared file maps see the same pages through the new memory map; the contents of
+        // anonymous and private maps only exist in the old one.
+        if self.kind() != MapKind::SharedFile {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.ptr as *const u8,
+                    new.ptr as *mut u8,
+                    self.len.min(new_len),
+                )
+            };
+        }
+        *self = new;
+        Ok(())
+    }
+
+    /// Makes the first `guard_len` bytes of the memory map inaccessible, and excludes them from
+    /// it. They stay mapped until the memory map is dropped.
+    pub fn guard(&mut self, guard_len: usize) -> io::Result<()> {
+        debug_assert!(guard_len <= self.len && guard_len % page_size() == 0);
+        retry_eintr(|| unsafe { libc::mprotect(self.ptr, guard_len, libc::PROT_NONE) })?;
+        self.ptr = unsafe { self.ptr.add(guard_len) };
+        self.head += guard_len;
+        self.len -= guard_len;
+        Ok(())
+    }
+
+    /// Narrows the memory map to the `len` bytes starting `head` bytes into it. The bytes outside
+    /// that range stay mapped until the memory map is dropped.
+    pub fn narrow(&mut self, head: usize, len: usize) {
+        debug_assert!(head + len <= self.len);
+        self.ptr = unsafe { self.ptr.add(head) };
+        self.offset += head as u64;
+        self.tail += self.len - head - len;
+        self.head += head;
+        self.len = len;
+    }
+
+    pub fn mlock(&self) -> io::Result<()> {
+        retry_eintr(|| unsafe { libc::mlock(self.ptr, self.len) }).map_err(memlock_error)
+    }
+
     pub fn munlock(&self) -> io::Result<()> {
         unsafe {
             if libc::munlock(self.ptr, self.len) == 0 {
@@ -266,27 +1014,346 @@ impl MmapInner {
             }
         }
     }
+
+    #[cfg(target_os = "linux")]
+    pub fn mlock_on_fault(&self) -> io::Result<()> {
+        let err = match retry_eintr(|| unsafe {
+            libc::mlock2(self.ptr, self.len, libc::MLOCK_ONFAULT)
+        }) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        match err.raw_os_error() {
+            // Kernels before 4.4 lack mlock2, which glibc reports as EINVAL for non-zero flags.
+            Some(libc::EINVAL) | Some(libc::ENOSYS) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "MLOCK_ONFAULT is not supported by this kernel, use mlock instead",
+            )),
+            _ => Err(memlock_error(err)),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn mlock_on_fault(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "MLOCK_ONFAULT is only supported on Linux",
+        ))
+    }
+
+    pub fn mlock_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
+        let ptr = unsafe { self.ptr.add(offset).offset(-(alignment as isize)) };
+        retry_eintr(|| unsafe { libc::mlock(ptr, len + alignment) }).map_err(memlock_error)
+    }
+
+    pub fn munlock_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        let alignment = (self.ptr as usize + offset) % page_size();
+        unsafe {
+            let ptr = self.ptr.add(offset).offset(-(alignment as isize));
+            if libc::munlock(ptr, len + alignment) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
 }
 
 impl Drop for MmapInner {
     fn drop(&mut self) {
-        let alignment = self.ptr as usize % page_size();
-        unsafe {
-            assert!(
-                libc::munmap(
-                    self.ptr.offset(-(alignment as isize)),
-                    (self.len + alignment) as libc::size_t
-                ) == 0,
-                "unable to unmap mmap: {}",
-                io::Error::last_os_error()
-            );
-        }
+        // Errors can not be reported from `drop`; use `close` to observe them.
+        let _ = self.close();
     }
 }
 
 unsafe impl Sync for MmapInner {}
 unsafe impl Send for MmapInner {}
 
-fn page_size() -> usize {
-    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+/// Returns the file status flags `file` was opened with.
+fn status_flags(file: &File) -> io::Result<libc::c_int> {
+    let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) };
+    if flags == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(flags)
+    }
+}
+
+/// Returns whether `file` was opened with write access.
+pub fn file_writable(file: &File) -> io::Result<bool> {
+    Ok(status_flags(file)? & libc::O_ACCMODE == libc::O_RDWR)
+}
+
+/// Checks that `file` was opened with the access a memory map of it requires.
+///
+/// `mmap` reports missing access as `EACCES` or `EBADF`, which is confusing for descriptors
+/// opened with `O_PATH` or in the wrong mode, so these are rejected with a descriptive error.
+fn check_access(file: &File, write: bool) -> io::Result<()> {
+    let flags = status_flags(file)?;
+    let denied = |msg| Err(io::Error::new(io::ErrorKind::PermissionDenied, msg));
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if flags & libc::O_PATH != 0 {
+            return denied("file descriptor was opened with O_PATH and can not be memory mapped");
+        }
+    }
+    match flags & libc::O_ACCMODE {
+        libc::O_WRONLY => denied("file is not open for reading, which memory maps require"),
+        libc::O_RDONLY if write => {
+            denied("file is not open for writing, which this memory map requires")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Creates a file of `len` bytes which has no name in the file system.
+///
+/// This uses `memfd_create` where available, falling back to an immediately unlinked temporary
+/// file.
+pub fn anonymous_file(len: u64) -> io::Result<File> {
+    let file = match memfd()? {
+        Some(file) => file,
+        None => unlinked_temp_file()?,
+    };
+    file.set_len(len)?;
+    Ok(file)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn memfd() -> io::Result<Option<File>> {
+    let name = b"mapr\0".as_ptr() as *const libc::c_char;
+    let fd = unsafe { libc::memfd_create(name, libc::MFD_CLOEXEC) };
+    if fd == -1 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ENOSYS) {
+            // Kernels older than 3.17 lack memfd_create.
+            Ok(None)
+        } else {
+            Err(err)
+        }
+    } else {
+        Ok(Some(unsafe { File::from_raw_fd(fd) }))
+    }
+}
+
+/// Creates a `memfd_create` file of `len` bytes backed by huge pages of `huge_size` bytes.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn huge_memfd(name: &str, huge_size: usize, len: u64) -> io::Result<File> {
+    let size_flag = match huge_size {
+        0x20_0000 => libc::MFD_HUGE_2MB,
+        0x4000_0000 => libc::MFD_HUGE_1GB,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "huge page size must be 2 MiB or 1 GiB",
+            ))
+        }
+    };
+    let name = memfd_name(name)?;
+
+    let flags = libc::MFD_CLOEXEC | libc::MFD_HUGETLB | size_flag;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    if fd == -1 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            // Kernels before 4.14 lack MFD_HUGETLB, and kernels without hugetlbfs support or a
+            // mount for the page size reject it.
+            Some(libc::ENOSYS) | Some(libc::EINVAL) | Some(libc::ENOENT) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "huge page backed memfd is not supported by this kernel",
+            )),
+            _ => Err(err),
+        };
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+    file.set_len(len)?;
+    Ok(file)
+}
+
+/// Creates a sealable `memfd_create` file of `len` bytes named `name`.
+#[cfg(target_os = "linux")]
+pub fn named_memfd(name: &str, len: u64) -> io::Result<File> {
+    let name = memfd_name(name)?;
+    let flags = libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+    file.set_len(len)?;
+    Ok(file)
+}
+
+/// Opens the POSIX shared memory object `name` for reading and writing, creating it if `create`
+/// is set.
+#[cfg(not(target_os = "android"))]
+pub fn shm_open(name: &str, create: bool) -> io::Result<File> {
+    let name = shm_name(name)?;
+    let flags = if create {
+        libc::O_CREAT | libc::O_RDWR | libc::O_CLOEXEC
+    } else {
+        libc::O_RDWR | libc::O_CLOEXEC
+    };
+    let mode: libc::mode_t = 0o600;
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, libc::c_uint::from(mode)) };
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    let fd = unsafe { libc::shm_open(name.as_ptr(), flags, mode) };
+    if fd == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+}
+
+/// Removes the name of the POSIX shared memory object `name`.
+#[cfg(not(target_os = "android"))]
+pub fn shm_unlink(name: &str) -> io::Result<()> {
+    let name = shm_name(name)?;
+    if unsafe { libc::shm_unlink(name.as_ptr()) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Converts `name` to the portable form of a POSIX shared memory object name, which starts with
+/// the only slash.
+#[cfg(not(target_os = "android"))]
+fn shm_name(name: &str) -> io::Result<CString> {
+    let name = name.strip_prefix('/').unwrap_or(name);
+    if name.is_empty() || name.contains('/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "shared memory name must be non-empty and contain no slash after the leading one",
+        ));
+    }
+    CString::new(format!("/{}", name)).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "shared memory name must not contain a nul byte",
+        )
+    })
+}
+
+/// Adds `seals` to the seals of a memfd file.
+#[cfg(target_os = "linux")]
+pub fn add_seals(file: &File, seals: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the seals of a memfd file.
+#[cfg(target_os = "linux")]
+pub fn get_seals(file: &File) -> io::Result<libc::c_int> {
+    let seals = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) };
+    if seals == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(seals)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn memfd_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "memfd name must not contain a nul byte",
+        )
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn memfd() -> io::Result<Option<File>> {
+    Ok(None)
+}
+
+fn unlinked_temp_file() -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let name = format!(
+            "mapr-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = env::temp_dir().join(name);
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Makes the instructions written to `start..end` visible to instruction fetches, like
+/// `__builtin___clear_cache`.
+#[cfg(target_arch = "aarch64")]
+unsafe fn clear_icache(start: usize, end: usize) {
+    use std::arch::asm;
+
+    let ctr: u64;
+    asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+    let dcache_line = 4 << ((ctr >> 16) & 0xf);
+    let icache_line = 4 << (ctr & 0xf);
+
+    let mut addr = start & !(dcache_line - 1);
+    while addr < end {
+        asm!("dc cvau, {}", in(reg) addr, options(nostack));
+        addr += dcache_line;
+    }
+    asm!("dsb ish", options(nostack));
+
+    let mut addr = start & !(icache_line - 1);
+    while addr < end {
+        asm!("ic ivau, {}", in(reg) addr, options(nostack));
+        addr += icache_line;
+    }
+    asm!("dsb ish", "isb", options(nostack));
+}
+
+/// How many times a system call interrupted by a signal is retried, so that a steady stream of
+/// signals can not stall the caller forever.
+const EINTR_RETRIES: usize = 100;
+
+/// Calls `syscall` until it succeeds, or fails with an error other than `EINTR`, or has been
+/// interrupted `EINTR_RETRIES` times in a row, in which case the `EINTR` error is returned.
+fn retry_eintr<F: FnMut() -> libc::c_int>(mut syscall: F) -> io::Result<()> {
+    let mut retries = 0;
+    loop {
+        if syscall() == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINTR) || retries == EINTR_RETRIES {
+            return Err(err);
+        }
+        retries += 1;
+    }
+}
+
+pub fn page_size() -> usize {
+    // The page size never changes at runtime, so it is only queried once.
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+    match PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => {
+            let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+            PAGE_SIZE.store(page_size, Ordering::Relaxed);
+            page_size
+        }
+        page_size => page_size,
+    }
 }
@@ -1,26 +1,37 @@
+use std::ffi::OsStr;
 use std::fs::File;
 use std::os::raw::c_void;
+use std::os::windows::ffi::OsStrExt;
 use std::os::windows::io::{AsRawHandle, RawHandle};
 use std::{io, mem, ptr};
 
 use winapi::shared::basetsd::SIZE_T;
 use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{ERROR_ALREADY_EXISTS, ERROR_WORKING_SET_QUOTA};
 use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
 use winapi::um::memoryapi::{
-    CreateFileMappingW, FlushViewOfFile, MapViewOfFile, UnmapViewOfFile, VirtualProtect,
+    CreateFileMappingW, FlushViewOfFile, MapViewOfFile, OpenFileMappingW, PrefetchVirtualMemory,
+    UnmapViewOfFile, VirtualAlloc, VirtualLock, VirtualProtect, VirtualQuery, VirtualUnlock,
     FILE_MAP_ALL_ACCESS, FILE_MAP_COPY, FILE_MAP_EXECUTE, FILE_MAP_READ, FILE_MAP_WRITE,
+    WIN32_MEMORY_RANGE_ENTRY,
 };
+use winapi::um::processthreadsapi::{FlushInstructionCache, GetCurrentProcess};
 use winapi::um::sysinfoapi::GetSystemInfo;
 use winapi::um::winnt::{
-    PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_READONLY,
-    PAGE_READWRITE, PAGE_WRITECOPY,
+    MEMORY_BASIC_INFORMATION, MEM_COMMIT, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+    PAGE_EXECUTE_WRITECOPY, PAGE_NOACCESS, PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOPY,
+    SEC_RESERVE,
 };
 
+use crate::{MapKind, Protection};
+
 pub struct MmapInner {
     file: Option<File>,
     ptr: *mut c_void,
     len: usize,
+    offset: u64,
     copy: bool,
+    unmapped: bool,
 }
 
 impl MmapInner {
@@ -36,7 +47,7 @@ impl MmapInner {
         copy: bool,
     ) -> io::Result<MmapInner> {
         let alignment = offset % allocation_granularity() as u64;
-        let aligned_offset = offset - alignment as u64;
+        let aligned_offset = offset - alignment;
         let aligned_len = len + alignment as usize;
 
         unsafe {
@@ -48,7 +59,7 @@ impl MmapInner {
                 0,
                 ptr::null(),
             );
-            if handle == ptr::null_mut() {
+            if handle.is_null() {
                 return Err(io::Error::last_os_error());
             }
 
@@ -61,20 +72,54 @@ impl MmapInner {
             );
             CloseHandle(handle);
 
-            if ptr == ptr::null_mut() {
+            if ptr.is_null() {
                 Err(io::Error::last_os_error())
             } else {
                 Ok(MmapInner {
                     file: Some(file.try_clone()?),
                     ptr: ptr.offset(alignment as isize),
-                    len: len as usize,
-                    copy: copy,
+                    len,
+                    offset,
+                    copy,
+                    unmapped: false,
                 })
             }
         }
     }
 
-    pub fn map(len: usize, file: &File, offset: u64, _locked: bool, _private: bool) -> io::Result<MmapInner> {
+    /// Maps `len` bytes of `file` starting at `offset` with `protection`.
+    ///
+    /// The options which only correspond to unix `mmap` flags, such as huge pages, address hints
+    /// and populating, are accepted to mirror the unix signature, and ignored. Windows has no
+    /// equivalent of `MAP_SYNC`, so `sync` is ignored too and `is_sync()` reports `false`. Private
+    /// writable maps are copy-on-write views, like `MAP_PRIVATE` maps on unix.
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_file(
+        len: usize,
+        file: &File,
+        offset: u64,
+        protection: Protection,
+        locked: bool,
+        private: bool,
+        _huge_shift: Option<u8>,
+        _noreserve: bool,
+        _populate: bool,
+        _sync: bool,
+        _addr: usize,
+        _fixed_noreplace: bool,
+        _low_32bit: bool,
+    ) -> io::Result<MmapInner> {
+        match protection {
+            Protection::Read => MmapInner::map(len, file, offset, locked),
+            Protection::ReadExec => MmapInner::map_exec(len, file, offset, locked),
+            Protection::ReadWrite if !private => MmapInner::map_mut(len, file, offset, locked),
+            Protection::ReadWrite | Protection::CopyOnWrite => {
+                MmapInner::map_copy(len, file, offset, locked)
+            }
+        }
+    }
+
+    fn map(len: usize, file: &File, offset: u64, locked: bool) -> io::Result<MmapInner> {
         let write = protection_supported(file.as_raw_handle(), PAGE_READWRITE);
         let exec = protection_supported(file.as_raw_handle(), PAGE_EXECUTE_READ);
         let mut access = FILE_MAP_READ;
@@ -98,10 +143,13 @@ impl MmapInner {
         if write || exec {
             inner.make_read_only()?;
         }
+        if locked {
+            inner.mlock()?;
+        }
         Ok(inner)
     }
 
-    pub fn map_exec(len: usize, file: &File, offset: u64, _locked: bool, _private: bool) -> io::Result<MmapInner> {
+    fn map_exec(len: usize, file: &File, offset: u64, locked: bool) -> io::Result<MmapInner> {
         let write = protection_supported(file.as_raw_handle(), PAGE_READWRITE);
         let mut access = FILE_MAP_READ | FILE_MAP_EXECUTE;
         let protection = if write {
@@ -115,10 +163,13 @@ impl MmapInner {
         if write {
             inner.make_exec()?;
         }
+        if locked {
+            inner.mlock()?;
+        }
         Ok(inner)
     }
 
-    pub fn map_mut(len: usize, file: &File, offset: u64, _locked: bool, _private: bool) -> io::Result<MmapInner> {
+    fn map_mut(len: usize, file: &File, offset: u64, locked: bool) -> io::Result<MmapInner> {
         let exec = protection_supported(file.as_raw_handle(), PAGE_EXECUTE_READ);
         let mut access = FILE_MAP_READ | FILE_MAP_WRITE;
         let protection = if exec {
@@ -132,10 +183,15 @@ impl MmapInner {
         if exec {
             inner.make_mut()?;
         }
+        if locked {
+            inner.mlock()?;
+        }
         Ok(inner)
     }
 
-    pub fn map_copy(len: usize, file: &File, offset: u64, _locked: bool) -> io::Result<MmapInner> {
+    /// Maps a copy-on-write view with `FILE_MAP_COPY`: written pages become private to the view,
+    /// and are never written to the file or seen by other views.
+    fn map_copy(len: usize, file: &File, offset: u64, locked: bool) -> io::Result<MmapInner> {
         let exec = protection_supported(file.as_raw_handle(), PAGE_EXECUTE_READWRITE);
         let mut access = FILE_MAP_COPY;
         let protection = if exec {
@@ -149,10 +205,26 @@ impl MmapInner {
         if exec {
             inner.make_mut()?;
         }
+        if locked {
+            inner.mlock()?;
+        }
         Ok(inner)
     }
 
-    pub fn map_anon(len: usize, _stack: bool, _locked: bool, _private: bool) -> io::Result<MmapInner> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn map_anon(
+        len: usize,
+        _stack: bool,
+        _grows_down: bool,
+        locked: bool,
+        _private: bool,
+        _huge_shift: Option<u8>,
+        _noreserve: bool,
+        _populate: bool,
+        _addr: usize,
+        _fixed_noreplace: bool,
+        _low_32bit: bool,
+    ) -> io::Result<MmapInner> {
         unsafe {
             // Create a mapping and view with maximum access permissions, then use `VirtualProtect`
             // to set the actual `Protection`. This way, we can set more permissive protection later
@@ -167,42 +239,189 @@ impl MmapInner {
                 (len & 0xffffffff) as DWORD,
                 ptr::null(),
             );
-            if handle == ptr::null_mut() {
+            if handle.is_null() {
                 return Err(io::Error::last_os_error());
             }
             let access = FILE_MAP_ALL_ACCESS | FILE_MAP_EXECUTE;
             let ptr = MapViewOfFile(handle, access, 0, 0, len as SIZE_T);
             CloseHandle(handle);
 
-            if ptr == ptr::null_mut() {
+            if ptr.is_null() {
                 return Err(io::Error::last_os_error());
             }
 
+            let inner = MmapInner {
+                file: None,
+                ptr,
+                len,
+                offset: 0,
+                copy: false,
+                unmapped: false,
+            };
             let mut old = 0;
             let result = VirtualProtect(ptr, len as SIZE_T, PAGE_READWRITE, &mut old);
-            if result != 0 {
+            if result == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if locked {
+                inner.mlock()?;
+            }
+            Ok(inner)
+        }
+    }
+
+    /// Maps `len` bytes of the named pagefile-backed section `name`, creating it if `create` is
+    /// set and it does not exist.
+    pub fn map_named(name: &str, len: usize, create: bool, locked: bool) -> io::Result<MmapInner> {
+        let name = shared_name(name)?;
+        unsafe {
+            let handle = if create {
+                CreateFileMappingW(
+                    INVALID_HANDLE_VALUE,
+                    ptr::null_mut(),
+                    PAGE_READWRITE,
+                    (len >> 16 >> 16) as DWORD,
+                    (len & 0xffffffff) as DWORD,
+                    name.as_ptr(),
+                )
+            } else {
+                OpenFileMappingW(FILE_MAP_READ | FILE_MAP_WRITE, 0, name.as_ptr())
+            };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            // An existing section is opened rather than created, and keeps its size.
+            let existing = !create
+                || io::Error::last_os_error().raw_os_error() == Some(ERROR_ALREADY_EXISTS as i32);
+
+            // Map the whole section, so that its size can be checked against `len`.
+            let ptr = MapViewOfFile(handle, FILE_MAP_READ | FILE_MAP_WRITE, 0, 0, 0);
+            CloseHandle(handle);
+            if ptr.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+
+            let inner = MmapInner {
+                file: None,
+                ptr,
+                len,
+                offset: 0,
+                copy: false,
+                unmapped: false,
+            };
+            if existing {
+                let mut info: MEMORY_BASIC_INFORMATION = mem::zeroed();
+                if VirtualQuery(ptr, &mut info, mem::size_of_val(&info) as SIZE_T) == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if info.RegionSize < len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "shared memory object is shorter than the memory map",
+                    ));
+                }
+            }
+            if locked {
+                inner.mlock()?;
+            }
+            Ok(inner)
+        }
+    }
+
+    /// Wraps the existing view of `len` bytes at `ptr`, which is unmapped on drop.
+    pub unsafe fn from_raw(ptr: *mut u8, len: usize) -> MmapInner {
+        MmapInner {
+            file: None,
+            ptr: ptr as *mut c_void,
+            len,
+            offset: 0,
+            copy: false,
+            unmapped: false,
+        }
+    }
+
+    /// Reserves `len` bytes of address space without backing them with memory.
+    ///
+    /// The pages are inaccessible until they are committed with `commit`.
+    pub fn reserve(len: usize) -> io::Result<MmapInner> {
+        unsafe {
+            let handle = CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                ptr::null_mut(),
+                PAGE_READWRITE | SEC_RESERVE,
+                (len >> 16 >> 16) as DWORD,
+                (len & 0xffffffff) as DWORD,
+                ptr::null(),
+            );
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let ptr = MapViewOfFile(handle, FILE_MAP_READ | FILE_MAP_WRITE, 0, 0, len as SIZE_T);
+            CloseHandle(handle);
+
+            if ptr.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
                 Ok(MmapInner {
                     file: None,
-                    ptr: ptr,
-                    len: len as usize,
+                    ptr,
+                    len,
+                    offset: 0,
                     copy: false,
+                    unmapped: false,
                 })
-            } else {
-                Err(io::Error::last_os_error())
             }
         }
     }
 
+    /// Makes the reserved pages covering `offset..offset + len` readable and writable.
+    pub fn commit(&mut self, offset: usize, len: usize) -> io::Result<()> {
+        let ptr = unsafe {
+            VirtualAlloc(
+                self.ptr.add(offset),
+                len as SIZE_T,
+                MEM_COMMIT,
+                PAGE_READWRITE,
+            )
+        };
+        if ptr.is_null() {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Releases the memory backing every page of a reserved map, making the pages inaccessible
+    /// until they are committed again.
+    ///
+    /// Pages of a mapped view can not be decommitted, so the view is replaced, which changes the
+    /// base pointer.
+    pub fn decommit(&mut self) -> io::Result<()> {
+        *self = MmapInner::reserve(self.len)?;
+        Ok(())
+    }
+
+    pub fn guard(&mut self, _guard_len: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "guard pages are not supported on Windows",
+        ))
+    }
+
+    /// `FlushViewOfFile` only hands the dirty pages to the file system, so the retained file
+    /// handle is then flushed with `FlushFileBuffers` (which `File::sync_data` calls) to write
+    /// them to stable storage. Copy-on-write views never write back to the file, which may not even
+    /// be open for writing as `FlushFileBuffers` requires, so their file is not flushed.
     pub fn flush(&self, offset: usize, len: usize) -> io::Result<()> {
         self.flush_async(offset, len)?;
-        if let Some(ref file) = self.file {
-            file.sync_data()?;
+        match self.file {
+            Some(ref file) if !self.copy => file.sync_data(),
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     pub fn flush_async(&self, offset: usize, len: usize) -> io::Result<()> {
-        let result = unsafe { FlushViewOfFile(self.ptr.offset(offset as isize), len as SIZE_T) };
+        let result = unsafe { FlushViewOfFile(self.ptr.add(offset), len as SIZE_T) };
         if result != 0 {
             Ok(())
         } else {
@@ -210,7 +429,13 @@ impl MmapInner {
         }
     }
 
-    fn virtual_protect(&mut self, protect: DWORD) -> io::Result<()> {
+    /// Views of a local file are backed by the same physical pages, so they never hold stale
+    /// contents to invalidate.
+    pub fn invalidate(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn virtual_protect(&self, protect: DWORD) -> io::Result<()> {
         unsafe {
             let alignment = self.ptr as usize % allocation_granularity();
             let ptr = self.ptr.offset(-(alignment as isize));
@@ -227,15 +452,91 @@ impl MmapInner {
         }
     }
 
+    pub fn protect_range(
+        &mut self,
+        offset: usize,
+        len: usize,
+        protection: Protection,
+    ) -> io::Result<()> {
+        let protect = match (protection, self.copy) {
+            (Protection::Read, _) => PAGE_READONLY,
+            (Protection::ReadWrite, true) | (Protection::CopyOnWrite, true) => PAGE_WRITECOPY,
+            (Protection::ReadWrite, false) | (Protection::CopyOnWrite, false) => PAGE_READWRITE,
+            (Protection::ReadExec, true) => PAGE_EXECUTE_WRITECOPY,
+            (Protection::ReadExec, false) => PAGE_EXECUTE_READ,
+        };
+        unsafe {
+            let alignment = (self.ptr as usize + offset) % page_size();
+            let ptr = self.ptr.add(offset).sub(alignment);
+            let mut old = 0;
+            if VirtualProtect(ptr, (len + alignment) as SIZE_T, protect, &mut old) == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if protection == Protection::ReadExec
+                && FlushInstructionCache(GetCurrentProcess(), ptr, (len + alignment) as SIZE_T) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the protection of the pages covering `offset..offset + len`, as reported by
+    /// `VirtualQuery`.
+    pub fn protection_of(&self, offset: usize, len: usize) -> io::Result<Protection> {
+        let start = self.ptr as usize + offset;
+        let end = start + len;
+        let mut addr = start - start % page_size();
+        let mut protection = None;
+        while addr < end {
+            let mut info: MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+            let size = mem::size_of_val(&info) as SIZE_T;
+            if unsafe { VirtualQuery(addr as *const c_void, &mut info, size) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Written pages of copy-on-write views become private read-write pages.
+            let region_protection = match info.Protect {
+                PAGE_READONLY => Protection::Read,
+                PAGE_READWRITE | PAGE_WRITECOPY if self.copy => Protection::CopyOnWrite,
+                PAGE_READWRITE => Protection::ReadWrite,
+                PAGE_EXECUTE_READ => Protection::ReadExec,
+                protect => {
+                    return Err(io::Error::other(format!(
+                        "memory map pages have a protection ({:#x}) without a Protection variant",
+                        protect
+                    )))
+                }
+            };
+            if protection.is_some_and(|protection| protection != region_protection) {
+                return Err(io::Error::other(
+                    "memory map range spans pages with different protections",
+                ));
+            }
+            protection = Some(region_protection);
+            addr = info.BaseAddress as usize + info.RegionSize;
+        }
+        protection
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "memory map range is empty"))
+    }
+
     pub fn make_read_only(&mut self) -> io::Result<()> {
         self.virtual_protect(PAGE_READONLY)
     }
 
     pub fn make_exec(&mut self) -> io::Result<()> {
         if self.copy {
-            self.virtual_protect(PAGE_EXECUTE_WRITECOPY)
+            self.virtual_protect(PAGE_EXECUTE_WRITECOPY)?;
+        } else {
+            self.virtual_protect(PAGE_EXECUTE_READ)?;
+        }
+        // Code written through the data cache must be made visible to instruction fetches, which
+        // matters on architectures without a coherent instruction cache, such as ARM64.
+        let result =
+            unsafe { FlushInstructionCache(GetCurrentProcess(), self.ptr, self.len as SIZE_T) };
+        if result != 0 {
+            Ok(())
         } else {
-            self.virtual_protect(PAGE_EXECUTE_READ)
+            Err(io::Error::last_os_error())
         }
     }
 
@@ -247,6 +548,155 @@ impl MmapInner {
         }
     }
 
+    /// Removes execute access from each region reported by `VirtualQuery`, keeping the rest of
+    /// its protection, e.g. ranges made read-only with `protect_range`.
+    pub fn ensure_no_exec(&self, _writable: bool) -> io::Result<()> {
+        let start = self.ptr as usize;
+        let end = start + self.len;
+        let mut addr = start - start % page_size();
+        while addr < end {
+            let mut info: MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+            let size = mem::size_of_val(&info) as SIZE_T;
+            if unsafe { VirtualQuery(addr as *const c_void, &mut info, size) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let region_end = (info.BaseAddress as usize + info.RegionSize).min(end);
+            let protect = match info.Protect {
+                PAGE_EXECUTE => Some(PAGE_NOACCESS),
+                PAGE_EXECUTE_READ => Some(PAGE_READONLY),
+                PAGE_EXECUTE_READWRITE => Some(PAGE_READWRITE),
+                PAGE_EXECUTE_WRITECOPY => Some(PAGE_WRITECOPY),
+                _ => None,
+            };
+            if let Some(protect) = protect {
+                let mut old = 0;
+                let len = (region_end - addr) as SIZE_T;
+                if unsafe { VirtualProtect(addr as *mut c_void, len, protect, &mut old) } == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            addr = region_end;
+        }
+        Ok(())
+    }
+
+    /// Replaces the file mapping with a copy-on-write view of the same range. Views can not be
+    /// remapped in place, so the base pointer changes. Maps which are already copy-on-write are
+    /// left untouched.
+    pub fn make_private(&mut self) -> io::Result<()> {
+        let file = match (self.kind(), &self.file) {
+            (MapKind::SharedFile, Some(file)) => file.try_clone()?,
+            (MapKind::PrivateFile, _) => return Ok(()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "anonymous memory maps can not be made private",
+                ))
+            }
+        };
+        *self = MmapInner::map_copy(self.len, &file, self.offset, false)?;
+        Ok(())
+    }
+
+    pub fn collapse_huge(&self, _offset: usize, _len: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "collapsing into huge pages is not supported on Windows",
+        ))
+    }
+
+    /// Resizes the memory map to `new_len` bytes by creating a new view and copying the contents
+    /// over, since views can not be resized in place.
+    pub fn resize(&mut self, new_len: usize) -> io::Result<()> {
+        let new = match (self.kind(), &self.file) {
+            (MapKind::SharedFile, Some(file)) => {
+                MmapInner::map_mut(new_len, file, self.offset, false)?
+            }
+            (MapKind::PrivateFile, Some(file)) => {
+                MmapInner::map_copy(new_len, file, self.offset, false)?
+            }
+            _ => MmapInner::map_anon(
+                new_len, false, false, false, false, None, false, false, 0, false, false,
+            )?,
+        };
+        // Shared file maps see the same pages through the new view; the contents of anonymous
+        // and copy-on-write maps only exist in the old one.
+        if self.kind() != MapKind::SharedFile {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.ptr as *const u8,
+                    new.ptr as *mut u8,
+                    self.len.min(new_len),
+                )
+            };
+        }
+        *self = new;
+        Ok(())
+    }
+
+    pub fn punch_hole(&mut self, _offset: usize, _len: usize) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "punching holes in memory maps is not supported on Windows",
+        ))
+    }
+
+    pub fn mlock(&self) -> io::Result<()> {
+        self.mlock_range(0, self.len)
+    }
+
+    pub fn mlock_on_fault(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "locking pages as they are faulted in is not supported on Windows",
+        ))
+    }
+
+    pub fn mlock_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        unsafe {
+            if VirtualLock(self.ptr.add(offset), len as SIZE_T) != 0 {
+                return Ok(());
+            }
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(code) if code == ERROR_WORKING_SET_QUOTA as i32 => Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "locking the memory map exceeds the working set quota of the process, see \
+                 SetProcessWorkingSetSize",
+            )),
+            _ => Err(err),
+        }
+    }
+
+    pub fn munlock(&self) -> io::Result<()> {
+        self.munlock_range(0, self.len)
+    }
+
+    pub fn munlock_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        unsafe {
+            if VirtualUnlock(self.ptr.add(offset), len as SIZE_T) != 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+    }
+
+    /// Asks the system to bring `offset..offset + len` of the memory map into memory.
+    pub fn prefetch(&self, offset: usize, len: usize) -> io::Result<()> {
+        let mut range = WIN32_MEMORY_RANGE_ENTRY {
+            VirtualAddress: unsafe { self.ptr.add(offset) },
+            NumberOfBytes: len as SIZE_T,
+        };
+        let result = unsafe { PrefetchVirtualMemory(GetCurrentProcess(), 1, &mut range, 0) };
+        if result != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
     #[inline]
     pub fn ptr(&self) -> *const u8 {
         self.ptr as *const u8
@@ -261,29 +711,105 @@ impl MmapInner {
     pub fn len(&self) -> usize {
         self.len
     }
-}
 
-impl Drop for MmapInner {
-    fn drop(&mut self) {
+    /// Returns how the memory of the map is backed.
+    pub fn kind(&self) -> MapKind {
+        if self.file.is_none() {
+            MapKind::Anonymous
+        } else if self.copy {
+            MapKind::PrivateFile
+        } else {
+            MapKind::SharedFile
+        }
+    }
+
+    /// Returns whether the memory map was created with `MAP_SYNC`, which Windows lacks.
+    #[inline]
+    pub fn is_sync(&self) -> bool {
+        false
+    }
+
+    pub fn mincore(&self) -> io::Result<Vec<bool>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "querying the residency of memory maps is not supported on Windows",
+        ))
+    }
+
+    pub fn resident_fraction(&self) -> io::Result<f64> {
+        self.mincore().map(|_| 0.0)
+    }
+
+    /// Returns the offset into the file at which the memory map starts.
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the retained file handle if the memory map is file-backed.
+    #[inline]
+    pub fn file(&self) -> Option<&File> {
+        self.file.as_ref()
+    }
+
+    /// Unmaps the memory map, returning the retained file handle if it is file-backed.
+    pub fn into_file(mut self) -> Option<File> {
+        self.file.take()
+    }
+
+    /// Releases ownership of the memory map without unmapping it, returning its pointer and
+    /// length. The retained file handle is closed.
+    pub fn into_raw(mut self) -> (*mut u8, usize) {
+        self.unmapped = true;
+        (self.ptr as *mut u8, self.len)
+    }
+
+    /// Unmaps the memory map. Once called, the memory map is never unmapped again, even if
+    /// unmapping failed.
+    pub fn close(&mut self) -> io::Result<()> {
+        if self.unmapped {
+            return Ok(());
+        }
+        self.unmapped = true;
         let alignment = self.ptr as usize % allocation_granularity();
         unsafe {
             let ptr = self.ptr.offset(-(alignment as isize));
-            assert!(
-                UnmapViewOfFile(ptr) != 0,
-                "unable to unmap mmap: {}",
-                io::Error::last_os_error()
-            );
+            if UnmapViewOfFile(ptr) != 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
         }
     }
 }
 
+impl Drop for MmapInner {
+    fn drop(&mut self) {
+        // Errors can not be reported from `drop`; use `close` to observe them.
+        let _ = self.close();
+    }
+}
+
 unsafe impl Sync for MmapInner {}
 unsafe impl Send for MmapInner {}
 
+/// Validates a shared memory name, accepting the leading slash of POSIX names, and converts it to
+/// a nul terminated wide string.
+pub fn shared_name(name: &str) -> io::Result<Vec<u16>> {
+    let name = name.strip_prefix('/').unwrap_or(name);
+    if name.is_empty() || name.contains('/') || name.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "shared memory name must be non-empty and contain no slash after the leading one",
+        ));
+    }
+    Ok(OsStr::new(name).encode_wide().chain(Some(0)).collect())
+}
+
 fn protection_supported(handle: RawHandle, protection: DWORD) -> bool {
     unsafe {
         let handle = CreateFileMappingW(handle, ptr::null_mut(), protection, 0, 0, ptr::null());
-        if handle == ptr::null_mut() {
+        if handle.is_null() {
             return false;
         }
         CloseHandle(handle);
@@ -291,10 +817,23 @@ fn protection_supported(handle: RawHandle, protection: DWORD) -> bool {
     }
 }
 
+/// Returns whether `file` was opened with write access.
+pub fn file_writable(file: &File) -> io::Result<bool> {
+    Ok(protection_supported(file.as_raw_handle(), PAGE_READWRITE))
+}
+
+pub fn page_size() -> usize {
+    unsafe {
+        let mut info = mem::zeroed();
+        GetSystemInfo(&mut info);
+        info.dwPageSize as usize
+    }
+}
+
 fn allocation_granularity() -> usize {
     unsafe {
         let mut info = mem::zeroed();
         GetSystemInfo(&mut info);
-        return info.dwAllocationGranularity as usize;
+        info.dwAllocationGranularity as usize
     }
 }